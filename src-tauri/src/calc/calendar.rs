@@ -0,0 +1,160 @@
+// 交易日历：沪深交易所周六、周日及节假日休市
+// 内置的节假日休市安排见 data/trading_calendar.json，新的一年公布后可导入
+use crate::error::AppError;
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::LazyLock;
+
+// 内置的休市日，按年份分组，只列出周一至周五的休市日
+const BUNDLED_CALENDAR: &str = include_str!("../../data/trading_calendar.json");
+
+// 各年的休市日
+pub type HolidayYears = BTreeMap<i32, BTreeSet<NaiveDate>>;
+
+// 内置文件随程序发布，解析失败说明打包的数据有误，不能当作没有节假日
+static BUNDLED: LazyLock<HolidayYears> = LazyLock::new(|| {
+    parse_holidays(BUNDLED_CALENDAR).expect("data/trading_calendar.json 格式错误")
+});
+
+/// 解析休市安排，格式为 {"2024": ["2024-01-01", ...], ...}
+pub fn parse_holidays(content: &str) -> Result<HolidayYears, AppError> {
+    let years: HolidayYears = serde_json::from_str(content)?;
+    for (year, holidays) in &years {
+        if let Some(date) = holidays.iter().find(|date| date.year() != *year) {
+            return Err(AppError::HolidayYearMismatch {
+                year: *year,
+                date: date.to_string(),
+            });
+        }
+    }
+    Ok(years)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TradingCalendar {
+    holidays: HolidayYears,
+}
+
+impl TradingCalendar {
+    /// 内置的交易日历
+    pub fn bundled() -> Self {
+        TradingCalendar {
+            holidays: BUNDLED.clone(),
+        }
+    }
+
+    /// 内置的交易日历，imported中的年份替换内置的同一年
+    pub fn with_imported(imported: HolidayYears) -> Self {
+        let mut calendar = Self::bundled();
+        calendar.holidays.extend(imported);
+        calendar
+    }
+
+    /// 某一年的休市日(不含周末)
+    pub fn holidays(&self, year: i32) -> Vec<NaiveDate> {
+        self.holidays
+            .get(&year)
+            .map(|holidays| holidays.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// 是否交易日
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+            && !self
+                .holidays
+                .get(&date.year())
+                .is_some_and(|holidays| holidays.contains(&date))
+    }
+
+    /// date之后的第一个交易日
+    pub fn next_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut next = date;
+        while let Some(day) = next.succ_opt() {
+            next = day;
+            if self.is_trading_day(next) {
+                break;
+            }
+        }
+        next
+    }
+
+    /// date之前的最后一个交易日
+    pub fn previous_trading_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut previous = date;
+        while let Some(day) = previous.pred_opt() {
+            previous = day;
+            if self.is_trading_day(previous) {
+                break;
+            }
+        }
+        previous
+    }
+
+    /// from(不含)至to(含)之间的交易日数，to早于from时为负数
+    pub fn trading_days_between(&self, from: NaiveDate, to: NaiveDate) -> i64 {
+        let (start, end, sign) = if from <= to {
+            (from, to, 1)
+        } else {
+            (to, from, -1)
+        };
+        let days = start
+            .iter_days()
+            .skip(1)
+            .take_while(|date| *date <= end)
+            .filter(|date| self.is_trading_day(*date))
+            .count() as i64;
+        days * sign
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn bundled_calendar_parses() {
+        let years = parse_holidays(BUNDLED_CALENDAR).unwrap();
+        assert_eq!(
+            years.keys().copied().collect::<Vec<_>>(),
+            vec![2023, 2024, 2025, 2026]
+        );
+        // 只列出周一至周五的休市日
+        assert!(years
+            .values()
+            .flatten()
+            .all(|date| !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)));
+
+        let calendar = TradingCalendar::bundled();
+        assert!(!calendar.is_trading_day(date("2024-10-01")));
+        assert_eq!(
+            calendar.next_trading_day(date("2024-09-30")),
+            date("2024-10-08")
+        );
+        assert_eq!(
+            calendar.previous_trading_day(date("2024-10-08")),
+            date("2024-09-30")
+        );
+        // 周末
+        assert!(!calendar.is_trading_day(date("2024-10-12")));
+        assert_eq!(
+            calendar.previous_trading_day(date("2024-10-14")),
+            date("2024-10-11")
+        );
+    }
+
+    #[test]
+    fn holiday_outside_its_year() {
+        assert_eq!(
+            parse_holidays(r#"{"2024": ["2024-10-01", "2025-01-01"]}"#),
+            Err(AppError::HolidayYearMismatch {
+                year: 2024,
+                date: "2025-01-01".to_string(),
+            })
+        );
+    }
+}
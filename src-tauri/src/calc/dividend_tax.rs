@@ -0,0 +1,92 @@
+// 差别化红利税：现金分红时不扣税，卖出时按所卖批次的持股期限补扣
+// 持股1个月以内(含)税率20%，1个月至1年(含)10%，超过1年免征
+use crate::calc::lot::{Lot, LotSale};
+use crate::calc::money::round_fee;
+use chrono::{Months, NaiveDate};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// 持股期限对应的红利税率，持股期限为买入日至卖出日
+pub fn dividend_tax_rate(buy_date: NaiveDate, sell_date: NaiveDate) -> Decimal {
+    if buy_date.checked_add_months(Months::new(1)) >= Some(sell_date) {
+        dec!(0.2)
+    } else if buy_date.checked_add_months(Months::new(12)) >= Some(sell_date) {
+        dec!(0.1)
+    } else {
+        Decimal::ZERO
+    }
+}
+
+/// 本次卖出应补扣的红利税，按各批次持有期间每股所得的现金分红计算
+/// 没有卖出日期或买入日期时不计算
+pub fn dividend_tax(lots: &[Lot], sales: &[LotSale], sell_date: Option<NaiveDate>) -> Decimal {
+    let Some(sell_date) = sell_date else {
+        return Decimal::ZERO;
+    };
+    round_fee(
+        sales
+            .iter()
+            .filter_map(|sale| {
+                let lot = lots
+                    .iter()
+                    .find(|lot| lot.stock_lot_id == sale.stock_lot_id)?;
+                let rate = dividend_tax_rate(lot.buy_date?, sell_date);
+                Some(lot.dividend_per_share * sale.sell_position * rate)
+            })
+            .sum(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn rate_by_holding_period() {
+        let buy_date = date("2024-01-31");
+        // 满1个月(含)20%，2月没有31日，按月末计算
+        assert_eq!(dividend_tax_rate(buy_date, date("2024-01-31")), dec!(0.2));
+        assert_eq!(dividend_tax_rate(buy_date, date("2024-02-29")), dec!(0.2));
+        assert_eq!(dividend_tax_rate(buy_date, date("2024-03-01")), dec!(0.1));
+        // 满1年(含)10%，超过1年免征
+        assert_eq!(dividend_tax_rate(buy_date, date("2025-01-31")), dec!(0.1));
+        assert_eq!(
+            dividend_tax_rate(buy_date, date("2025-02-01")),
+            Decimal::ZERO
+        );
+    }
+
+    #[test]
+    fn tax_by_sold_lots() {
+        let lot = |stock_lot_id: i32, buy_date: &str| Lot {
+            stock_lot_id,
+            buy_price: dec!(10),
+            remaining_position: dec!(1000),
+            buy_date: Some(date(buy_date)),
+            dividend_per_share: dec!(0.3),
+        };
+        let sale = |stock_lot_id: i32, sell_position: Decimal| LotSale {
+            stock_lot_id,
+            sell_position,
+            buy_price: dec!(10),
+            sell_price: dec!(11),
+            realized_profit: sell_position,
+        };
+        let lots = [
+            lot(1, "2023-01-04"),
+            lot(2, "2024-01-04"),
+            lot(3, "2024-03-01"),
+        ];
+        let sales = [sale(1, dec!(1000)), sale(2, dec!(500)), sale(3, dec!(100))];
+        // 第1批超过1年免征，第2批500*0.3*10%，第3批100*0.3*20%
+        assert_eq!(
+            dividend_tax(&lots, &sales, Some(date("2024-03-15"))),
+            dec!(21)
+        );
+        assert_eq!(dividend_tax(&lots, &sales, None), Decimal::ZERO);
+    }
+}
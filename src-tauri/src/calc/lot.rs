@@ -0,0 +1,370 @@
+// 批次(税批)计算：每次买入形成一个批次，卖出时按规则消耗批次并计算已实现盈亏
+use crate::calc::money::round_fee;
+use crate::constant::lot_method::LotMethod;
+use crate::error::AppError;
+use chrono::NaiveDate;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+// 持有中的批次
+#[derive(Debug, Clone, Copy)]
+pub struct Lot {
+    pub stock_lot_id: i32,
+    pub buy_price: Decimal,          // 买入价格
+    pub remaining_position: Decimal, // 剩余数量
+    pub buy_date: Option<NaiveDate>, // 买入日期，计算红利税的持股期限
+    pub dividend_per_share: Decimal, // 持有期间每股所得的现金分红(税前)
+}
+
+// 指定批次卖出时的选择
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct LotSelection {
+    pub stock_lot_id: i32,
+    pub position: Decimal, // 从该批次卖出的数量
+}
+
+// 一次卖出对某个批次的消耗
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LotSale {
+    pub stock_lot_id: i32,
+    pub sell_position: Decimal,   // 卖出数量
+    pub buy_price: Decimal,       // 买入价格
+    pub sell_price: Decimal,      // 卖出价格
+    pub realized_profit: Decimal, // 已实现盈亏(未扣费用)
+}
+
+// 一次送转股给某个批次新增的股份
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LotBonus {
+    pub source_lot_id: i32,      // 原批次
+    pub bonus_position: Decimal, // 送转股数
+}
+
+/// 按匹配方式消耗批次，lots需按买入先后排序
+pub fn consume_lots(
+    lots: &[Lot],
+    sell_position: Decimal,
+    sell_price: Decimal,
+    method: LotMethod,
+    selections: &[LotSelection],
+) -> Result<Vec<LotSale>, AppError> {
+    let plan: Vec<(Lot, Decimal)> = match method {
+        LotMethod::Fifo => take_in_order(lots.iter(), sell_position),
+        LotMethod::Lifo => take_in_order(lots.iter().rev(), sell_position),
+        LotMethod::Specific => {
+            let mut plan = Vec::new();
+            for selection in selections.iter().filter(|s| s.position > Decimal::ZERO) {
+                let lot = lots
+                    .iter()
+                    .find(|lot| lot.stock_lot_id == selection.stock_lot_id)
+                    .ok_or(AppError::LotUnavailable {
+                        stock_lot_id: selection.stock_lot_id,
+                    })?;
+                let taken: Decimal = plan
+                    .iter()
+                    .filter(|(l, _): &&(Lot, Decimal)| l.stock_lot_id == lot.stock_lot_id)
+                    .map(|(_, position)| *position)
+                    .sum();
+                if taken + selection.position > lot.remaining_position {
+                    return Err(AppError::OversellLot {
+                        stock_lot_id: lot.stock_lot_id,
+                        position: selection.position,
+                        remaining_position: lot.remaining_position - taken,
+                    });
+                }
+                plan.push((*lot, selection.position));
+            }
+            plan
+        }
+    };
+
+    let total: Decimal = plan.iter().map(|(_, position)| *position).sum();
+    if total != sell_position {
+        return Err(AppError::LotMismatch {
+            selected_position: total,
+            position: sell_position,
+        });
+    }
+
+    Ok(plan
+        .into_iter()
+        .map(|(lot, position)| LotSale {
+            stock_lot_id: lot.stock_lot_id,
+            sell_position: position,
+            buy_price: lot.buy_price,
+            sell_price,
+            realized_profit: round_fee((sell_price - lot.buy_price) * position),
+        })
+        .collect())
+}
+
+/// 扣减卖出后剩余的批次
+pub fn apply_sales(lots: &[Lot], sales: &[LotSale]) -> Vec<Lot> {
+    lots.iter()
+        .map(|lot| {
+            let sold: Decimal = sales
+                .iter()
+                .filter(|sale| sale.stock_lot_id == lot.stock_lot_id)
+                .map(|sale| sale.sell_position)
+                .sum();
+            Lot {
+                remaining_position: lot.remaining_position - sold,
+                ..*lot
+            }
+        })
+        .filter(|lot| lot.remaining_position > Decimal::ZERO)
+        .collect()
+}
+
+/// 拆合股后的批次：每split_from股变为split_to股，数量按比例折算，买入价格和每股分红反向折算
+pub fn split_lots(lots: &[Lot], split_from: Decimal, split_to: Decimal) -> Vec<Lot> {
+    lots.iter()
+        .map(|lot| Lot {
+            buy_price: split_price(lot.buy_price, split_from, split_to),
+            remaining_position: split_position(lot.remaining_position, split_from, split_to),
+            dividend_per_share: split_price(lot.dividend_per_share, split_from, split_to),
+            ..*lot
+        })
+        .collect()
+}
+
+// 拆合股后的数量，先乘后除，整除时没有误差
+pub fn split_position(position: Decimal, split_from: Decimal, split_to: Decimal) -> Decimal {
+    position * split_to / split_from
+}
+
+// 拆合股后的每股价格，去掉除法产生的多余的0
+pub fn split_price(price: Decimal, split_from: Decimal, split_to: Decimal) -> Decimal {
+    (price * split_from / split_to).normalize()
+}
+
+/// 送转股按全部持仓计算，不足1股的部分舍去，再按持有数量分配到各批次：
+/// 各批次先按比例取整，余下的股数依次给取整时舍去较多的批次，只返回分到股份的批次
+pub fn allocate_bonus(lots: &[Lot], bonus_per_share: Decimal) -> Vec<LotBonus> {
+    let total: Decimal = lots.iter().map(|lot| lot.remaining_position).sum();
+    let mut bonuses: Vec<(LotBonus, Decimal)> = lots
+        .iter()
+        .map(|lot| {
+            let exact = lot.remaining_position * bonus_per_share;
+            let bonus = LotBonus {
+                source_lot_id: lot.stock_lot_id,
+                bonus_position: exact.floor(),
+            };
+            (bonus, exact.fract())
+        })
+        .collect();
+    let allocated: Decimal = bonuses.iter().map(|(bonus, _)| bonus.bonus_position).sum();
+    let left = ((total * bonus_per_share).floor() - allocated)
+        .to_usize()
+        .unwrap_or(0);
+    let mut order: Vec<usize> = (0..bonuses.len()).collect();
+    order.sort_by(|a, b| bonuses[*b].1.cmp(&bonuses[*a].1));
+    for index in order.into_iter().take(left) {
+        bonuses[index].0.bonus_position += Decimal::ONE;
+    }
+    bonuses
+        .into_iter()
+        .map(|(bonus, _)| bonus)
+        .filter(|bonus| bonus.bonus_position > Decimal::ZERO)
+        .collect()
+}
+
+/// 送转股新增的批次：0成本，买入日期沿用原批次(红利税的持股期限从原批次买入时计算)
+/// 送转之前的现金分红只派给原批次，新批次从0开始累计
+pub fn bonus_lots(lots: &[Lot], bonuses: &[LotBonus]) -> Vec<Lot> {
+    bonuses
+        .iter()
+        .map(|bonus| Lot {
+            stock_lot_id: 0, // 尚未保存
+            buy_price: Decimal::ZERO,
+            remaining_position: bonus.bonus_position,
+            buy_date: lots
+                .iter()
+                .find(|lot| lot.stock_lot_id == bonus.source_lot_id)
+                .and_then(|lot| lot.buy_date),
+            dividend_per_share: Decimal::ZERO,
+        })
+        .collect()
+}
+
+// 依次从批次中取出卖出数量
+fn take_in_order<'a>(
+    lots: impl Iterator<Item = &'a Lot>,
+    sell_position: Decimal,
+) -> Vec<(Lot, Decimal)> {
+    let mut plan = Vec::new();
+    let mut left = sell_position;
+    for lot in lots {
+        if left <= Decimal::ZERO {
+            break;
+        }
+        let position = lot.remaining_position.min(left);
+        if position > Decimal::ZERO {
+            plan.push((*lot, position));
+            left -= position;
+        }
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn lot(stock_lot_id: i32, buy_price: Decimal, remaining_position: Decimal) -> Lot {
+        Lot {
+            stock_lot_id,
+            buy_price,
+            remaining_position,
+            buy_date: NaiveDate::from_ymd_opt(2024, 3, 1),
+            dividend_per_share: Decimal::ZERO,
+        }
+    }
+
+    #[test]
+    fn split_position_and_price() {
+        // 1拆3：数量乘3，价格除3
+        assert_eq!(split_position(dec!(900), dec!(1), dec!(3)), dec!(2700));
+        assert_eq!(split_price(dec!(12), dec!(1), dec!(3)), dec!(4));
+        // 10合1：数量除10，价格乘10，去掉多余的0
+        assert_eq!(split_position(dec!(1000), dec!(10), dec!(1)), dec!(100));
+        assert_eq!(split_price(dec!(1.20), dec!(10), dec!(1)).to_string(), "12");
+        // 除不尽的价格保留全部精度，拆合回去后与原价一致
+        let price = split_price(dec!(10), dec!(1), dec!(3));
+        assert_eq!(price.round_dp(4), dec!(3.3333));
+        assert_eq!(split_price(price, dec!(3), dec!(1)).round_dp(10), dec!(10));
+    }
+
+    #[test]
+    fn split_lots_keeps_ids_and_dates() {
+        let lots = [
+            Lot {
+                dividend_per_share: dec!(0.3),
+                ..lot(1, dec!(10), dec!(900))
+            },
+            lot(2, dec!(12), dec!(300)),
+        ];
+        let split = split_lots(&lots, dec!(1), dec!(3));
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].stock_lot_id, 1);
+        assert_eq!(split[0].remaining_position, dec!(2700));
+        assert_eq!(split[0].buy_price.round_dp(4), dec!(3.3333));
+        assert_eq!(split[0].dividend_per_share, dec!(0.1));
+        assert_eq!(split[0].buy_date, lots[0].buy_date);
+        assert_eq!(split[1].remaining_position, dec!(900));
+        assert_eq!(split[1].buy_price, dec!(4));
+        // 合股还原
+        let merged = split_lots(&split, dec!(3), dec!(1));
+        assert_eq!(merged[1].remaining_position, dec!(300));
+        assert_eq!(merged[1].buy_price, dec!(12));
+    }
+
+    fn sold(sales: &[LotSale]) -> Vec<(i32, Decimal)> {
+        sales
+            .iter()
+            .map(|sale| (sale.stock_lot_id, sale.sell_position))
+            .collect()
+    }
+
+    #[test]
+    fn consume_in_order() {
+        let lots = [
+            lot(1, dec!(10), dec!(500)),
+            lot(2, dec!(12), dec!(300)),
+            lot(3, dec!(11), dec!(200)),
+        ];
+        let fifo = consume_lots(&lots, dec!(600), dec!(13), LotMethod::Fifo, &[]).unwrap();
+        assert_eq!(sold(&fifo), vec![(1, dec!(500)), (2, dec!(100))]);
+        assert_eq!(fifo[0].realized_profit, dec!(1500));
+        assert_eq!(fifo[1].realized_profit, dec!(100));
+        let lifo = consume_lots(&lots, dec!(600), dec!(13), LotMethod::Lifo, &[]).unwrap();
+        assert_eq!(
+            sold(&lifo),
+            vec![(3, dec!(200)), (2, dec!(300)), (1, dec!(100))]
+        );
+        // 剩余批次
+        let left = apply_sales(&lots, &lifo);
+        assert_eq!(left.len(), 1);
+        assert_eq!(
+            (left[0].stock_lot_id, left[0].remaining_position),
+            (1, dec!(400))
+        );
+        // 超过持仓
+        assert_eq!(
+            consume_lots(&lots, dec!(1100), dec!(13), LotMethod::Fifo, &[]).unwrap_err(),
+            AppError::LotMismatch {
+                selected_position: dec!(1000),
+                position: dec!(1100),
+            }
+        );
+    }
+
+    #[test]
+    fn consume_selected_lots() {
+        let lots = [lot(1, dec!(10), dec!(500)), lot(2, dec!(12), dec!(300))];
+        let select = |selections: &[(i32, Decimal)]| {
+            let selections: Vec<LotSelection> = selections
+                .iter()
+                .map(|(stock_lot_id, position)| LotSelection {
+                    stock_lot_id: *stock_lot_id,
+                    position: *position,
+                })
+                .collect();
+            consume_lots(&lots, dec!(400), dec!(13), LotMethod::Specific, &selections)
+        };
+        assert_eq!(
+            sold(&select(&[(2, dec!(300)), (1, dec!(100))]).unwrap()),
+            vec![(2, dec!(300)), (1, dec!(100))]
+        );
+        assert_eq!(
+            select(&[(3, dec!(400))]).unwrap_err(),
+            AppError::LotUnavailable { stock_lot_id: 3 }
+        );
+        // 同一批次分两次选择，合计超过剩余数量
+        assert_eq!(
+            select(&[(2, dec!(200)), (2, dec!(200))]).unwrap_err(),
+            AppError::OversellLot {
+                stock_lot_id: 2,
+                position: dec!(200),
+                remaining_position: dec!(100),
+            }
+        );
+        assert_eq!(
+            select(&[(1, dec!(300))]).unwrap_err(),
+            AppError::LotMismatch {
+                selected_position: dec!(300),
+                position: dec!(400),
+            }
+        );
+    }
+
+    #[test]
+    fn allocate_bonus_rounding() {
+        // 每股送0.3股：全部持仓1001股送300股，各批次取整后共298股，余下2股给舍去较多的第1、3批
+        let lots = [
+            lot(1, dec!(10), dec!(333)),
+            lot(2, dec!(12), dec!(335)),
+            lot(3, dec!(11), dec!(333)),
+        ];
+        let bonuses = allocate_bonus(&lots, dec!(0.3));
+        let allocated: Vec<(i32, Decimal)> = bonuses
+            .iter()
+            .map(|bonus| (bonus.source_lot_id, bonus.bonus_position))
+            .collect();
+        assert_eq!(
+            allocated,
+            vec![(1, dec!(100)), (2, dec!(100)), (3, dec!(100))]
+        );
+        // 不足1股的批次不返回
+        let lots = [lot(1, dec!(10), dec!(1000)), lot(2, dec!(12), dec!(3))];
+        let bonuses = allocate_bonus(&lots, dec!(0.3));
+        assert_eq!(bonuses.len(), 1);
+        assert_eq!(bonuses[0].bonus_position, dec!(300));
+        // 送转批次0成本，沿用原批次的买入日期
+        let new_lots = bonus_lots(&lots, &bonuses);
+        assert_eq!(new_lots[0].buy_price, Decimal::ZERO);
+        assert_eq!(new_lots[0].buy_date, lots[0].buy_date);
+    }
+}
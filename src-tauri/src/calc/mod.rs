@@ -0,0 +1,8 @@
+pub mod calendar;
+pub mod dividend_tax;
+pub mod lot;
+pub mod money;
+pub mod settlement;
+pub mod time;
+pub mod trade_engine;
+pub mod validation;
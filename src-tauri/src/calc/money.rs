@@ -0,0 +1,29 @@
+// 金额精度与舍入规则
+use rust_decimal::{Decimal, RoundingStrategy};
+use rust_decimal_macros::dec;
+
+pub const FEE_SCALE: u32 = 2; // 费用、金额精确到分(0.01元)
+pub const PRICE_SCALE: u32 = 3; // 价格、成本精确到厘(0.001元)
+pub const RATE_SCALE: u32 = 6; // 利润率
+pub const PRICE_TICK: Decimal = dec!(0.01); // 股票最小价格变动0.01元
+pub const FUND_PRICE_TICK: Decimal = dec!(0.001); // 基金、可转债最小价格变动0.001元
+
+// 费用、盈亏金额：四舍五入到0.01元
+pub fn round_fee(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(FEE_SCALE, RoundingStrategy::MidpointAwayFromZero)
+}
+
+// 价格、成本：四舍五入到0.001元
+pub fn round_price(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(PRICE_SCALE, RoundingStrategy::MidpointAwayFromZero)
+}
+
+// 比例
+pub fn round_rate(value: Decimal) -> Decimal {
+    value.round_dp_with_strategy(RATE_SCALE, RoundingStrategy::MidpointAwayFromZero)
+}
+
+// 向上取整到最小价格变动单位
+pub fn ceil_to_tick(value: Decimal, tick: Decimal) -> Decimal {
+    (value / tick).ceil() * tick
+}
@@ -0,0 +1,136 @@
+// 交收规则：A股T+1，当日买入的数量下一个交易日才能卖出
+use crate::calc::calendar::TradingCalendar;
+use crate::constant::stock_type::StockType;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+// 可卖数量与冻结数量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Sellable {
+    pub sellable_position: Decimal, // 可卖数量
+    pub frozen_position: Decimal,   // 尚未交收、不能卖出的数量
+}
+
+/// 计算trade_date当天的可卖数量
+/// buys为各次买入的日期和数量，T+0的品种买入后即可卖出
+pub fn sellable_position(
+    calendar: &TradingCalendar,
+    stock_type: StockType,
+    buys: &[(NaiveDate, Decimal)],
+    total_position: Decimal,
+    trade_date: NaiveDate,
+) -> Sellable {
+    let frozen_position = if stock_type.is_t0() {
+        Decimal::ZERO
+    } else {
+        buys.iter()
+            .filter(|(buy_date, _)| calendar.next_trading_day(*buy_date) > trade_date)
+            .map(|(_, position)| *position)
+            .sum::<Decimal>()
+            .min(total_position)
+    };
+    Sellable {
+        sellable_position: total_position - frozen_position,
+        frozen_position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn date(text: &str) -> NaiveDate {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn bought_today_is_frozen_until_next_trading_day() {
+        let calendar = TradingCalendar::bundled();
+        let buys = [
+            (date("2024-03-01"), dec!(900)),
+            (date("2024-03-04"), dec!(300)),
+        ];
+        // 周五买入的900股周一可卖，周一买入的300股冻结
+        assert_eq!(
+            sellable_position(
+                &calendar,
+                StockType::SH,
+                &buys,
+                dec!(1200),
+                date("2024-03-04")
+            ),
+            Sellable {
+                sellable_position: dec!(900),
+                frozen_position: dec!(300),
+            }
+        );
+        assert_eq!(
+            sellable_position(
+                &calendar,
+                StockType::SH,
+                &buys,
+                dec!(1200),
+                date("2024-03-05")
+            ),
+            Sellable {
+                sellable_position: dec!(1200),
+                frozen_position: dec!(0),
+            }
+        );
+    }
+
+    #[test]
+    fn holidays_delay_settlement() {
+        let calendar = TradingCalendar::bundled();
+        // 国庆节前最后一个交易日买入，节后第一个交易日(10月8日)才能卖出
+        let buys = [(date("2024-09-30"), dec!(500))];
+        let frozen = |trade_date: &str| {
+            sellable_position(&calendar, StockType::SZ, &buys, dec!(500), date(trade_date))
+                .frozen_position
+        };
+        assert_eq!(frozen("2024-09-30"), dec!(500));
+        assert_eq!(frozen("2024-10-07"), dec!(500));
+        assert_eq!(frozen("2024-10-08"), dec!(0));
+    }
+
+    #[test]
+    fn frozen_never_exceeds_position() {
+        let calendar = TradingCalendar::bundled();
+        // 当日买入后又卖出了之前的持仓，冻结数量不超过剩余持仓
+        let buys = [(date("2024-03-04"), dec!(300))];
+        assert_eq!(
+            sellable_position(
+                &calendar,
+                StockType::SH,
+                &buys,
+                dec!(200),
+                date("2024-03-04")
+            ),
+            Sellable {
+                sellable_position: dec!(0),
+                frozen_position: dec!(200),
+            }
+        );
+    }
+
+    #[test]
+    fn t0_is_always_sellable() {
+        let calendar = TradingCalendar::bundled();
+        let buys = [(date("2024-03-04"), dec!(10))];
+        assert_eq!(
+            sellable_position(
+                &calendar,
+                StockType::KZZ,
+                &buys,
+                dec!(10),
+                date("2024-03-04")
+            ),
+            Sellable {
+                sellable_position: dec!(10),
+                frozen_position: dec!(0),
+            }
+        );
+    }
+}
@@ -0,0 +1,40 @@
+// 时间处理：数据库中的时间统一保存为本地时间 YYYY-MM-DD HH:MM:SS
+use chrono::{Local, NaiveDate, NaiveDateTime};
+
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// 前端及旧数据中可能出现的时间格式
+const TIME_FORMATS: &[&str] = &[
+    TIME_FORMAT,
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// 解析时间，只有日期时取当天0点，无法解析返回None
+pub fn parse_time(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// 当前本地时间
+pub fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+/// 格式化为数据库保存的时间
+pub fn format_time(time: NaiveDateTime) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+/// 操作发生的时间：优先使用用户填写的操作时间，否则使用记录创建时间
+pub fn action_time(action_time: &str, created_at: &str) -> Option<NaiveDateTime> {
+    parse_time(action_time).or_else(|| parse_time(created_at))
+}
@@ -0,0 +1,734 @@
+// 交易计算引擎：只做计算，不访问数据库
+use crate::calc::dividend_tax::dividend_tax;
+use crate::calc::lot::{
+    allocate_bonus, apply_sales, bonus_lots, consume_lots, split_lots, split_position, split_price,
+    Lot, LotBonus, LotSale, LotSelection,
+};
+use crate::calc::money::{ceil_to_tick, round_fee, round_price, round_rate, PRICE_TICK};
+use crate::constant::action_type::ActionType;
+use crate::constant::lot_method::LotMethod;
+use crate::error::AppError;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/*************************************输入**************************************/
+// 费率
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TradeFeeRate {
+    pub commission_fee_rate: Decimal, // 佣金
+    pub tax_fee_rate: Decimal,        // 印花税
+    pub regulatory_fee_rate: Decimal, // 证管费
+    pub brokerage_fee_rate: Decimal,  // 经手费
+    pub transfer_fee_rate: Decimal,   // 过户费
+    pub min_commission: Decimal,      // 最低佣金(免五为0)
+}
+
+// 持仓状态（上一次操作之后）
+#[derive(Debug, Clone, Default)]
+pub struct PositionState {
+    pub current_cost: Decimal,          // 持仓成本
+    pub total_position: Decimal,        // 总数量
+    pub total_fee: Decimal,             // 到目前总手续费
+    pub total_realized_profit: Decimal, // 到目前已实现盈亏
+    pub lots: Vec<Lot>,                 // 持有中的批次(按买入先后)
+}
+
+// 本次交易
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trade {
+    pub action: ActionType,
+    pub current_price: Decimal,            // 当前价格
+    pub transaction_price: Decimal, // 交易价格(平仓时忽略，使用当前价格；分红时为每股派息，送转股时为每股送转股数；拆合股时忽略)
+    pub transaction_position: Decimal, // 交易数量(平仓、分红时忽略，使用总数量；送转股时忽略，按总数量计算；拆合股时忽略)
+    pub split_from: Decimal,           // 拆合前股数(拆合股时每split_from股变为split_to股，其余为1)
+    pub split_to: Decimal,             // 拆合后股数
+    pub lot_method: LotMethod,         // 卖出时批次的匹配方式
+    pub lot_selections: Vec<LotSelection>, // 指定批次卖出时的选择
+    #[serde(skip)]
+    pub trade_date: Option<NaiveDate>, // 交易日期，按操作时间设置，用于计算红利税
+}
+
+impl Trade {
+    pub fn new(
+        action: ActionType,
+        current_price: Decimal,
+        transaction_price: Decimal,
+        transaction_position: Decimal,
+    ) -> Self {
+        Trade {
+            action,
+            current_price,
+            transaction_price,
+            transaction_position,
+            split_from: Decimal::ONE,
+            split_to: Decimal::ONE,
+            lot_method: LotMethod::Fifo,
+            lot_selections: Vec::new(),
+            trade_date: None,
+        }
+    }
+
+    // 拆合股：每split_from股变为split_to股，不产生交易价格和数量
+    pub fn split(current_price: Decimal, split_from: Decimal, split_to: Decimal) -> Self {
+        Trade {
+            split_from,
+            split_to,
+            ..Trade::new(
+                ActionType::Split,
+                current_price,
+                Decimal::ZERO,
+                Decimal::ZERO,
+            )
+        }
+    }
+}
+
+/*************************************输出**************************************/
+// 各项交易费用
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TradeFee {
+    pub commission_fee: Decimal,   // 佣金
+    pub tax_fee: Decimal,          // 印花税
+    pub regulatory_fee: Decimal,   // 证管费
+    pub brokerage_fee: Decimal,    // 经手费
+    pub transfer_fee: Decimal,     // 过户费
+    pub dividend_tax_fee: Decimal, // 红利税
+}
+
+impl TradeFee {
+    pub fn total(&self) -> Decimal {
+        self.commission_fee
+            + self.tax_fee
+            + self.dividend_tax_fee
+            + self.regulatory_fee
+            + self.brokerage_fee
+            + self.transfer_fee
+    }
+}
+
+// 计算结果，字段与StockActionRecord一致
+#[derive(Debug, Clone, Serialize)]
+pub struct TradeResult {
+    pub current_price: Decimal,
+    pub current_cost: Decimal,
+    pub total_position: Decimal,
+    pub total_fee: Decimal,
+    pub transaction_price: Decimal,
+    pub transaction_position: Decimal,
+    pub transaction_commission_fee: Decimal,   // 佣金
+    pub transaction_tax_fee: Decimal,          // 印花税
+    pub transaction_regulatory_fee: Decimal,   // 证管费
+    pub transaction_brokerage_fee: Decimal,    // 经手费
+    pub transaction_transfer_fee: Decimal,     // 过户费
+    pub transaction_dividend_tax_fee: Decimal, // 红利税
+    pub split_from: Decimal,                   // 拆合前股数
+    pub split_to: Decimal,                     // 拆合后股数
+    pub action: i32,
+    pub profit: Decimal,                // 持仓盈亏(摊薄成本)
+    pub profit_rate: Decimal,           // 持仓盈亏比例(摊薄成本)
+    pub realized_profit: Decimal,       // 本次已实现盈亏(按批次，未扣费用；分红时为派息金额)
+    pub unrealized_profit: Decimal,     // 剩余批次的浮动盈亏
+    pub net_profit_after_fees: Decimal, // 累计已实现 + 浮动 - 累计费用
+    pub lot_sales: Vec<LotSale>,        // 本次卖出消耗的批次
+    pub lot_bonuses: Vec<LotBonus>,     // 本次送转股给各批次新增的股份
+}
+
+impl TradeResult {
+    // 本次交易费用合计
+    pub fn transaction_fee(&self) -> Decimal {
+        self.transaction_commission_fee
+            + self.transaction_tax_fee
+            + self.transaction_dividend_tax_fee
+            + self.transaction_regulatory_fee
+            + self.transaction_brokerage_fee
+            + self.transaction_transfer_fee
+    }
+}
+
+// 含费成本与保本价
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BreakEven {
+    pub cost_with_fee: Decimal, // 含费成本 = (持仓成本 * 总数量 + 累计费用) / 总数量
+    pub break_even_price: Decimal, // 保本价：全部卖出扣除卖出费用后收回含费成本的最低价格
+}
+
+/*************************************计算引擎**************************************/
+pub struct TradeEngine {
+    fee_rate: TradeFeeRate,
+}
+
+impl TradeEngine {
+    pub fn new(fee_rate: TradeFeeRate) -> Self {
+        TradeEngine { fee_rate }
+    }
+
+    /// 计算本次交易费用，每项费用分别四舍五入到分，红利税与所卖批次有关，不在此计算
+    pub fn calculate_fee(&self, action: ActionType, price: Decimal, position: Decimal) -> TradeFee {
+        if action.is_corporate_action() {
+            // 分红、送转、配股不收取交易费用
+            return TradeFee::default();
+        }
+        let transaction_value = price * position;
+        let mut commission_fee = round_fee(transaction_value * self.fee_rate.commission_fee_rate);
+        if commission_fee < self.fee_rate.min_commission {
+            // 佣金不足最低佣金时按最低佣金收取
+            commission_fee = self.fee_rate.min_commission;
+        }
+        // 印花税仅卖出收取，买入(开仓、加仓)不收
+        let tax_fee = if action.is_sell() {
+            round_fee(transaction_value * self.fee_rate.tax_fee_rate)
+        } else {
+            Decimal::ZERO
+        };
+        TradeFee {
+            commission_fee,
+            tax_fee,
+            regulatory_fee: round_fee(transaction_value * self.fee_rate.regulatory_fee_rate),
+            brokerage_fee: round_fee(transaction_value * self.fee_rate.brokerage_fee_rate),
+            transfer_fee: round_fee(transaction_value * self.fee_rate.transfer_fee_rate),
+            dividend_tax_fee: Decimal::ZERO,
+        }
+    }
+
+    /// 计算含费成本和保本价，空仓或卖出费率不低于100%(无法保本)时返回None
+    pub fn break_even(&self, state: &PositionState) -> Option<BreakEven> {
+        let position = state.total_position;
+        if position <= Decimal::ZERO {
+            return None;
+        }
+        // 需要收回的金额(摊薄成本已包含之前卖出的盈亏)
+        let cost_basis = state.current_cost * position + state.total_fee;
+        let cost_with_fee = round_price(cost_basis / position);
+        if cost_basis <= Decimal::ZERO {
+            return Some(BreakEven {
+                cost_with_fee,
+                break_even_price: Decimal::ZERO,
+            });
+        }
+
+        // 卖出费用 = max(佣金, 最低佣金) + 印花税 + 证管费 + 经手费 + 过户费
+        let rate = &self.fee_rate;
+        let other_rate = rate.tax_fee_rate
+            + rate.regulatory_fee_rate
+            + rate.brokerage_fee_rate
+            + rate.transfer_fee_rate;
+        let net_rate = Decimal::ONE - other_rate - rate.commission_fee_rate;
+        if net_rate <= Decimal::ZERO {
+            return None;
+        }
+        // 按比例收取佣金、按最低佣金收取时都要收回成本，取两者的较大解
+        let price = (cost_basis / (position * net_rate))
+            .max((cost_basis + rate.min_commission) / (position * (Decimal::ONE - other_rate)));
+
+        // 费用逐项四舍五入，按最小价格变动向上取整后再校验：亏损时上调直到不亏损，
+        // 舍入使更低的价格也不亏损时下调
+        let mut break_even_price = ceil_to_tick(price, PRICE_TICK);
+        while self.net_proceeds(break_even_price, position) < cost_basis {
+            break_even_price += PRICE_TICK;
+        }
+        while break_even_price > PRICE_TICK
+            && self.net_proceeds(break_even_price - PRICE_TICK, position) >= cost_basis
+        {
+            break_even_price -= PRICE_TICK;
+        }
+        Some(BreakEven {
+            cost_with_fee,
+            break_even_price,
+        })
+    }
+
+    // 以price全部卖出时扣除卖出费用后收回的金额
+    fn net_proceeds(&self, price: Decimal, position: Decimal) -> Decimal {
+        price * position
+            - self
+                .calculate_fee(ActionType::Close, price, position)
+                .total()
+    }
+
+    /// 根据持仓状态和本次交易计算新的操作记录
+    pub fn calculate(&self, state: &PositionState, trade: &Trade) -> Result<TradeResult, AppError> {
+        let current_price = round_price(trade.current_price);
+        // 送转股:按每股送转股数分配到持有中的各批次
+        let lot_bonuses = match trade.action {
+            ActionType::BonusShares => allocate_bonus(&state.lots, trade.transaction_price),
+            _ => Vec::new(),
+        };
+        let (transaction_price, transaction_position) = match trade.action {
+            ActionType::Close => (current_price, state.total_position), // 平仓:以当前价格卖出全部
+            // 分红:按全部持仓派息，每股派息不按价格精度舍入
+            ActionType::CashDividend => (trade.transaction_price, state.total_position),
+            // 送转股:每股送转股数不按价格精度舍入，送转股数为各批次分到的合计
+            ActionType::BonusShares => (
+                trade.transaction_price,
+                lot_bonuses.iter().map(|bonus| bonus.bonus_position).sum(),
+            ),
+            // 拆合股:没有交易价格和数量，比例另外记录
+            ActionType::Split => (Decimal::ZERO, Decimal::ZERO),
+            _ => (
+                round_price(trade.transaction_price),
+                trade.transaction_position,
+            ),
+        };
+        if let ActionType::ReducePosition = trade.action {
+            if transaction_position >= state.total_position {
+                return Err(AppError::OversellPosition {
+                    position: transaction_position,
+                    total_position: state.total_position,
+                });
+            }
+        }
+
+        // 批次：买入、配股新增一个批次，送转按原批次各新增一个批次，卖出按匹配方式消耗批次，
+        // 分红计入持有中的批次
+        let (lot_sales, remaining_lots) = match trade.action {
+            ActionType::Open | ActionType::AddPosition | ActionType::RightsIssue => {
+                let mut lots = state.lots.clone();
+                lots.push(Lot {
+                    stock_lot_id: 0, // 尚未保存
+                    buy_price: transaction_price,
+                    remaining_position: transaction_position,
+                    buy_date: trade.trade_date,
+                    dividend_per_share: Decimal::ZERO,
+                });
+                (Vec::new(), lots)
+            }
+            ActionType::BonusShares => {
+                let mut lots = state.lots.clone();
+                lots.extend(bonus_lots(&state.lots, &lot_bonuses));
+                (Vec::new(), lots)
+            }
+            ActionType::ReducePosition | ActionType::Close => {
+                let lot_sales = consume_lots(
+                    &state.lots,
+                    transaction_position,
+                    transaction_price,
+                    trade.lot_method,
+                    &trade.lot_selections,
+                )?;
+                let lots = apply_sales(&state.lots, &lot_sales);
+                (lot_sales, lots)
+            }
+            ActionType::CashDividend => {
+                let lots = state
+                    .lots
+                    .iter()
+                    .map(|lot| Lot {
+                        dividend_per_share: lot.dividend_per_share + transaction_price,
+                        ..*lot
+                    })
+                    .collect();
+                (Vec::new(), lots)
+            }
+            ActionType::Split => (
+                Vec::new(),
+                split_lots(&state.lots, trade.split_from, trade.split_to),
+            ),
+        };
+
+        // 本次各项费用，卖出时按所卖批次的持股期限补扣红利税
+        let fee = TradeFee {
+            dividend_tax_fee: dividend_tax(&state.lots, &lot_sales, trade.trade_date),
+            ..self.calculate_fee(trade.action, transaction_price, transaction_position)
+        };
+        let total_fee = state.total_fee + fee.total();
+        let mut realized_profit: Decimal = lot_sales.iter().map(|sale| sale.realized_profit).sum();
+        if trade.action == ActionType::CashDividend {
+            // 现金分红计入已实现收益
+            realized_profit += round_fee(transaction_price * transaction_position);
+        }
+        let unrealized_profit = round_fee(
+            remaining_lots
+                .iter()
+                .map(|lot| (current_price - lot.buy_price) * lot.remaining_position)
+                .sum(),
+        );
+        let net_profit_after_fees =
+            state.total_realized_profit + realized_profit + unrealized_profit - total_fee;
+
+        let (current_cost, total_position, profit, profit_rate) = match trade.action {
+            ActionType::Open => {
+                // 开仓:成本 = 交易价格, 总数 = 交易数量
+                let current_cost = transaction_price;
+                let total_position = transaction_position;
+                let profit = round_fee((current_price - current_cost) * total_position);
+                let profit_rate =
+                    calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
+                (current_cost, total_position, profit, profit_rate)
+            }
+            ActionType::AddPosition | ActionType::RightsIssue => {
+                // 配股以配股价格计入，与加仓相同
+                // 总仓位
+                let total_position = state.total_position + transaction_position;
+                // 新成本价（加权平均）
+                let current_cost = round_price(
+                    (state.current_cost * state.total_position
+                        + transaction_price * transaction_position)
+                        / total_position,
+                );
+                // 当前整体浮动利润
+                let profit = round_fee((current_price - current_cost) * total_position);
+                // 利润率：以当前总成本为基准
+                let profit_rate =
+                    calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
+                (current_cost, total_position, profit, profit_rate)
+            }
+            ActionType::BonusShares => {
+                // 送转股:以0价格计入，持仓成本按新的总数量摊薄
+                let total_position = state.total_position + transaction_position;
+                let current_cost =
+                    round_price(state.current_cost * state.total_position / total_position);
+                let profit = round_fee((current_price - current_cost) * total_position);
+                let profit_rate =
+                    calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
+                (current_cost, total_position, profit, profit_rate)
+            }
+            ActionType::ReducePosition => {
+                // 采用利润反向摊薄计算剩余成本的方式（券商常见写法之一）
+                // 新总手数
+                let total_position = state.total_position - transaction_position;
+                // 摊薄成本价 = (原成本*原总手数-卖出价格*卖出手数) / 新总手数
+                let current_cost = round_price(
+                    (state.current_cost * state.total_position
+                        - transaction_price * transaction_position)
+                        / total_position,
+                );
+                // 利润 = (当前价格 - 持仓成本) * 当前持仓数量
+                let profit = round_fee((current_price - current_cost) * total_position);
+                // 利润率 = 利润 / 当前成本
+                let profit_rate =
+                    calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
+                (current_cost, total_position, profit, profit_rate)
+            }
+            ActionType::CashDividend => {
+                // 除息:持仓不变，成本价扣除每股派息
+                let total_position = state.total_position;
+                let current_cost = round_price(
+                    (state.current_cost * total_position - realized_profit) / total_position,
+                );
+                let profit = round_fee((current_price - current_cost) * total_position);
+                let profit_rate =
+                    calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
+                (current_cost, total_position, profit, profit_rate)
+            }
+            ActionType::Split => {
+                // 拆合股:总数量按比例折算，成本价反向折算，不产生盈亏和费用
+                let total_position =
+                    split_position(state.total_position, trade.split_from, trade.split_to);
+                let current_cost = round_price(split_price(
+                    state.current_cost,
+                    trade.split_from,
+                    trade.split_to,
+                ));
+                let profit = round_fee((current_price - current_cost) * total_position);
+                let profit_rate =
+                    calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
+                (current_cost, total_position, profit, profit_rate)
+            }
+            ActionType::Close => {
+                // 利润
+                let profit = round_fee((current_price - state.current_cost) * state.total_position);
+                let profit_rate = calculate_safe_profit_rate(
+                    profit,
+                    state.current_cost,
+                    state.total_position,
+                    current_price,
+                );
+                // 成本价和总持仓变成0
+                (Decimal::ZERO, Decimal::ZERO, profit, profit_rate)
+            }
+        };
+
+        Ok(TradeResult {
+            current_price,
+            current_cost,
+            total_position,
+            total_fee,
+            transaction_price,
+            transaction_position,
+            transaction_commission_fee: fee.commission_fee,
+            transaction_tax_fee: fee.tax_fee,
+            transaction_regulatory_fee: fee.regulatory_fee,
+            transaction_brokerage_fee: fee.brokerage_fee,
+            transaction_transfer_fee: fee.transfer_fee,
+            transaction_dividend_tax_fee: fee.dividend_tax_fee,
+            split_from: trade.split_from,
+            split_to: trade.split_to,
+            action: trade.action as i32,
+            profit,
+            profit_rate,
+            realized_profit,
+            unrealized_profit,
+            net_profit_after_fees,
+            lot_sales,
+            lot_bonuses,
+        })
+    }
+}
+
+// 利润率，分母为0时返回0
+pub fn calculate_safe_profit_rate(
+    profit: Decimal,
+    cost: Decimal,
+    position: Decimal,
+    current_price: Decimal,
+) -> Decimal {
+    let base = if cost <= Decimal::ZERO {
+        // 如果成本为 0 或负，说明是"完全靠利润买出的免费股"，用市值当分母
+        current_price * position
+    } else {
+        cost * position
+    };
+    profit
+        .checked_div(base)
+        .map(round_rate)
+        .unwrap_or(Decimal::ZERO)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    // 测试用的股票费率，数据库的测试也使用
+    pub(crate) fn fee_rate() -> TradeFeeRate {
+        TradeFeeRate {
+            commission_fee_rate: dec!(0.00025),
+            tax_fee_rate: dec!(0.0005),
+            regulatory_fee_rate: dec!(0.00002),
+            brokerage_fee_rate: dec!(0.0000341),
+            transfer_fee_rate: dec!(0.00001),
+            min_commission: dec!(5),
+        }
+    }
+
+    // 按计算结果得到下一次操作前的持仓状态，新批次的ID接着已有批次编号
+    fn next_state(state: &PositionState, trade: &Trade, result: &TradeResult) -> PositionState {
+        let mut lots = apply_sales(&state.lots, &result.lot_sales);
+        if trade.action.adds_lot() {
+            let last_id = state.lots.iter().map(|lot| lot.stock_lot_id).max();
+            lots.push(Lot {
+                stock_lot_id: last_id.unwrap_or(0) + 1,
+                buy_price: result.transaction_price,
+                remaining_position: result.transaction_position,
+                buy_date: None,
+                dividend_per_share: Decimal::ZERO,
+            });
+        }
+        PositionState {
+            current_cost: result.current_cost,
+            total_position: result.total_position,
+            total_fee: result.total_fee,
+            total_realized_profit: state.total_realized_profit + result.realized_profit,
+            lots,
+        }
+    }
+
+    fn apply(
+        engine: &TradeEngine,
+        state: &PositionState,
+        trade: Trade,
+    ) -> (TradeResult, PositionState) {
+        let result = engine.calculate(state, &trade).unwrap();
+        let state = next_state(state, &trade, &result);
+        (result, state)
+    }
+
+    #[test]
+    fn open_add_reduce_close() {
+        let engine = TradeEngine::new(fee_rate());
+
+        // 建仓1000股@10，佣金不足5元按5元收取，买入不收印花税
+        let open = Trade::new(ActionType::Open, dec!(10), dec!(10), dec!(1000));
+        let (result, state) = apply(&engine, &PositionState::default(), open);
+        assert_eq!(result.transaction_commission_fee, dec!(5));
+        assert_eq!(result.transaction_tax_fee, dec!(0));
+        assert_eq!(result.transaction_fee(), dec!(5.64));
+        assert_eq!(result.current_cost, dec!(10));
+        assert_eq!(result.total_position, dec!(1000));
+        assert_eq!(result.net_profit_after_fees, dec!(-5.64));
+
+        // 加仓1000股@12，成本加权平均
+        let add = Trade::new(ActionType::AddPosition, dec!(12), dec!(12), dec!(1000));
+        let (result, state) = apply(&engine, &state, add);
+        assert_eq!(result.transaction_fee(), dec!(5.77));
+        assert_eq!(result.total_fee, dec!(11.41));
+        assert_eq!(result.current_cost, dec!(11));
+        assert_eq!(result.total_position, dec!(2000));
+        assert_eq!(result.profit, dec!(2000));
+        assert_eq!(result.profit_rate, dec!(0.090909));
+        assert_eq!(result.unrealized_profit, dec!(2000));
+
+        // 减仓500股@13，先进先出卖出第一批，剩余成本按利润摊薄
+        let reduce = Trade::new(ActionType::ReducePosition, dec!(13), dec!(13), dec!(500));
+        let (result, state) = apply(&engine, &state, reduce);
+        assert_eq!(result.transaction_tax_fee, dec!(3.25));
+        assert_eq!(result.transaction_transfer_fee, dec!(0.07));
+        assert_eq!(result.transaction_fee(), dec!(8.67));
+        assert_eq!(result.current_cost, dec!(10.333));
+        assert_eq!(result.total_position, dec!(1500));
+        assert_eq!(result.realized_profit, dec!(1500));
+        assert_eq!(result.unrealized_profit, dec!(2500));
+        assert_eq!(result.net_profit_after_fees, dec!(3979.92));
+        assert_eq!(state.lots[0].remaining_position, dec!(500));
+
+        // 平仓@14，卖出全部剩余批次
+        let close = Trade::new(ActionType::Close, dec!(14), dec!(0), dec!(0));
+        let (result, state) = apply(&engine, &state, close);
+        assert_eq!(result.transaction_position, dec!(1500));
+        assert_eq!(result.transaction_fee(), dec!(17.10));
+        assert_eq!(result.realized_profit, dec!(4000));
+        assert_eq!(result.current_cost, dec!(0));
+        assert_eq!(result.total_position, dec!(0));
+        // 卖出27500 - 买入22000 - 费用37.18
+        assert_eq!(result.net_profit_after_fees, dec!(5462.82));
+        assert!(state.lots.is_empty());
+    }
+
+    #[test]
+    fn reduce_cannot_sell_all() {
+        let engine = TradeEngine::new(fee_rate());
+        let open = Trade::new(ActionType::Open, dec!(10), dec!(10), dec!(1000));
+        let (_, state) = apply(&engine, &PositionState::default(), open);
+        let reduce = Trade::new(ActionType::ReducePosition, dec!(10), dec!(10), dec!(1000));
+        assert!(matches!(
+            engine.calculate(&state, &reduce),
+            Err(AppError::OversellPosition { .. })
+        ));
+    }
+
+    #[test]
+    fn stamp_duty_on_sells_only() {
+        let engine = TradeEngine::new(fee_rate());
+        let buy = engine.calculate_fee(ActionType::AddPosition, dec!(20), dec!(1000));
+        let sell = engine.calculate_fee(ActionType::ReducePosition, dec!(20), dec!(1000));
+        assert_eq!(buy.tax_fee, dec!(0));
+        assert_eq!(sell.tax_fee, dec!(10));
+        assert_eq!(buy.commission_fee, dec!(5));
+        assert_eq!(sell.total() - buy.total(), dec!(10));
+    }
+
+    #[test]
+    fn break_even_covers_cost_and_sell_fees() {
+        let engine = TradeEngine::new(fee_rate());
+        let state = PositionState {
+            current_cost: dec!(11),
+            total_position: dec!(2000),
+            total_fee: dec!(11.41),
+            ..Default::default()
+        };
+        let break_even = engine.break_even(&state).unwrap();
+        assert_eq!(break_even.cost_with_fee, dec!(11.006));
+        assert_eq!(break_even.break_even_price, dec!(11.02));
+        assert!(engine.break_even(&PositionState::default()).is_none());
+    }
+
+    #[test]
+    fn break_even_is_lowest_price_without_loss() {
+        // 持仓很少、最低佣金很高时，逐项舍入和最低佣金使保本价偏离按比例计算的解
+        for min_commission in [dec!(0), dec!(5), dec!(100), dec!(5000)] {
+            let engine = TradeEngine::new(TradeFeeRate {
+                min_commission,
+                ..fee_rate()
+            });
+            for position in [dec!(1), dec!(10), dec!(100), dec!(12345)] {
+                for current_cost in [dec!(0.013), dec!(1.005), dec!(9.999), dec!(1234.567)] {
+                    let state = PositionState {
+                        current_cost,
+                        total_position: position,
+                        total_fee: dec!(5.01),
+                        ..Default::default()
+                    };
+                    let cost_basis = current_cost * position + state.total_fee;
+                    let price = engine.break_even(&state).unwrap().break_even_price;
+                    assert!(engine.net_proceeds(price, position) >= cost_basis);
+                    assert!(engine.net_proceeds(price - PRICE_TICK, position) < cost_basis);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_break_even_when_fees_take_everything() {
+        let engine = TradeEngine::new(TradeFeeRate {
+            tax_fee_rate: dec!(1),
+            ..fee_rate()
+        });
+        let state = PositionState {
+            current_cost: dec!(10),
+            total_position: dec!(100),
+            ..Default::default()
+        };
+        assert!(engine.break_even(&state).is_none());
+    }
+
+    #[test]
+    fn bonus_shares_keep_holding_period() {
+        let engine = TradeEngine::new(fee_rate());
+        let date = |text: &str| text.parse::<NaiveDate>().ok();
+        let lot = |stock_lot_id, buy_price, remaining_position, buy_date| Lot {
+            stock_lot_id,
+            buy_price,
+            remaining_position,
+            buy_date,
+            dividend_per_share: Decimal::ZERO,
+        };
+        let state = PositionState {
+            current_cost: dec!(10.388),
+            total_position: dec!(1560),
+            lots: vec![
+                lot(1, dec!(10), dec!(1055), date("2024-01-02")),
+                lot(2, dec!(11.2), dec!(505), date("2024-06-03")),
+            ],
+            ..Default::default()
+        };
+
+        // 10送3：合计468股，两个批次各舍去0.5股，余下的1股给先买入的批次
+        let bonus = Trade {
+            trade_date: date("2024-07-01"),
+            ..Trade::new(ActionType::BonusShares, dec!(8), dec!(0.3), dec!(0))
+        };
+        let result = engine.calculate(&state, &bonus).unwrap();
+        assert_eq!(result.transaction_price, dec!(0.3));
+        assert_eq!(result.transaction_position, dec!(468));
+        assert_eq!(
+            result
+                .lot_bonuses
+                .iter()
+                .map(|bonus| (bonus.source_lot_id, bonus.bonus_position))
+                .collect::<Vec<_>>(),
+            vec![(1, dec!(317)), (2, dec!(151))]
+        );
+        assert_eq!(result.total_position, dec!(2028));
+        assert_eq!(result.current_cost, dec!(7.991));
+        assert_eq!(result.transaction_fee(), dec!(0));
+
+        // 送转后每股派息0.5，卖出第一批的送转股：持股期限从2024-01-02算起，超过1个月按10%补税
+        let mut lots = state.lots.clone();
+        lots.extend(bonus_lots(&state.lots, &result.lot_bonuses));
+        for (index, lot) in lots.iter_mut().enumerate() {
+            lot.stock_lot_id = index as i32 + 1;
+            lot.dividend_per_share = dec!(0.5);
+        }
+        assert_eq!(lots[2].buy_date, date("2024-01-02"));
+        assert_eq!(lots[2].buy_price, dec!(0));
+        let state = PositionState {
+            current_cost: result.current_cost,
+            total_position: result.total_position,
+            lots,
+            ..Default::default()
+        };
+        let reduce = Trade {
+            lot_method: LotMethod::Specific,
+            lot_selections: vec![LotSelection {
+                stock_lot_id: 3,
+                position: dec!(317),
+            }],
+            trade_date: date("2024-08-01"),
+            ..Trade::new(ActionType::ReducePosition, dec!(8), dec!(8), dec!(317))
+        };
+        let result = engine.calculate(&state, &reduce).unwrap();
+        assert_eq!(result.transaction_dividend_tax_fee, dec!(15.85));
+        assert_eq!(result.realized_profit, dec!(2536));
+    }
+}
@@ -0,0 +1,461 @@
+// 交易输入校验：只做检查，不访问数据库，命令在计算和写入之前调用
+use crate::calc::lot::split_position;
+use crate::calc::settlement::Sellable;
+use crate::calc::trade_engine::{PositionState, Trade};
+use crate::constant::action_type::ActionType;
+use crate::constant::board_lot::BoardLot;
+use crate::constant::price_limit::PriceLimit;
+use crate::constant::stock_status::StockStatus;
+use crate::constant::stock_type::StockType;
+use crate::error::AppError;
+use rust_decimal::Decimal;
+
+// 价格必须大于0
+pub fn validate_price(price: Decimal) -> Result<(), AppError> {
+    if price <= Decimal::ZERO {
+        return Err(AppError::InvalidPrice { price });
+    }
+    Ok(())
+}
+
+// 数量必须大于0
+pub fn validate_quantity(position: Decimal) -> Result<(), AppError> {
+    if position <= Decimal::ZERO {
+        return Err(AppError::InvalidQuantity { position });
+    }
+    Ok(())
+}
+
+// 价格须为最小价格变动单位的整数倍，传入前收盘价时须在涨跌幅限制内
+pub fn validate_price_limit(
+    stock_type: StockType,
+    is_st: bool,
+    price: Decimal,
+    prev_close: Option<Decimal>,
+) -> Result<(), AppError> {
+    let price_limit = PriceLimit::for_stock_type(stock_type, is_st);
+    if !price_limit.is_on_tick(price) {
+        return Err(AppError::PriceTick {
+            price,
+            tick: price_limit.tick,
+        });
+    }
+    let Some(prev_close) = prev_close else {
+        return Ok(());
+    };
+    validate_price(prev_close)?;
+    let (limit_down, limit_up) = price_limit.band(prev_close);
+    if price < limit_down || price > limit_up {
+        return Err(AppError::PriceLimit {
+            price,
+            prev_close,
+            limit_down,
+            limit_up,
+        });
+    }
+    Ok(())
+}
+
+// 已平仓的股票不能再交易
+pub fn validate_status(stock_id: i32, status: StockStatus) -> Result<(), AppError> {
+    if status == StockStatus::CLOSE {
+        return Err(AppError::StockClosed { stock_id });
+    }
+    Ok(())
+}
+
+// 买入须为整手；卖出须为整手，零股须一次卖出
+pub fn validate_board_lot(
+    stock_type: StockType,
+    action: ActionType,
+    position: Decimal,
+    total_position: Decimal,
+) -> Result<(), AppError> {
+    let board_lot = BoardLot::for_stock_type(stock_type);
+    if board_lot.is_valid(position) {
+        return Ok(());
+    }
+    let odd_position = board_lot.odd_position(total_position);
+    if action.is_sell()
+        && !odd_position.is_zero()
+        && (position == odd_position || board_lot.is_valid(position - odd_position))
+    {
+        return Ok(());
+    }
+    Err(AppError::BoardLot {
+        position,
+        min_position: board_lot.min_position,
+        step: board_lot.step,
+        odd_position: action.is_sell().then_some(odd_position),
+        suggested_position: suggest_position(&board_lot, action, position, total_position),
+    })
+}
+
+// 建议的数量：不超过position的最大有效数量，没有时取最小的有效数量
+// 减仓的数量须小于持仓，没有合适的数量时为空
+fn suggest_position(
+    board_lot: &BoardLot,
+    action: ActionType,
+    position: Decimal,
+    total_position: Decimal,
+) -> Option<Decimal> {
+    if !action.is_sell() {
+        return Some(board_lot.floor(position).unwrap_or(board_lot.min_position));
+    }
+    let odd_position = board_lot.odd_position(total_position);
+    let mut below = board_lot.floor(position);
+    let mut above = board_lot.min_position;
+    if !odd_position.is_zero() {
+        if odd_position <= position {
+            let with_odd = board_lot
+                .floor(position - odd_position)
+                .map_or(odd_position, |floor| floor + odd_position);
+            below = below.max(Some(with_odd));
+        } else {
+            above = above.min(odd_position);
+        }
+    }
+    Some(below.unwrap_or(above)).filter(|suggested| *suggested < total_position)
+}
+
+/// 校验本次交易：价格、数量大于0，价格符合最小变动单位及涨跌幅限制(传入前收盘价prev_close时)，
+/// 减仓数量小于持仓，数量符合所属板块的整手规则，平仓时须有持仓；
+/// 分红、送转、配股时须有持仓，不校验整手及涨跌幅
+pub fn validate_trade(
+    stock_type: StockType,
+    is_st: bool,
+    prev_close: Option<Decimal>,
+    state: &PositionState,
+    trade: &Trade,
+) -> Result<(), AppError> {
+    validate_price(trade.current_price)?;
+    if trade.action == ActionType::Close {
+        if state.total_position <= Decimal::ZERO {
+            return Err(AppError::InvalidQuantity {
+                position: state.total_position,
+            });
+        }
+        return validate_price_limit(stock_type, is_st, trade.current_price, prev_close);
+    }
+    if trade.action.is_corporate_action() {
+        return validate_corporate_action(state, trade);
+    }
+    validate_price(trade.transaction_price)?;
+    validate_price_limit(stock_type, is_st, trade.transaction_price, prev_close)?;
+    validate_quantity(trade.transaction_position)?;
+    if trade.action == ActionType::ReducePosition
+        && trade.transaction_position >= state.total_position
+    {
+        return Err(AppError::OversellPosition {
+            position: trade.transaction_position,
+            total_position: state.total_position,
+        });
+    }
+    validate_board_lot(
+        stock_type,
+        trade.action,
+        trade.transaction_position,
+        state.total_position,
+    )
+}
+
+// 分红、送转、配股、拆合股：须有持仓，每股派息、配股价格大于0，送转后至少新增1股，配股数量大于0，
+// 拆合股前后的股数大于0，拆合后各批次仍为整数股
+fn validate_corporate_action(state: &PositionState, trade: &Trade) -> Result<(), AppError> {
+    if state.total_position <= Decimal::ZERO {
+        return Err(AppError::InvalidQuantity {
+            position: state.total_position,
+        });
+    }
+    match trade.action {
+        ActionType::CashDividend if trade.transaction_price <= Decimal::ZERO => {
+            Err(AppError::InvalidDividend {
+                cash_per_ten: (trade.transaction_price * Decimal::TEN).normalize(),
+            })
+        }
+        ActionType::BonusShares
+            if (state.total_position * trade.transaction_price).floor() <= Decimal::ZERO =>
+        {
+            Err(AppError::InvalidBonus {
+                shares_per_ten: (trade.transaction_price * Decimal::TEN).normalize(),
+            })
+        }
+        ActionType::CashDividend | ActionType::BonusShares => Ok(()),
+        ActionType::Split => validate_split(state, trade.split_from, trade.split_to),
+        _ => {
+            validate_price(trade.transaction_price)?;
+            validate_quantity(trade.transaction_position)
+        }
+    }
+}
+
+// 拆合股：每split_from股变为split_to股
+fn validate_split(
+    state: &PositionState,
+    split_from: Decimal,
+    split_to: Decimal,
+) -> Result<(), AppError> {
+    validate_quantity(split_from)?;
+    validate_quantity(split_to)?;
+    for lot in &state.lots {
+        let position = split_position(lot.remaining_position, split_from, split_to);
+        if !position.fract().is_zero() {
+            return Err(AppError::SplitFraction {
+                position,
+                split_from,
+                split_to,
+            });
+        }
+    }
+    Ok(())
+}
+
+// 费率须不小于0且小于1(100%)，最低佣金不小于0
+// market_rates依次为印花税、证管费、经手费、过户费，为空时按费率表收取
+pub fn validate_fee_rates(
+    commission_fee_rate: Decimal,
+    market_rates: [Option<Decimal>; 4],
+    min_commission: Decimal,
+) -> Result<(), AppError> {
+    let fields = [
+        "commission_fee_rate",
+        "tax_fee_rate",
+        "regulatory_fee_rate",
+        "brokerage_fee_rate",
+        "transfer_fee_rate",
+    ];
+    let rates = std::iter::once(Some(commission_fee_rate)).chain(market_rates);
+    for (field, rate) in fields.into_iter().zip(rates) {
+        if let Some(rate) = rate.filter(|rate| *rate < Decimal::ZERO || *rate >= Decimal::ONE) {
+            return Err(AppError::InvalidFeeRate {
+                field: field.to_string(),
+                rate,
+            });
+        }
+    }
+    if min_commission < Decimal::ZERO {
+        return Err(AppError::InvalidMinCommission { min_commission });
+    }
+    Ok(())
+}
+
+// T+1：卖出数量不能超过可卖数量，平仓时position为全部持仓
+pub fn validate_sellable(position: Decimal, sellable: &Sellable) -> Result<(), AppError> {
+    if position > sellable.sellable_position {
+        return Err(AppError::PositionFrozen {
+            position,
+            sellable_position: sellable.sellable_position,
+            frozen_position: sellable.frozen_position,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn board_lot_error(
+        stock_type: StockType,
+        action: ActionType,
+        position: Decimal,
+        total_position: Decimal,
+    ) -> (Option<Decimal>, Option<Decimal>) {
+        match validate_board_lot(stock_type, action, position, total_position) {
+            Err(AppError::BoardLot {
+                odd_position,
+                suggested_position,
+                ..
+            }) => (odd_position, suggested_position),
+            other => panic!("expected BoardLot, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn trade_inputs() {
+        let state = PositionState {
+            total_position: dec!(1000),
+            ..PositionState::default()
+        };
+        let trade = |action, price, position| {
+            let trade = Trade::new(action, dec!(10), price, position);
+            validate_trade(StockType::SH, false, None, &state, &trade)
+        };
+        assert!(trade(ActionType::AddPosition, dec!(10), dec!(100)).is_ok());
+        assert_eq!(
+            trade(ActionType::AddPosition, dec!(0), dec!(100)),
+            Err(AppError::InvalidPrice { price: dec!(0) })
+        );
+        assert_eq!(
+            trade(ActionType::AddPosition, dec!(10), dec!(-100)),
+            Err(AppError::InvalidQuantity {
+                position: dec!(-100)
+            })
+        );
+        // 减仓不能卖出全部持仓，全部卖出应平仓
+        assert_eq!(
+            trade(ActionType::ReducePosition, dec!(10), dec!(1000)),
+            Err(AppError::OversellPosition {
+                position: dec!(1000),
+                total_position: dec!(1000),
+            })
+        );
+        let empty = PositionState::default();
+        assert_eq!(
+            validate_trade(
+                StockType::SH,
+                false,
+                None,
+                &empty,
+                &Trade::new(ActionType::Close, dec!(10), dec!(10), Decimal::ZERO),
+            ),
+            Err(AppError::InvalidQuantity { position: dec!(0) })
+        );
+        assert_eq!(
+            validate_status(1, StockStatus::CLOSE),
+            Err(AppError::StockClosed { stock_id: 1 })
+        );
+        assert!(validate_status(1, StockStatus::OPEN).is_ok());
+    }
+
+    #[test]
+    fn buys_must_be_whole_lots() {
+        let buy = ActionType::AddPosition;
+        assert!(validate_board_lot(StockType::SH, buy, dec!(300), dec!(0)).is_ok());
+        assert_eq!(
+            board_lot_error(StockType::SH, buy, dec!(250), dec!(0)),
+            (None, Some(dec!(200)))
+        );
+        // 不足一手时建议最少数量
+        assert_eq!(
+            board_lot_error(StockType::SZ, buy, dec!(50), dec!(0)),
+            (None, Some(dec!(100)))
+        );
+        // 科创板不少于200股，以1股递增
+        assert!(validate_board_lot(StockType::KCB, buy, dec!(201), dec!(0)).is_ok());
+        assert_eq!(
+            board_lot_error(StockType::KCB, buy, dec!(150), dec!(0)),
+            (None, Some(dec!(200)))
+        );
+        // 可转债10张的整数倍
+        assert_eq!(
+            board_lot_error(StockType::KZZ, buy, dec!(25), dec!(0)),
+            (None, Some(dec!(20)))
+        );
+    }
+
+    // 持有1050股，零股50股须一次卖出，可以与整手一起卖出
+    #[test]
+    fn odd_lot_sells_at_once() {
+        let sell = ActionType::ReducePosition;
+        for position in [dec!(50), dec!(100), dec!(150), dec!(1000)] {
+            assert!(validate_board_lot(StockType::SH, sell, position, dec!(1050)).is_ok());
+        }
+        assert_eq!(
+            board_lot_error(StockType::SH, sell, dec!(120), dec!(1050)),
+            (Some(dec!(50)), Some(dec!(100)))
+        );
+        assert_eq!(
+            board_lot_error(StockType::SH, sell, dec!(180), dec!(1050)),
+            (Some(dec!(50)), Some(dec!(150)))
+        );
+        // 零股多于卖出数量时建议卖出全部零股
+        assert_eq!(
+            board_lot_error(StockType::SH, sell, dec!(30), dec!(1050)),
+            (Some(dec!(50)), Some(dec!(50)))
+        );
+        // 没有零股时卖出也须为整手
+        assert_eq!(
+            board_lot_error(StockType::SH, sell, dec!(30), dec!(1000)),
+            (Some(dec!(0)), Some(dec!(100)))
+        );
+    }
+
+    #[test]
+    fn suggested_sell_is_below_total_position() {
+        let board_lot = BoardLot::for_stock_type(StockType::SH);
+        let sell = ActionType::ReducePosition;
+        // 持仓不足一手，全部为零股，减仓没有合适的数量
+        assert_eq!(suggest_position(&board_lot, sell, dec!(30), dec!(50)), None);
+        // 只能卖出整手时，建议的数量须小于持仓
+        assert_eq!(
+            suggest_position(&board_lot, sell, dec!(130), dec!(100)),
+            None
+        );
+        assert_eq!(
+            suggest_position(&board_lot, sell, dec!(130), dec!(150)),
+            Some(dec!(100))
+        );
+        // 买入不受持仓限制
+        assert_eq!(
+            suggest_position(&board_lot, ActionType::AddPosition, dec!(130), dec!(0)),
+            Some(dec!(100))
+        );
+    }
+
+    // 分红、送转按每10股提示，不使用交易价格、数量的错误
+    #[test]
+    fn dividend_and_bonus_ratios() {
+        let state = PositionState {
+            total_position: dec!(1000),
+            ..PositionState::default()
+        };
+        let corporate = |action, per_share| {
+            let trade = Trade::new(action, dec!(10), per_share, Decimal::ZERO);
+            validate_trade(StockType::SH, false, None, &state, &trade)
+        };
+        assert_eq!(
+            corporate(ActionType::CashDividend, dec!(0)),
+            Err(AppError::InvalidDividend {
+                cash_per_ten: dec!(0)
+            })
+        );
+        assert!(corporate(ActionType::CashDividend, dec!(0.3)).is_ok());
+        // 1000股每10股送0.001股不足1股
+        assert_eq!(
+            corporate(ActionType::BonusShares, dec!(0.0001)),
+            Err(AppError::InvalidBonus {
+                shares_per_ten: dec!(0.001)
+            })
+        );
+        assert!(corporate(ActionType::BonusShares, dec!(0.001)).is_ok());
+    }
+
+    #[test]
+    fn fee_rates_in_range() {
+        let none = [None; 4];
+        assert!(validate_fee_rates(dec!(0), none, dec!(0)).is_ok());
+        assert!(validate_fee_rates(
+            dec!(0.00025),
+            [Some(dec!(0.0005)), None, None, None],
+            dec!(5)
+        )
+        .is_ok());
+        assert_eq!(
+            validate_fee_rates(dec!(1), none, dec!(5)),
+            Err(AppError::InvalidFeeRate {
+                field: "commission_fee_rate".to_string(),
+                rate: dec!(1)
+            })
+        );
+        // 未填的交易所费率按费率表收取，不检查
+        assert_eq!(
+            validate_fee_rates(
+                dec!(0.00025),
+                [None, None, Some(dec!(-0.00001)), None],
+                dec!(5)
+            ),
+            Err(AppError::InvalidFeeRate {
+                field: "brokerage_fee_rate".to_string(),
+                rate: dec!(-0.00001)
+            })
+        );
+        assert_eq!(
+            validate_fee_rates(dec!(0.00025), none, dec!(-1)),
+            Err(AppError::InvalidMinCommission {
+                min_commission: dec!(-1)
+            })
+        );
+    }
+}
@@ -1,10 +1,17 @@
+use serde::{Deserialize, Serialize};
+
 // 操作类型
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ActionType {
     Open = 1,           // 建仓
     Close = 2,          // 平仓
     AddPosition = 3,    // 加仓
     ReducePosition = 4, // 减仓
+    CashDividend = 5,   // 现金分红
+    BonusShares = 6,    // 送转股
+    RightsIssue = 7,    // 配股
+    Split = 8,          // 拆股、合股
 }
 
 impl From<i32> for ActionType {
@@ -14,7 +21,40 @@ impl From<i32> for ActionType {
             2 => ActionType::Close,
             3 => ActionType::AddPosition,
             4 => ActionType::ReducePosition,
+            5 => ActionType::CashDividend,
+            6 => ActionType::BonusShares,
+            7 => ActionType::RightsIssue,
+            8 => ActionType::Split,
             _ => ActionType::Open, // 默认值
         }
     }
 }
+
+impl ActionType {
+    // 是否卖出，印花税仅卖出时收取
+    pub fn is_sell(self) -> bool {
+        matches!(self, ActionType::ReducePosition | ActionType::Close)
+    }
+
+    // 是否新增持仓批次，现金分红不增加持仓
+    pub fn adds_lot(self) -> bool {
+        matches!(
+            self,
+            ActionType::Open
+                | ActionType::AddPosition
+                | ActionType::BonusShares
+                | ActionType::RightsIssue
+        )
+    }
+
+    // 是否公司行为(分红、送转、配股、拆合股)，不收取交易费用，不受整手及涨跌幅限制
+    pub fn is_corporate_action(self) -> bool {
+        matches!(
+            self,
+            ActionType::CashDividend
+                | ActionType::BonusShares
+                | ActionType::RightsIssue
+                | ActionType::Split
+        )
+    }
+}
@@ -0,0 +1,61 @@
+// 各板块的申报数量规则
+use crate::constant::stock_type::StockType;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
+
+// 单笔数量须不少于min_position，超出部分为step的整数倍
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BoardLot {
+    pub min_position: Decimal, // 单笔最少数量
+    pub step: Decimal,         // 递增单位
+}
+
+impl BoardLot {
+    pub fn for_stock_type(stock_type: StockType) -> BoardLot {
+        match stock_type {
+            // 科创板：不少于200股，以1股递增
+            StockType::KCB => BoardLot {
+                min_position: dec!(200),
+                step: dec!(1),
+            },
+            // 北交所：不少于100股，以1股递增
+            StockType::BJ => BoardLot {
+                min_position: dec!(100),
+                step: dec!(1),
+            },
+            // 可转债：10张(1手)的整数倍
+            StockType::KZZ => BoardLot {
+                min_position: dec!(10),
+                step: dec!(10),
+            },
+            // 沪深主板、创业板、ETF：100股(1手)的整数倍
+            StockType::SH | StockType::SZ | StockType::CYB | StockType::ETF => BoardLot {
+                min_position: dec!(100),
+                step: dec!(100),
+            },
+        }
+    }
+
+    // 是否为有效的整手数量
+    pub fn is_valid(&self, position: Decimal) -> bool {
+        position >= self.min_position && ((position - self.min_position) % self.step).is_zero()
+    }
+
+    // 不超过position的最大整手数量
+    pub fn floor(&self, position: Decimal) -> Option<Decimal> {
+        if position < self.min_position {
+            return None;
+        }
+        let extra = position - self.min_position;
+        Some(position - extra % self.step)
+    }
+
+    // 持仓中凑不成整手的零股，卖出时须一次卖出
+    pub fn odd_position(&self, total_position: Decimal) -> Decimal {
+        if total_position < self.min_position {
+            return total_position;
+        }
+        (total_position - self.min_position) % self.step
+    }
+}
@@ -1,50 +1,168 @@
-// 费率结构体
-use crate::constant::action_type::ActionType;
+// 交易所及登记结算公司收取的费率表
 use crate::constant::stock_type::StockType;
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde::Serialize;
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub struct FeeRates {
-    pub tax: f64,        // 印花税费率
-    pub regulatory: f64, // 证管费费率
-    pub brokerage: f64,  // 经手费费率
-    pub transfer: f64,   // 过户费费率
+    pub tax: Decimal,        // 印花税费率(仅卖出收取)
+    pub regulatory: Decimal, // 证管费费率
+    pub brokerage: Decimal,  // 经手费费率
+    pub transfer: Decimal,   // 过户费费率
+}
+
+// 某一时间段内适用于若干股票类型的费率，日期格式为 YYYY-MM-DD，valid_to 含当天
+pub struct FeeSchedule {
+    pub stock_types: &'static [StockType],
+    pub valid_from: &'static str,
+    pub valid_to: Option<&'static str>,
+    pub rates: FeeRates,
+}
+
+// 沪深A股(含创业板、科创板)收费标准一致，北交所暂按同一标准，差异可在费率方案中设置
+const A_SHARE: &[StockType] = &[
+    StockType::SH,
+    StockType::SZ,
+    StockType::CYB,
+    StockType::KCB,
+    StockType::BJ,
+];
+
+// 场内基金、可转债不收印花税、过户费，其余费用一般已含在佣金中
+const FUND_BOND: &[StockType] = &[StockType::ETF, StockType::KZZ];
+
+// 新的收费标准生效时，在末尾追加一条，并给上一条补上 valid_to
+pub const FEE_SCHEDULES: &[FeeSchedule] = &[
+    // 2015-08-01 沪深统一经手费、过户费
+    FeeSchedule {
+        stock_types: A_SHARE,
+        valid_from: "2015-08-01",
+        valid_to: Some("2022-04-28"),
+        rates: FeeRates {
+            tax: dec!(0.001),
+            regulatory: dec!(0.00002),
+            brokerage: dec!(0.0000487),
+            transfer: dec!(0.00002),
+        },
+    },
+    // 2022-04-29 过户费下调为0.001%
+    FeeSchedule {
+        stock_types: A_SHARE,
+        valid_from: "2022-04-29",
+        valid_to: Some("2023-08-27"),
+        rates: FeeRates {
+            tax: dec!(0.001),
+            regulatory: dec!(0.00002),
+            brokerage: dec!(0.0000487),
+            transfer: dec!(0.00001),
+        },
+    },
+    // 2023-08-28 印花税减半征收，经手费下调30%
+    FeeSchedule {
+        stock_types: A_SHARE,
+        valid_from: "2023-08-28",
+        valid_to: None,
+        rates: FeeRates {
+            tax: dec!(0.0005),
+            regulatory: dec!(0.00002),
+            brokerage: dec!(0.0000341),
+            transfer: dec!(0.00001),
+        },
+    },
+    // 场内基金、可转债
+    FeeSchedule {
+        stock_types: FUND_BOND,
+        valid_from: "2015-08-01",
+        valid_to: None,
+        rates: FeeRates {
+            tax: dec!(0),
+            regulatory: dec!(0),
+            brokerage: dec!(0),
+            transfer: dec!(0),
+        },
+    },
+];
+
+impl FeeSchedule {
+    fn applies(&self, stock_type: StockType, trade_date: &str) -> bool {
+        self.stock_types.contains(&stock_type)
+            && self.valid_from <= trade_date
+            && self.valid_to.is_none_or(|valid_to| trade_date <= valid_to)
+    }
 }
 
 impl FeeRates {
-    // 为不同股票类型创建费率
-    #[allow(dead_code)]
-    pub fn for_stock_type(stock_type: StockType, action_type: ActionType) -> Self {
-        let tax_rate = match action_type {
-            ActionType::AddPosition => 0.0, // 买入时不收印花税
-            _ => 0.001, // 卖出时收印花税(开仓需要记录税率，先返回，后续判断不计算)
-        };
-
-        match stock_type {
-            StockType::SH => Self {
-                tax: tax_rate,
-                regulatory: 0.00002,
-                brokerage: 0.0000487,
-                transfer: 0.00001,
-            },
-            StockType::SZ => Self {
-                tax: tax_rate,
-                regulatory: 0.00002,
-                brokerage: 0.0000341,
-                transfer: 0.0,
-            },
-            StockType::CYB => Self {
-                tax: tax_rate,
-                regulatory: 0.00002,
-                brokerage: 0.0000487,
-                transfer: 0.00001,
-            },
-            StockType::KCB => Self {
-                tax: tax_rate,
-                regulatory: 0.00002,
-                brokerage: 0.0000487,
-                transfer: 0.00001,
-            },
+    // 按股票类型和交易日期查找适用的费率
+    pub fn for_stock_type(stock_type: StockType, trade_date: NaiveDate) -> Option<Self> {
+        let trade_date = trade_date.format("%Y-%m-%d").to_string();
+        FEE_SCHEDULES
+            .iter()
+            .find(|schedule| schedule.applies(stock_type, &trade_date))
+            .map(|schedule| schedule.rates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(stock_type: StockType, trade_date: &str) -> Option<FeeRates> {
+        FeeRates::for_stock_type(stock_type, trade_date.parse().unwrap())
+    }
+
+    #[test]
+    fn schedule_changes_on_its_dates() {
+        // 过户费下调前后
+        assert_eq!(
+            rates(StockType::SH, "2022-04-28").unwrap().transfer,
+            dec!(0.00002)
+        );
+        assert_eq!(
+            rates(StockType::SH, "2022-04-29").unwrap().transfer,
+            dec!(0.00001)
+        );
+        // 印花税减半、经手费下调前后
+        let before = rates(StockType::SZ, "2023-08-27").unwrap();
+        let after = rates(StockType::SZ, "2023-08-28").unwrap();
+        assert_eq!(
+            (before.tax, before.brokerage),
+            (dec!(0.001), dec!(0.0000487))
+        );
+        assert_eq!(
+            (after.tax, after.brokerage),
+            (dec!(0.0005), dec!(0.0000341))
+        );
+        // 最新的收费标准没有结束日期
+        assert_eq!(rates(StockType::KCB, "2030-01-01"), Some(after));
+    }
+
+    #[test]
+    fn schedule_by_stock_type() {
+        assert_eq!(rates(StockType::KZZ, "2024-03-01").unwrap().tax, dec!(0));
+        // 费率表之前的日期没有适用的费率
+        assert_eq!(rates(StockType::SH, "2015-07-31"), None);
+        assert_eq!(rates(StockType::ETF, "2015-07-31"), None);
+    }
+
+    #[test]
+    fn schedules_do_not_overlap() {
+        // 同一股票类型的收费标准首尾相接，每天只有一条适用
+        for schedule in FEE_SCHEDULES {
+            for stock_type in schedule.stock_types {
+                let next = FEE_SCHEDULES.iter().find(|other| {
+                    other.stock_types.contains(stock_type) && other.valid_from > schedule.valid_from
+                });
+                match (schedule.valid_to, next) {
+                    (Some(valid_to), Some(next)) => {
+                        let valid_to: NaiveDate = valid_to.parse().unwrap();
+                        assert_eq!(valid_to.succ_opt().unwrap().to_string(), next.valid_from);
+                    }
+                    (None, None) => {}
+                    _ => panic!("{} 的收费标准未首尾相接", schedule.valid_from),
+                }
+            }
         }
     }
 }
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+// 卖出时批次的匹配方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LotMethod {
+    Fifo = 1,     // 先进先出
+    Lifo = 2,     // 后进先出
+    Specific = 3, // 指定批次
+}
+
+impl From<i32> for LotMethod {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => LotMethod::Fifo,
+            2 => LotMethod::Lifo,
+            3 => LotMethod::Specific,
+            _ => LotMethod::Fifo, // 默认值
+        }
+    }
+}
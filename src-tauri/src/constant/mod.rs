@@ -1,4 +1,7 @@
 pub mod action_type;
+pub mod board_lot;
 pub mod fee_rate;
+pub mod lot_method;
+pub mod price_limit;
 pub mod stock_status;
 pub mod stock_type;
@@ -0,0 +1,44 @@
+// 各板块的最小价格变动单位及涨跌幅限制
+use crate::calc::money::{FUND_PRICE_TICK, PRICE_TICK};
+use crate::constant::stock_type::StockType;
+use rust_decimal::{Decimal, RoundingStrategy};
+use rust_decimal_macros::dec;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PriceLimit {
+    pub tick: Decimal,       // 最小价格变动单位
+    pub limit_rate: Decimal, // 涨跌幅限制比例
+}
+
+impl PriceLimit {
+    // is_st: 是否ST股票，仅沪深主板的ST股票涨跌幅限制为5%
+    pub fn for_stock_type(stock_type: StockType, is_st: bool) -> PriceLimit {
+        let (tick, limit_rate) = match stock_type {
+            StockType::SH | StockType::SZ if is_st => (PRICE_TICK, dec!(0.05)),
+            StockType::SH | StockType::SZ => (PRICE_TICK, dec!(0.1)),
+            StockType::CYB | StockType::KCB => (PRICE_TICK, dec!(0.2)),
+            StockType::BJ => (PRICE_TICK, dec!(0.3)),
+            StockType::ETF => (FUND_PRICE_TICK, dec!(0.1)),
+            StockType::KZZ => (FUND_PRICE_TICK, dec!(0.2)),
+        };
+        PriceLimit { tick, limit_rate }
+    }
+
+    // 价格是否为最小价格变动单位的整数倍
+    pub fn is_on_tick(&self, price: Decimal) -> bool {
+        (price % self.tick).is_zero()
+    }
+
+    // 按前收盘价计算跌停价、涨停价，四舍五入到最小价格变动单位
+    pub fn band(&self, prev_close: Decimal) -> (Decimal, Decimal) {
+        let to_tick = |price: Decimal| {
+            (price / self.tick).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+                * self.tick
+        };
+        (
+            to_tick(prev_close * (Decimal::ONE - self.limit_rate)),
+            to_tick(prev_close * (Decimal::ONE + self.limit_rate)),
+        )
+    }
+}
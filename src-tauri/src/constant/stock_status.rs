@@ -1,5 +1,6 @@
 // 股票类型
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum StockStatus {
     OPEN = 1,  // 正常买卖中
     CLOSE = 2, // 已经平仓
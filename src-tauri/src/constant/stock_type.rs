@@ -1,9 +1,13 @@
 // 股票类型
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum StockType {
     SH = 1,  // 沪
     SZ = 2,  // 深
     CYB = 3, // 创业板
     KCB = 4, // 科创板
+    BJ = 5,  // 北交所
+    ETF = 6, // 场内基金(ETF)
+    KZZ = 7, // 可转债
 }
 
 impl From<i32> for StockType {
@@ -13,7 +17,17 @@ impl From<i32> for StockType {
             2 => StockType::SZ,
             3 => StockType::CYB,
             4 => StockType::KCB,
+            5 => StockType::BJ,
+            6 => StockType::ETF,
+            7 => StockType::KZZ,
             _ => StockType::SH, // 默认值
         }
     }
 }
+
+impl StockType {
+    // 是否T+0交易(买入当日可卖出)，股票及股票类ETF为T+1
+    pub fn is_t0(self) -> bool {
+        matches!(self, StockType::KZZ)
+    }
+}
@@ -1,153 +1,437 @@
+use crate::database::migration::run_migrations;
+use crate::error::AppError;
 use rusqlite::{Connection, Result};
-use std::sync::{Arc, Mutex, OnceLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 /*************************************数据库状态管理器**************************************/
-// 应用状态管理器，用于共享数据库连接
+// 应用状态管理器，由Tauri托管(app.manage)，命令通过State<DatabaseState>取得数据库
 pub struct DatabaseState {
     pub db: Arc<Mutex<Connection>>,
+    pub db_path: Option<PathBuf>, // 数据库文件路径，内存数据库为空
 }
 
 impl DatabaseState {
-    pub fn new(db_path: &str) -> Result<Self> {
+    pub fn new(db_path: &Path) -> Result<Self> {
         let conn = Connection::open(db_path)?; // 打开数据库
+        Self::init(conn, Some(db_path.to_path_buf()))
+    }
+
+    // 内存数据库(:memory:)，结构与文件数据库相同，关闭后数据即丢弃，用于测试
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?, None)
+    }
 
+    fn init(mut conn: Connection, db_path: Option<PathBuf>) -> Result<Self> {
         // 设置时区为中国时区 (UTC+8)
         conn.execute("PRAGMA timezone = '+08:00'", [])?;
 
-        create_tables(&conn)?; // 创建表
+        run_migrations(&mut conn)?; // 创建表及升级表结构
         Ok(DatabaseState {
             db: Arc::new(Mutex::new(conn)),
+            db_path,
         })
     }
+
     // 获取数据库连接
     pub fn get_connection(&self) -> Arc<Mutex<Connection>> {
         Arc::clone(&self.db)
     }
-}
 
-/*************************************数据库表创建**************************************/
-// 独立的表格创建函数，可以被DatabaseState
-fn create_tables(conn: &Connection) -> Result<()> {
-    conn.execute(
-        "
-         CREATE TABLE IF NOT EXISTS tb_stock_fee (
-            stock_fee_id INTEGER PRIMARY KEY AUTOINCREMENT,       -- ID
-            stock_fee_name TEXT NOT NULL,                         -- 费用名称
-            commission_fee_rate REAL NOT NULL DEFAULT 0.0003, -- 佣金费率 万0.1~万3(最低5元)
-            tax_fee_rate REAL NOT NULL DEFAULT 0.0001,        -- 印花税 0.1% 仅卖出收取
-            regulatory_fee_rate REAL NOT NULL DEFAULT 0.00002, -- 证管费 0.002%
-            brokerage_fee_rate REAL NOT NULL DEFAULT 0.0000487,  -- 经手费 沪市为0.00487% 深市为0.0341‰
-            transfer_fee_rate REAL NOT NULL DEFAULT 0,         -- 过户费 沪市为0.001%(万0.1) 深市为0 
-            created_at DATETIME DEFAULT (datetime('now', 'localtime')),    -- 创建时间
-            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))     -- 更新时间（无法自动更新）
-        );
-        ",
-        [],
-    )?;
-
-    conn.execute(
-        "
-         insert into tb_stock_fee (stock_fee_id, stock_fee_name, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate) values (1,'默认费率', 0.00025, 0.001, 0.00002, 0.0000341, 0);
-        ",
-        [],
-    ).ok(); // 忽略错误，可能已经存在
-
-    conn.execute(
-        "
-         CREATE TABLE IF NOT EXISTS tb_stock (
-            stock_id INTEGER PRIMARY KEY AUTOINCREMENT,       -- ID
-            stock_name TEXT NOT NULL,                         -- 股票名称
-            type INTEGER NOT NULL DEFAULT 1,                  -- 股票类型 1-沪 2-深 3-创业板 4-科创板
-            commission_fee_rate REAL NOT NULL DEFAULT 0.0003, -- 佣金费率 万0.1~万3(最低5元)
-            tax_fee_rate REAL NOT NULL DEFAULT 0.0001,        -- 印花税 0.1% 仅卖出收取
-            regulatory_fee_rate REAL NOT NULL DEFAULT 0.00002, -- 证管费 0.002%
-            brokerage_fee_rate REAL NOT NULL DEFAULT 0.0000487,  -- 经手费 沪市为0.00487% 深市为0.0341‰
-            transfer_fee_rate REAL NOT NULL DEFAULT 0,         -- 过户费 沪市为0.001%(万0.1) 深市为0 
-            status INTEGER NOT NULL DEFAULT 1,                 -- 状态 1-正常买卖中 2-已经平仓
-            sort INTEGER NOT NULL DEFAULT 0,                 -- 排序
-            created_at DATETIME DEFAULT (datetime('now', 'localtime')),    -- 创建时间
-            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))     -- 更新时间（无法自动更新）
-        );
-        ",
-        [],
-    )?;
-
-    conn.execute(
-        "
-        CREATE TABLE IF NOT EXISTS tb_stock_action (
-            stock_action_id INTEGER PRIMARY KEY AUTOINCREMENT,     -- ID
-            stock_id INTEGER NOT NULL,                             -- 股票ID
-            current_price REAL NOT NULL,                           -- 当前价格
-            current_cost REAL NOT NULL DEFAULT 0,                  -- 持仓成本
-            total_position REAL NOT NULL,                            -- 总数量
-            total_fee REAL NOT NULL DEFAULT 0,                     -- 到目前总手续费
-            transaction_price REAL NOT NULL,                       -- 交易价格
-            transaction_position REAL NOT NULL,                      -- 交易数量
-            transaction_commission_fee REAL NOT NULL DEFAULT 0,    -- 交易佣金费
-            transaction_tax_fee REAL NOT NULL DEFAULT 0,           -- 交易印花税
-            transaction_regulatory_fee REAL NOT NULL DEFAULT 0,    -- 交易证管费
-            transaction_brokerage_fee REAL NOT NULL DEFAULT 0,     -- 交易经手费
-            transaction_transfer_fee REAL NOT NULL DEFAULT 0,      -- 交易过户费
-            action INTEGER NOT NULL DEFAULT 1,                     -- 操作类型 1-建仓 2-平仓 3-加仓 4-减仓
-            profit REAL NOT NULL DEFAULT 0,                        -- 盈亏金额(忽略清仓手续费)
-            profit_rate REAL NOT NULL DEFAULT 0,                   -- 盈亏比例(忽略清仓手续费)
-            action_time DATETIME NOT NULL DEFAULT '', -- 操作时间
-            action_info TEXT NOT NULL DEFAULT '',            -- 操作信息
-            created_at DATETIME DEFAULT (datetime('now', 'localtime')),         -- 创建时间
-            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))          -- 更新时间（需应用层更新）
-        );
-        ",
-        [],
-    )?;
-    // 创建索引
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_tb_stock_action_stock_id ON tb_stock_action(stock_id);",
-        [],
-    )?;
-
-    // 创建触发器来自动更新修改时间
-    conn.execute(
-                 "CREATE TRIGGER IF NOT EXISTS update_tb_stock_timestamp 
-         AFTER UPDATE ON tb_stock_fee
-         BEGIN
-            UPDATE tb_stock SET updated_at = datetime('now', 'localtime') WHERE stock_fee_id = NEW.stock_fee_id;
-         END",
-        [],
-    )?;
-
-    // 创建触发器来自动更新修改时间
-    conn.execute(
-                 "CREATE TRIGGER IF NOT EXISTS update_tb_stock_timestamp 
-         AFTER UPDATE ON tb_stock
-         BEGIN
-            UPDATE tb_stock SET updated_at = datetime('now', 'localtime') WHERE stock_id = NEW.stock_id;
-         END",
-        [],
-    )?;
-
-    conn.execute(
-                 "CREATE TRIGGER IF NOT EXISTS update_tb_stock_action_timestamp 
-         AFTER UPDATE ON tb_stock_action
-         BEGIN
-            UPDATE tb_stock_action SET updated_at = datetime('now', 'localtime') WHERE stock_action_id = NEW.stock_action_id;
-         END",
-        [],
-    )?;
-
-    Ok(())
+    // 在一个事务中执行数据库操作，返回Ok时提交，返回Err时回滚
+    // 一个命令的全部读写都应在同一个事务中完成，中途出错不会留下一半的数据
+    pub fn transaction<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let mut conn = self.db.lock().unwrap();
+        let tx = conn.transaction()?;
+        let value = f(&tx)?;
+        tx.commit()?;
+        Ok(value)
+    }
 }
 
-/*************************************全局数据库实例**************************************/
-static DB_INSTANCE: OnceLock<Arc<Mutex<Connection>>> = OnceLock::new();
-
-// 获取公用的数据库状态 - 单例模式
-#[allow(dead_code)]
-pub fn get_db_state() -> Arc<Mutex<Connection>> {
-    DB_INSTANCE
-        .get_or_init(|| {
-            let db_path = "calculator-db.db";
-            let db_state = DatabaseState::new(db_path).unwrap();
-            db_state.get_connection()
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calc::lot::LotSelection;
+    use crate::calc::time::parse_time;
+    use crate::calc::trade_engine::tests::fee_rate;
+    use crate::calc::trade_engine::{Trade, TradeFeeRate};
+    use crate::constant::action_type::ActionType;
+    use crate::constant::lot_method::LotMethod;
+    use crate::constant::stock_status::StockStatus;
+    use crate::database::fee_rate_history::RateOwner;
+    use crate::database::repository::{ActionRepo, FeeRepo, StockRepo};
+    use crate::error::{AppError, Entity};
+    use crate::handler::event::{record_event, redo, undo, StockEvent};
+    use crate::handler::stock::update_stock_fee_rate;
+    use crate::handler::stock_action::{
+        back_position, edit_action, load_position_state, load_sellable, open_position,
+        trade_position,
+    };
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn in_memory_is_migrated() {
+        let db = DatabaseState::in_memory().unwrap();
+        let mut conn = db.db.lock().unwrap();
+        let version: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert!(version > 0);
+        // 已是最新版本，再次迁移不做任何修改
+        run_migrations(&mut conn).unwrap();
+        let again: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(again, version);
+        assert!(conn
+            .get_fee_list()
+            .unwrap()
+            .iter()
+            .any(|fee| fee.is_default));
+    }
+
+    // 建仓、加仓、减仓、平仓写入数据库后，读回的记录与计算引擎的结果一致
+    #[test]
+    fn open_add_reduce_close() {
+        let db = DatabaseState::in_memory().unwrap();
+        let fee_rate = fee_rate();
+        let at = |time: &str| parse_time(time).unwrap();
+        let stock_id = db
+            .transaction(|conn| {
+                let open = Trade::new(ActionType::Open, dec!(10), dec!(10), dec!(1000));
+                let stock_id = open_position(
+                    conn,
+                    "平安银行",
+                    2,
+                    None,
+                    false,
+                    &fee_rate,
+                    &open,
+                    at("2024-03-01 10:00:00"),
+                )?;
+                let add = Trade::new(ActionType::AddPosition, dec!(12), dec!(12), dec!(1000));
+                trade_position(conn, stock_id, &add, at("2024-03-04 10:00:00"))?;
+                let reduce = Trade::new(ActionType::ReducePosition, dec!(13), dec!(13), dec!(500));
+                let (_, sales) =
+                    trade_position(conn, stock_id, &reduce, at("2024-03-05 10:00:00"))?;
+                assert_eq!(sales.len(), 1);
+                assert_eq!(sales[0].buy_price, dec!(10));
+                let close = Trade::new(ActionType::Close, dec!(14), dec!(0), dec!(0));
+                trade_position(conn, stock_id, &close, at("2024-03-06 10:00:00"))?;
+                Ok(stock_id)
+            })
+            .unwrap();
+
+        let conn = db.db.lock().unwrap();
+        let actions = conn.get_actions_by_stock_id(stock_id).unwrap();
+        let fees: Vec<Decimal> = actions.iter().map(|action| action.fee().total()).collect();
+        assert_eq!(fees, vec![dec!(5.64), dec!(5.77), dec!(8.67), dec!(17.10)]);
+        let last = actions.last().unwrap();
+        assert_eq!(last.action, ActionType::Close as i32);
+        assert_eq!(last.transaction_position, dec!(1500));
+        assert_eq!(last.total_position, dec!(0));
+        assert_eq!(last.net_profit_after_fees, dec!(5462.82));
+        let stock = conn.get_stock_by_id(stock_id).unwrap().unwrap();
+        assert_eq!(stock.status, StockStatus::CLOSE as i32);
+        assert!(conn
+            .get_lots_by_stock_id(stock_id)
+            .unwrap()
+            .iter()
+            .all(|lot| lot.remaining_position == Decimal::ZERO));
+    }
+
+    // 回退掉建仓之后再回退，没有可回退的操作
+    #[test]
+    fn back_without_actions_is_not_found() {
+        let db = DatabaseState::in_memory().unwrap();
+        let result = db.transaction(|conn| {
+            let open = Trade::new(ActionType::Open, dec!(10), dec!(10), dec!(1000));
+            let stock_id = open_position(
+                conn,
+                "平安银行",
+                2,
+                None,
+                false,
+                &fee_rate(),
+                &open,
+                parse_time("2024-03-01 10:00:00").unwrap(),
+            )?;
+            back_position(conn, stock_id)?;
+            back_position(conn, stock_id)
+        });
+        assert!(matches!(
+            result,
+            Err(AppError::NotFound {
+                entity: Entity::Action,
+                id: None
+            })
+        ));
+    }
+
+    // 拆股之前的加仓价格修改后重放：拆股按记录的比例折算批次，卖出沿用原来的批次，
+    // 拆股记录的交易价格和数量仍为0
+    #[test]
+    fn replay_across_split() {
+        let db = DatabaseState::in_memory().unwrap();
+        let at = |time: &str| parse_time(time).unwrap();
+        db.transaction(|conn| {
+            let open = Trade::new(ActionType::Open, dec!(10), dec!(10), dec!(900));
+            let stock_id = open_position(
+                conn,
+                "平安银行",
+                2,
+                None,
+                false,
+                &fee_rate(),
+                &open,
+                at("2024-03-01 10:00:00"),
+            )?;
+            let add = Trade::new(ActionType::AddPosition, dec!(12), dec!(12), dec!(300));
+            let (add_id, _) = trade_position(conn, stock_id, &add, at("2024-03-04 10:00:00"))?;
+            let split = Trade::split(dec!(4), dec!(1), dec!(3));
+            trade_position(conn, stock_id, &split, at("2024-03-04 14:00:00"))?;
+            // 当天买入的300股拆股后冻结900股
+            let total_position = load_position_state(conn, stock_id)?.total_position;
+            let stock = conn.get_stock_by_id(stock_id)?.unwrap();
+            let sellable = load_sellable(conn, &stock, total_position, at("2024-03-04 15:00:00"))?;
+            assert_eq!(sellable.frozen_position, dec!(900));
+            assert_eq!(sellable.sellable_position, dec!(2700));
+            let reduce = Trade::new(ActionType::ReducePosition, dec!(4), dec!(4), dec!(1000));
+            trade_position(conn, stock_id, &reduce, at("2024-03-05 10:00:00"))?;
+
+            edit_action(conn, add_id, Some(dec!(15)), None, None, None)?;
+            let actions = conn.get_actions_by_stock_id(stock_id)?;
+            let split = &actions[2];
+            assert_eq!((split.split_from, split.split_to), (dec!(1), dec!(3)));
+            assert_eq!(split.transaction_price, Decimal::ZERO);
+            assert_eq!(split.transaction_position, Decimal::ZERO);
+            assert_eq!(split.current_cost, dec!(3.75));
+            let sell = &actions[3];
+            assert_eq!(sell.total_position, dec!(2600));
+            assert_eq!(sell.realized_profit, dec!(666.67));
+            let lots = load_position_state(conn, stock_id)?.lots;
+            let lots: Vec<(Decimal, Decimal)> = lots
+                .iter()
+                .map(|lot| (lot.remaining_position, lot.buy_price.round_dp(4)))
+                .collect();
+            assert_eq!(lots, vec![(dec!(1700), dec!(3.3333)), (dec!(900), dec!(5))]);
+            Ok(())
         })
-        .clone()
+        .unwrap();
+    }
+
+    // 建仓1000股@10、加仓1000股@12、再按reduce卖出，返回股票ID及加仓的操作ID
+    fn open_add_reduce(conn: &Connection, reduce: Trade) -> Result<(i32, i32), AppError> {
+        let at = |time: &str| parse_time(time).unwrap();
+        let open = Trade::new(ActionType::Open, dec!(10), dec!(10), dec!(1000));
+        let stock_id = open_position(
+            conn,
+            "平安银行",
+            2,
+            None,
+            false,
+            &fee_rate(),
+            &open,
+            at("2024-03-01 10:00:00"),
+        )?;
+        let add = Trade::new(ActionType::AddPosition, dec!(12), dec!(12), dec!(1000));
+        let (add_id, _) = trade_position(conn, stock_id, &add, at("2024-03-04 10:00:00"))?;
+        trade_position(conn, stock_id, &reduce, at("2024-03-05 10:00:00"))?;
+        Ok((stock_id, add_id))
+    }
+
+    // 修改建仓价格后重放：之后的卖出沿用原来的批次，按新的买入价格计算盈亏
+    #[test]
+    fn edit_past_action_replays() {
+        let db = DatabaseState::in_memory().unwrap();
+        db.transaction(|conn| {
+            let reduce = Trade::new(ActionType::ReducePosition, dec!(13), dec!(13), dec!(500));
+            let (stock_id, _) = open_add_reduce(conn, reduce)?;
+            let first = conn.get_actions_by_stock_id(stock_id)?[0].stock_action_id;
+            edit_action(conn, first, Some(dec!(9)), None, None, None)?;
+            let actions = conn.get_actions_by_stock_id(stock_id)?;
+            assert_eq!(actions[0].transaction_price, dec!(9));
+            assert_eq!(actions[1].current_cost, dec!(10.5));
+            assert_eq!(actions[2].realized_profit, dec!(2000));
+            assert_eq!(actions[2].total_position, dec!(1500));
+            let lots: Vec<(i32, Decimal, Decimal)> = load_position_state(conn, stock_id)?
+                .lots
+                .iter()
+                .map(|lot| (lot.stock_lot_id, lot.remaining_position, lot.buy_price))
+                .collect();
+            assert_eq!(
+                lots,
+                vec![(1, dec!(500), dec!(9)), (2, dec!(1000), dec!(12))]
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    // 指定批次卖出后，修改加仓数量使该批次不够卖：重放第3条操作失败，不改用先进先出
+    #[test]
+    fn selected_lot_no_longer_fits() {
+        let db = DatabaseState::in_memory().unwrap();
+        let (stock_id, add_id) = db
+            .transaction(|conn| {
+                let reduce = Trade {
+                    lot_method: LotMethod::Specific,
+                    lot_selections: vec![LotSelection {
+                        stock_lot_id: 2,
+                        position: dec!(800),
+                    }],
+                    ..Trade::new(ActionType::ReducePosition, dec!(13), dec!(13), dec!(800))
+                };
+                open_add_reduce(conn, reduce)
+            })
+            .unwrap();
+        let result = db.transaction(|conn| edit_action(conn, add_id, None, Some(500), None, None));
+        match result {
+            Err(AppError::ActionReplay { index, error }) => {
+                assert_eq!(index, 3);
+                assert_eq!(
+                    *error,
+                    AppError::OversellLot {
+                        stock_lot_id: 2,
+                        position: dec!(800),
+                        remaining_position: dec!(500),
+                    }
+                );
+            }
+            other => panic!("expected ActionReplay, got {other:?}"),
+        }
+        // 整体回滚，加仓数量不变
+        let conn = db.db.lock().unwrap();
+        let actions = conn.get_actions_by_stock_id(stock_id).unwrap();
+        assert_eq!(actions[1].transaction_position, dec!(1000));
+        assert_eq!(actions[2].total_position, dec!(1200));
+    }
+
+    // 撤销、重做修改费率及拆股：撤销后按原费率重算费用，重做后与撤销前一致
+    #[test]
+    fn undo_redo_fee_rate_and_split() {
+        let db = DatabaseState::in_memory().unwrap();
+        let at = |time: &str| parse_time(time).unwrap();
+        let stock_id = db
+            .transaction(|conn| {
+                let open = Trade::new(ActionType::Open, dec!(10), dec!(10), dec!(1000));
+                let open_at = at("2024-03-01 10:00:00");
+                let stock_id = open_position(
+                    conn,
+                    "平安银行",
+                    2,
+                    None,
+                    false,
+                    &fee_rate(),
+                    &open,
+                    open_at,
+                )?;
+                record_event(
+                    conn,
+                    &StockEvent::OpenPosition {
+                        stock_id,
+                        stock_name: "平安银行".to_string(),
+                        stock_type: 2,
+                        stock_fee_id: None,
+                        is_st: false,
+                        fee_rate: fee_rate(),
+                        trade: open,
+                    },
+                    open_at,
+                )?;
+                let trade = |trade: Trade, time: &str| -> Result<(), AppError> {
+                    let (stock_action_id, _) = trade_position(conn, stock_id, &trade, at(time))?;
+                    record_event(
+                        conn,
+                        &StockEvent::Trade {
+                            stock_id,
+                            stock_action_id,
+                            trade,
+                        },
+                        at(time),
+                    )
+                };
+                trade(
+                    Trade::new(ActionType::AddPosition, dec!(12), dec!(12), dec!(1000)),
+                    "2024-03-04 10:00:00",
+                )?;
+                // 佣金费率自加仓之前生效，加仓的佣金由最低5元变为12元
+                let rate = TradeFeeRate {
+                    commission_fee_rate: dec!(0.001),
+                    ..fee_rate()
+                };
+                let valid_from = "2024-03-02 00:00:00";
+                update_stock_fee_rate(conn, stock_id, &rate, at(valid_from))?;
+                record_event(
+                    conn,
+                    &StockEvent::UpdateStockFeeRate {
+                        stock_id,
+                        fee_rate: rate,
+                        valid_from: valid_from.to_string(),
+                    },
+                    at("2024-03-06 10:00:00"),
+                )?;
+                trade(
+                    Trade::split(dec!(6), dec!(1), dec!(2)),
+                    "2024-03-07 10:00:00",
+                )?;
+                Ok(stock_id)
+            })
+            .unwrap();
+        let commissions = |conn: &Connection| -> Vec<Decimal> {
+            conn.get_actions_by_stock_id(stock_id)
+                .unwrap()
+                .iter()
+                .map(|action| action.transaction_commission_fee)
+                .collect()
+        };
+        let history_len = |conn: &Connection| {
+            conn.get_rate_history(RateOwner::Stock(stock_id))
+                .unwrap()
+                .len()
+        };
+        let (before, before_history) = {
+            let conn = db.db.lock().unwrap();
+            (commissions(&conn), history_len(&conn))
+        };
+        assert_eq!(before, vec![dec!(5), dec!(12), dec!(0)]);
+        // 开仓时的费率及新费率
+        assert_eq!(before_history, 2);
+
+        assert_eq!(db.transaction(|conn| undo(conn, 1)).unwrap(), 1);
+        {
+            let conn = db.db.lock().unwrap();
+            assert_eq!(commissions(&conn), vec![dec!(5), dec!(12)]);
+            assert_eq!(
+                load_position_state(&*conn, stock_id)
+                    .unwrap()
+                    .total_position,
+                dec!(2000)
+            );
+        }
+        assert_eq!(db.transaction(|conn| undo(conn, 1)).unwrap(), 1);
+        {
+            let conn = db.db.lock().unwrap();
+            assert_eq!(commissions(&conn), vec![dec!(5), dec!(5)]);
+            assert_eq!(history_len(&conn), 0);
+        }
+
+        assert_eq!(db.transaction(|conn| redo(conn, 10)).unwrap(), 2);
+        let conn = db.db.lock().unwrap();
+        assert_eq!(commissions(&conn), before);
+        assert_eq!(history_len(&conn), before_history);
+        let lots: Vec<(Decimal, Decimal)> = load_position_state(&*conn, stock_id)
+            .unwrap()
+            .lots
+            .iter()
+            .map(|lot| (lot.remaining_position, lot.buy_price))
+            .collect();
+        assert_eq!(lots, vec![(dec!(2000), dec!(5)), (dec!(2000), dec!(6))]);
+    }
 }
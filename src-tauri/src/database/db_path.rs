@@ -0,0 +1,167 @@
+use crate::error::AppError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*************************************数据库文件位置**************************************/
+// 数据库文件名
+pub const DB_FILE_NAME: &str = "calculator-db.db";
+// 环境变量，优先级最高
+pub const DB_PATH_ENV: &str = "TRADER_CALCULATOR_DB";
+// 设置文件，保存在应用配置目录
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+// 应用设置
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AppSettings {
+    #[serde(default)]
+    pub db_path: Option<String>, // 自定义数据库路径
+}
+
+// 数据库路径来源
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbPathSource {
+    Env,      // 环境变量
+    Settings, // 设置文件
+    AppData,  // 应用数据目录(默认)
+}
+
+impl AppSettings {
+    // 读取设置，文件不存在或格式错误时使用默认值
+    pub fn load(config_dir: &Path) -> AppSettings {
+        fs::read_to_string(config_dir.join(SETTINGS_FILE_NAME))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), AppError> {
+        fs::create_dir_all(config_dir).map_err(|e| AppError::io("创建配置目录", e))?;
+        let text = serde_json::to_string_pretty(self)?;
+        fs::write(config_dir.join(SETTINGS_FILE_NAME), text)
+            .map_err(|e| AppError::io("保存设置", e))
+    }
+}
+
+// 确定数据库路径：环境变量 > 设置文件 > 应用数据目录
+pub fn resolve_db_path(data_dir: &Path, config_dir: &Path) -> (PathBuf, DbPathSource) {
+    if let Some(db_path) = std::env::var_os(DB_PATH_ENV).filter(|p| !p.is_empty()) {
+        return (PathBuf::from(db_path), DbPathSource::Env);
+    }
+    if let Some(db_path) = AppSettings::load(config_dir)
+        .db_path
+        .filter(|p| !p.trim().is_empty())
+    {
+        return (PathBuf::from(db_path), DbPathSource::Settings);
+    }
+    (data_dir.join(DB_FILE_NAME), DbPathSource::AppData)
+}
+
+// 准备数据库文件：创建目录，首次运行时复制旧位置(工作目录或程序目录)的数据库
+pub fn prepare_db_path(db_path: &Path) -> Result<(), AppError> {
+    if let Some(parent) = db_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| AppError::io("创建数据库目录", e))?;
+    }
+    if db_path.exists() {
+        return Ok(());
+    }
+    if let Some(legacy_path) = find_legacy_db() {
+        fs::copy(&legacy_path, db_path).map_err(|e| AppError::io("迁移旧数据库", e))?;
+    }
+    Ok(())
+}
+
+// 旧版本把数据库建在启动时的工作目录
+fn find_legacy_db() -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Ok(current_dir) = std::env::current_dir() {
+        candidates.push(current_dir.join(DB_FILE_NAME));
+    }
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(exe_dir.join(DB_FILE_NAME));
+    }
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个测试使用单独的临时目录
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("trader-calculator-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn resolve_in_priority_order() {
+        let dir = temp_dir("resolve");
+        let data_dir = dir.join("data");
+        let config_dir = dir.join("config");
+        std::env::remove_var(DB_PATH_ENV);
+
+        let (db_path, source) = resolve_db_path(&data_dir, &config_dir);
+        assert_eq!(db_path, data_dir.join(DB_FILE_NAME));
+        assert!(matches!(source, DbPathSource::AppData));
+
+        // 设置文件中的空路径视为未设置
+        AppSettings {
+            db_path: Some(" ".to_string()),
+        }
+        .save(&config_dir)
+        .unwrap();
+        let (_, source) = resolve_db_path(&data_dir, &config_dir);
+        assert!(matches!(source, DbPathSource::AppData));
+
+        let custom = dir.join("custom.db");
+        AppSettings {
+            db_path: Some(custom.to_string_lossy().to_string()),
+        }
+        .save(&config_dir)
+        .unwrap();
+        let (db_path, source) = resolve_db_path(&data_dir, &config_dir);
+        assert_eq!(db_path, custom);
+        assert!(matches!(source, DbPathSource::Settings));
+
+        // 环境变量优先于设置文件
+        let env_path = dir.join("env.db");
+        std::env::set_var(DB_PATH_ENV, &env_path);
+        let (db_path, source) = resolve_db_path(&data_dir, &config_dir);
+        std::env::remove_var(DB_PATH_ENV);
+        assert_eq!(db_path, env_path);
+        assert!(matches!(source, DbPathSource::Env));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn broken_settings_use_default() {
+        let config_dir = temp_dir("settings");
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(config_dir.join(SETTINGS_FILE_NAME), "{").unwrap();
+        assert!(AppSettings::load(&config_dir).db_path.is_none());
+        let _ = fs::remove_dir_all(&config_dir);
+    }
+
+    #[test]
+    fn prepare_creates_parent_dir() {
+        let dir = temp_dir("prepare");
+        let db_path = dir.join("nested").join(DB_FILE_NAME);
+        fs::create_dir_all(&dir).unwrap();
+        // 已有数据库时不复制旧位置的数据库
+        fs::create_dir_all(db_path.parent().unwrap()).unwrap();
+        fs::write(&db_path, "existing").unwrap();
+        prepare_db_path(&db_path).unwrap();
+        assert_eq!(fs::read_to_string(&db_path).unwrap(), "existing");
+        fs::remove_dir_all(db_path.parent().unwrap()).unwrap();
+        prepare_db_path(&db_path).unwrap();
+        assert!(db_path.parent().unwrap().is_dir());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,80 @@
+use rusqlite::types::{FromSqlError, ValueRef};
+use rusqlite::Row;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+// 读取金额字段，金额以TEXT保存，兼容旧库中的REAL/INTEGER值
+pub fn get_decimal(row: &Row, idx: usize) -> rusqlite::Result<Decimal> {
+    match row.get_ref(idx)? {
+        ValueRef::Null => Ok(Decimal::ZERO),
+        ValueRef::Integer(value) => Ok(Decimal::from(value)),
+        ValueRef::Real(value) => Decimal::from_f64(value).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                idx,
+                rusqlite::types::Type::Real,
+                Box::new(FromSqlError::InvalidType),
+            )
+        }),
+        ValueRef::Text(text) => {
+            // 旧库REAL转TEXT时较小的值会保存为科学计数法，如 3.41e-05
+            let text = String::from_utf8_lossy(text);
+            let text = text.trim();
+            Decimal::from_str(text)
+                .or_else(|_| Decimal::from_scientific(text))
+                .map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        idx,
+                        rusqlite::types::Type::Text,
+                        Box::new(e),
+                    )
+                })
+        }
+        ValueRef::Blob(_) => Err(rusqlite::Error::InvalidColumnType(
+            idx,
+            "decimal".to_string(),
+            rusqlite::types::Type::Blob,
+        )),
+    }
+}
+
+// 读取可空的费率字段，NULL 表示未设置
+pub fn get_optional_decimal(row: &Row, idx: usize) -> rusqlite::Result<Option<Decimal>> {
+    match row.get_ref(idx)? {
+        ValueRef::Null => Ok(None),
+        _ => get_decimal(row, idx).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use rust_decimal_macros::dec;
+
+    fn read(sql: &str) -> rusqlite::Result<Decimal> {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.query_row(sql, [], |row| get_decimal(row, 0))
+    }
+
+    #[test]
+    fn reads_text_real_and_integer() {
+        assert_eq!(read("SELECT '10.25'").unwrap(), dec!(10.25));
+        assert_eq!(read("SELECT ' 3 '").unwrap(), dec!(3));
+        assert_eq!(read("SELECT 1000").unwrap(), dec!(1000));
+        assert_eq!(read("SELECT 0.5").unwrap(), dec!(0.5));
+        assert_eq!(read("SELECT NULL").unwrap(), Decimal::ZERO);
+        assert!(read("SELECT 'abc'").is_err());
+    }
+
+    // 旧库REAL转TEXT留下的科学计数法
+    #[test]
+    fn reads_scientific_notation() {
+        assert_eq!(read("SELECT '3.41e-05'").unwrap(), dec!(0.0000341));
+        assert_eq!(read("SELECT '1E-5'").unwrap(), dec!(0.00001));
+        assert_eq!(
+            read("SELECT CAST(0.0000341 AS TEXT)").unwrap(),
+            dec!(0.0000341)
+        );
+    }
+}
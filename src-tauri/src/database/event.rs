@@ -0,0 +1,184 @@
+use crate::database::fee_rate_history::BEGINNING;
+use rusqlite::{params, Connection, Params, Row};
+use serde::Serialize;
+
+// 初始快照事件：事件表建立之前已有的数据，保存在tb_snapshot_*表中
+pub const SNAPSHOT_EVENT: &str = "Snapshot";
+
+// 由事件重放得到的表
+pub const PROJECTION_TABLES: [&str; 4] = [
+    "tb_stock",
+    "tb_stock_action",
+    "tb_stock_lot",
+    "tb_stock_lot_sale",
+];
+
+// 初始快照表名，如tb_stock的快照为tb_snapshot_stock
+pub fn snapshot_table(table: &str) -> String {
+    table.replacen("tb_", "tb_snapshot_", 1)
+}
+
+// 操作事件：对股票、操作记录及费率的每次修改按顺序追加，不修改也不删除
+// 撤销只标记undone，撤销后有新的操作时，已撤销的事件不能再重做，才会被删除
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRecord {
+    pub event_id: i32,
+    pub event_type: String,
+    pub payload: String, // 事件内容(JSON)
+    pub undone: bool,    // 是否已撤销
+    pub created_at: String,
+}
+
+const EVENT_SELECT: &str = "SELECT event_id, event_type, payload, undone, created_at FROM tb_event";
+
+impl EventRecord {
+    fn from_row(row: &Row) -> rusqlite::Result<EventRecord> {
+        Ok(EventRecord {
+            event_id: row.get(0)?,
+            event_type: row.get(1)?,
+            payload: row.get(2)?,
+            undone: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+
+    fn query<P: Params>(
+        conn: &Connection,
+        sql: &str,
+        params: P,
+    ) -> Result<Vec<EventRecord>, rusqlite::Error> {
+        let mut stmt = conn.prepare(sql)?;
+        let event_iter = stmt.query_map(params, Self::from_row)?;
+        let mut events = Vec::new();
+        for event in event_iter {
+            events.push(event?);
+        }
+        Ok(events)
+    }
+
+    /// 追加事件，已撤销的事件不能再重做
+    pub fn append(
+        conn: &Connection,
+        event_type: &str,
+        payload: &str,
+        created_at: &str,
+    ) -> Result<i64, rusqlite::Error> {
+        conn.execute("DELETE FROM tb_event WHERE undone = 1", [])?;
+        conn.execute(
+            "INSERT INTO tb_event (event_type, payload, created_at) VALUES (?1, ?2, ?3)",
+            params![event_type, payload, created_at],
+        )?;
+        let event_id = conn.last_insert_rowid();
+        Ok(event_id)
+    }
+
+    /// 全部事件，按发生顺序
+    pub fn get_events(conn: &Connection) -> Result<Vec<EventRecord>, rusqlite::Error> {
+        Self::query(conn, &format!("{EVENT_SELECT} ORDER BY event_id ASC"), [])
+    }
+
+    /// 未撤销的事件，按发生顺序
+    pub fn get_active_events(conn: &Connection) -> Result<Vec<EventRecord>, rusqlite::Error> {
+        Self::query(
+            conn,
+            &format!("{EVENT_SELECT} WHERE undone = 0 ORDER BY event_id ASC"),
+            [],
+        )
+    }
+
+    /// 可撤销的事件(初始快照除外)，从最近的开始
+    pub fn get_undo_events(
+        conn: &Connection,
+        limit: i64,
+    ) -> Result<Vec<EventRecord>, rusqlite::Error> {
+        Self::query(
+            conn,
+            &format!("{EVENT_SELECT} WHERE undone = 0 AND event_type != '{SNAPSHOT_EVENT}' ORDER BY event_id DESC LIMIT ?1"),
+            [limit],
+        )
+    }
+
+    /// 可重做的事件，从最早撤销的开始
+    pub fn get_redo_events(
+        conn: &Connection,
+        limit: i64,
+    ) -> Result<Vec<EventRecord>, rusqlite::Error> {
+        Self::query(
+            conn,
+            &format!("{EVENT_SELECT} WHERE undone = 1 ORDER BY event_id ASC LIMIT ?1"),
+            [limit],
+        )
+    }
+
+    /// 标记撤销或重做
+    pub fn set_undone(
+        conn: &Connection,
+        event_ids: &[i32],
+        undone: bool,
+    ) -> Result<(), rusqlite::Error> {
+        for event_id in event_ids {
+            conn.execute(
+                "UPDATE tb_event SET undone = ?1 WHERE event_id = ?2",
+                params![undone, event_id],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 清空由事件得到的数据，自增ID从头开始，重放后ID与原来一致
+    /// 股票自定义费率历史由修改费率事件重建，股票ID重用时不会带上其他股票的费率
+    /// 费率方案恢复为新建时的费率，之后的修改由修改费率方案事件重建
+    pub fn clear_projection(conn: &Connection) -> Result<(), rusqlite::Error> {
+        for table in PROJECTION_TABLES {
+            conn.execute(&format!("DELETE FROM {table}"), [])?;
+            conn.execute("DELETE FROM sqlite_sequence WHERE name = ?1", [table])?;
+        }
+        conn.execute(
+            "DELETE FROM tb_fee_rate_history WHERE stock_id IS NOT NULL",
+            [],
+        )?;
+        conn.execute(
+            "DELETE FROM tb_fee_rate_history WHERE stock_fee_id IS NOT NULL AND valid_from <> ?1",
+            [BEGINNING],
+        )?;
+        conn.execute(
+            "UPDATE tb_fee_rate_history SET valid_to = NULL WHERE stock_fee_id IS NOT NULL",
+            [],
+        )?;
+        conn.execute(
+            "UPDATE tb_stock_fee SET (commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission) = (SELECT commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission FROM tb_fee_rate_history WHERE stock_fee_id = tb_stock_fee.stock_fee_id AND valid_from = ?1) WHERE stock_fee_id IN (SELECT stock_fee_id FROM tb_fee_rate_history WHERE valid_from = ?1)",
+            [BEGINNING],
+        )?;
+        Ok(())
+    }
+
+    /// 恢复初始快照，包括当时的自增ID
+    pub fn restore_snapshot(conn: &Connection) -> Result<(), rusqlite::Error> {
+        for table in PROJECTION_TABLES {
+            // 快照之后新增的字段取默认值
+            let snapshot = snapshot_table(table);
+            let columns = Self::table_columns(conn, &snapshot)?.join(", ");
+            conn.execute(
+                &format!("INSERT INTO {table} ({columns}) SELECT {columns} FROM {snapshot}"),
+                [],
+            )?;
+            conn.execute("DELETE FROM sqlite_sequence WHERE name = ?1", [table])?;
+        }
+        conn.execute(
+            "INSERT INTO sqlite_sequence (name, seq) SELECT name, seq FROM tb_snapshot_sequence",
+            [],
+        )?;
+        Ok(())
+    }
+
+    // 表的字段
+    fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{table}')"))?;
+        let column_iter = stmt.query_map([], |row| row.get(0))?;
+        let mut columns = Vec::new();
+        for column in column_iter {
+            columns.push(column?);
+        }
+        Ok(columns)
+    }
+}
@@ -0,0 +1,220 @@
+use crate::calc::time::format_time;
+use crate::calc::trade_engine::TradeFeeRate;
+use crate::constant::fee_rate::FeeRates;
+use crate::constant::stock_type::StockType;
+use crate::database::decimal::{get_decimal, get_optional_decimal};
+use crate::database::stock::StockRecord;
+use crate::error::AppError;
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, Row};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+// 首条费率的生效时间，表示此前一直有效
+pub const BEGINNING: &str = "1970-01-01 00:00:00";
+
+// 费率历史：费率方案(stock_fee_id)或单只股票自定义费率(stock_id)在某段时间内的取值
+#[derive(Debug, Clone, Serialize)]
+pub struct FeeRateHistoryRecord {
+    pub fee_rate_history_id: i32,
+    pub stock_fee_id: Option<i32>,
+    pub stock_id: Option<i32>,
+    pub commission_fee_rate: Decimal,
+    // 费率方案中为空时按费率表收取，股票自定义费率均有值
+    pub tax_fee_rate: Option<Decimal>,
+    pub regulatory_fee_rate: Option<Decimal>,
+    pub brokerage_fee_rate: Option<Decimal>,
+    pub transfer_fee_rate: Option<Decimal>,
+    pub min_commission: Decimal,
+    pub valid_from: String,       // 生效时间(含)
+    pub valid_to: Option<String>, // 失效时间(不含)，空为至今有效
+    pub created_at: String,
+}
+
+// 费率历史的归属
+#[derive(Debug, Clone, Copy)]
+pub enum RateOwner {
+    Fee(i32),   // 费率方案
+    Stock(i32), // 股票自定义费率
+}
+
+impl RateOwner {
+    fn column(&self) -> (&'static str, i32) {
+        match *self {
+            RateOwner::Fee(stock_fee_id) => ("stock_fee_id", stock_fee_id),
+            RateOwner::Stock(stock_id) => ("stock_id", stock_id),
+        }
+    }
+}
+
+const FEE_RATE_HISTORY_SELECT: &str = "SELECT fee_rate_history_id, stock_fee_id, stock_id, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission, valid_from, valid_to, created_at FROM tb_fee_rate_history";
+
+impl FeeRateHistoryRecord {
+    fn from_row(row: &Row) -> rusqlite::Result<FeeRateHistoryRecord> {
+        Ok(FeeRateHistoryRecord {
+            fee_rate_history_id: row.get(0)?,
+            stock_fee_id: row.get(1)?,
+            stock_id: row.get(2)?,
+            commission_fee_rate: get_decimal(row, 3)?,
+            tax_fee_rate: get_optional_decimal(row, 4)?,
+            regulatory_fee_rate: get_optional_decimal(row, 5)?,
+            brokerage_fee_rate: get_optional_decimal(row, 6)?,
+            transfer_fee_rate: get_optional_decimal(row, 7)?,
+            min_commission: get_decimal(row, 8)?,
+            valid_from: row.get(9)?,
+            valid_to: row.get(10)?,
+            created_at: row.get(11)?,
+        })
+    }
+
+    /// 以费率表为基础叠加本条费率
+    pub fn fee_rate(&self, market: &FeeRates) -> TradeFeeRate {
+        TradeFeeRate {
+            commission_fee_rate: self.commission_fee_rate,
+            tax_fee_rate: self.tax_fee_rate.unwrap_or(market.tax),
+            regulatory_fee_rate: self.regulatory_fee_rate.unwrap_or(market.regulatory),
+            brokerage_fee_rate: self.brokerage_fee_rate.unwrap_or(market.brokerage),
+            transfer_fee_rate: self.transfer_fee_rate.unwrap_or(market.transfer),
+            min_commission: self.min_commission,
+        }
+    }
+
+    /// 记录费率变更：结束当前生效的费率，新费率自valid_from起生效
+    /// market_rates依次为印花税、证管费、经手费、过户费
+    pub fn record_change(
+        conn: &Connection,
+        owner: RateOwner,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+        valid_from: NaiveDateTime,
+    ) -> Result<i64, AppError> {
+        let (column, owner_id) = owner.column();
+        let valid_from = format_time(valid_from);
+        let current_from: Option<String> = conn.query_row(
+            &format!("SELECT MAX(valid_from) FROM tb_fee_rate_history WHERE {column} = ?1"),
+            [owner_id],
+            |row| row.get(0),
+        )?;
+        if let Some(current_from) = current_from.filter(|current_from| valid_from <= *current_from)
+        {
+            return Err(AppError::ValidFromTooEarly {
+                valid_from,
+                current_from,
+            });
+        }
+        conn.execute(
+            &format!("UPDATE tb_fee_rate_history SET valid_to = ?1 WHERE {column} = ?2 AND valid_to IS NULL"),
+            params![valid_from, owner_id],
+        )?;
+        let fee_rate_history_id = Self::insert(
+            conn,
+            owner,
+            commission_fee_rate,
+            market_rates,
+            min_commission,
+            &valid_from,
+        )?;
+        Ok(fee_rate_history_id)
+    }
+
+    /// 插入一条费率历史，market_rates依次为印花税、证管费、经手费、过户费
+    pub fn insert(
+        conn: &Connection,
+        owner: RateOwner,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+        valid_from: &str,
+    ) -> Result<i64, rusqlite::Error> {
+        let (column, owner_id) = owner.column();
+        let [tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate] =
+            market_rates.map(|rate| rate.map(|rate| rate.to_string()));
+        conn.execute(
+            &format!("INSERT INTO tb_fee_rate_history ({column}, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission, valid_from) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"),
+            params![
+                owner_id,
+                commission_fee_rate.to_string(),
+                tax_fee_rate,
+                regulatory_fee_rate,
+                brokerage_fee_rate,
+                transfer_fee_rate,
+                min_commission.to_string(),
+                valid_from,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// 查询某一时间生效的费率
+    pub fn get_rate_at(
+        conn: &Connection,
+        owner: RateOwner,
+        at: NaiveDateTime,
+    ) -> Result<Option<FeeRateHistoryRecord>, rusqlite::Error> {
+        let (column, owner_id) = owner.column();
+        let mut stmt = conn.prepare(&format!(
+            "{FEE_RATE_HISTORY_SELECT} WHERE {column} = ?1 AND valid_from <= ?2 AND (valid_to IS NULL OR valid_to > ?2) ORDER BY valid_from DESC LIMIT 1"
+        ))?;
+        let mut rows = stmt.query_map(params![owner_id, format_time(at)], Self::from_row)?;
+        match rows.next() {
+            Some(record) => Ok(Some(record?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 查询费率历史，按生效时间排序
+    pub fn get_history(
+        conn: &Connection,
+        owner: RateOwner,
+    ) -> Result<Vec<FeeRateHistoryRecord>, rusqlite::Error> {
+        let (column, owner_id) = owner.column();
+        let mut stmt = conn.prepare(&format!(
+            "{FEE_RATE_HISTORY_SELECT} WHERE {column} = ?1 ORDER BY valid_from ASC"
+        ))?;
+        let history_iter = stmt.query_map([owner_id], Self::from_row)?;
+        let mut history = Vec::new();
+        for record in history_iter {
+            history.push(record?);
+        }
+        Ok(history)
+    }
+
+    /// 删除费率历史
+    pub fn delete_history(conn: &Connection, owner: RateOwner) -> Result<(), rusqlite::Error> {
+        let (column, owner_id) = owner.column();
+        conn.execute(
+            &format!("DELETE FROM tb_fee_rate_history WHERE {column} = ?1"),
+            [owner_id],
+        )?;
+        Ok(())
+    }
+
+    /// 股票在某一时间适用的费率：
+    /// 股票自定义费率 > 开仓所用费率方案(叠加当日费率表) > 股票开仓时记录的费率
+    pub fn resolve(
+        conn: &Connection,
+        stock: &StockRecord,
+        at: NaiveDateTime,
+    ) -> Result<TradeFeeRate, rusqlite::Error> {
+        let snapshot = stock.fee_rate();
+        if let Some(record) = Self::get_rate_at(conn, RateOwner::Stock(stock.stock_id), at)? {
+            let snapshot_rates = FeeRates {
+                tax: snapshot.tax_fee_rate,
+                regulatory: snapshot.regulatory_fee_rate,
+                brokerage: snapshot.brokerage_fee_rate,
+                transfer: snapshot.transfer_fee_rate,
+            };
+            return Ok(record.fee_rate(&snapshot_rates));
+        }
+        if let Some(stock_fee_id) = stock.stock_fee_id {
+            if let (Some(record), Some(market)) = (
+                Self::get_rate_at(conn, RateOwner::Fee(stock_fee_id), at)?,
+                FeeRates::for_stock_type(StockType::from(stock.stock_type), at.date()),
+            ) {
+                return Ok(record.fee_rate(&market));
+            }
+        }
+        Ok(snapshot)
+    }
+}
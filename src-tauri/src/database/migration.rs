@@ -0,0 +1,836 @@
+use crate::calc::money::round_fee;
+use crate::database::decimal::get_decimal;
+use crate::database::event::{snapshot_table, SNAPSHOT_EVENT};
+use crate::database::fee_rate_history::BEGINNING;
+use rusqlite::{params, Connection, Result};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/*************************************数据库迁移**************************************/
+// 迁移步骤，按版本号顺序执行，当前版本记录在 PRAGMA user_version
+// 已发布的步骤不能修改，表结构变化只能追加新的步骤
+struct Migration {
+    version: i32,
+    up: fn(&Connection) -> Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    // 创建基础表
+    Migration {
+        version: 1,
+        up: create_tables,
+    },
+    // 金额字段由REAL改为TEXT
+    Migration {
+        version: 2,
+        up: convert_decimal_columns,
+    },
+    // 修复更新时间触发器
+    Migration {
+        version: 3,
+        up: fix_timestamp_triggers,
+    },
+    // 新增买入批次表
+    Migration {
+        version: 4,
+        up: create_lot_tables,
+    },
+    // 操作记录区分已实现、浮动盈亏
+    Migration {
+        version: 5,
+        up: add_profit_columns,
+    },
+    // 费率新增最低佣金
+    Migration {
+        version: 6,
+        up: add_min_commission_columns,
+    },
+    // 支持多个费率方案
+    Migration {
+        version: 7,
+        up: add_fee_profile_columns,
+    },
+    // 费率方案的交易所费率改为可空，按费率表收取
+    Migration {
+        version: 8,
+        up: make_market_rates_optional,
+    },
+    // 新增费率历史表
+    Migration {
+        version: 9,
+        up: create_fee_rate_history,
+    },
+    // 新增操作事件表，现有数据作为初始快照
+    Migration {
+        version: 10,
+        up: create_event_log,
+    },
+    // 股票新增ST标记
+    Migration {
+        version: 11,
+        up: add_st_column,
+    },
+    // 新增交易日历表
+    Migration {
+        version: 12,
+        up: create_trading_calendar,
+    },
+    // 操作记录新增红利税
+    Migration {
+        version: 13,
+        up: add_dividend_tax_column,
+    },
+    // 操作记录新增拆合股比例
+    Migration {
+        version: 14,
+        up: add_split_columns,
+    },
+];
+
+// 执行所有未执行的迁移，每个步骤在独立事务中完成
+pub fn run_migrations(conn: &mut Connection) -> Result<()> {
+    let current_version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current_version) {
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/*************************************v1 创建基础表**************************************/
+// v1表结构，金额、费率、数量均以TEXT保存十进制字符串，避免浮点误差
+const TB_STOCK_FEE_COLUMNS: &str = "
+            stock_fee_id INTEGER PRIMARY KEY AUTOINCREMENT,       -- ID
+            stock_fee_name TEXT NOT NULL,                         -- 费用名称
+            commission_fee_rate TEXT NOT NULL DEFAULT '0.0003', -- 佣金费率 万0.1~万3(最低5元)
+            tax_fee_rate TEXT NOT NULL DEFAULT '0.0001',        -- 印花税 0.1% 仅卖出收取
+            regulatory_fee_rate TEXT NOT NULL DEFAULT '0.00002', -- 证管费 0.002%
+            brokerage_fee_rate TEXT NOT NULL DEFAULT '0.0000487',  -- 经手费 沪市为0.00487% 深市为0.0341‰
+            transfer_fee_rate TEXT NOT NULL DEFAULT '0',         -- 过户费 沪市为0.001%(万0.1) 深市为0 
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),    -- 创建时间
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))     -- 更新时间（无法自动更新）
+";
+
+const TB_STOCK_COLUMNS: &str = "
+            stock_id INTEGER PRIMARY KEY AUTOINCREMENT,       -- ID
+            stock_name TEXT NOT NULL,                         -- 股票名称
+            type INTEGER NOT NULL DEFAULT 1,                  -- 股票类型 1-沪 2-深 3-创业板 4-科创板
+            commission_fee_rate TEXT NOT NULL DEFAULT '0.0003', -- 佣金费率 万0.1~万3(最低5元)
+            tax_fee_rate TEXT NOT NULL DEFAULT '0.0001',        -- 印花税 0.1% 仅卖出收取
+            regulatory_fee_rate TEXT NOT NULL DEFAULT '0.00002', -- 证管费 0.002%
+            brokerage_fee_rate TEXT NOT NULL DEFAULT '0.0000487',  -- 经手费 沪市为0.00487% 深市为0.0341‰
+            transfer_fee_rate TEXT NOT NULL DEFAULT '0',         -- 过户费 沪市为0.001%(万0.1) 深市为0 
+            status INTEGER NOT NULL DEFAULT 1,                 -- 状态 1-正常买卖中 2-已经平仓
+            sort INTEGER NOT NULL DEFAULT 0,                 -- 排序
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),    -- 创建时间
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))     -- 更新时间（无法自动更新）
+";
+
+const TB_STOCK_ACTION_COLUMNS: &str = "
+            stock_action_id INTEGER PRIMARY KEY AUTOINCREMENT,     -- ID
+            stock_id INTEGER NOT NULL,                             -- 股票ID
+            current_price TEXT NOT NULL,                           -- 当前价格
+            current_cost TEXT NOT NULL DEFAULT '0',                -- 持仓成本
+            total_position TEXT NOT NULL,                          -- 总数量
+            total_fee TEXT NOT NULL DEFAULT '0',                   -- 到目前总手续费
+            transaction_price TEXT NOT NULL,                       -- 交易价格
+            transaction_position TEXT NOT NULL,                    -- 交易数量
+            transaction_commission_fee TEXT NOT NULL DEFAULT '0',  -- 交易佣金费
+            transaction_tax_fee TEXT NOT NULL DEFAULT '0',         -- 交易印花税
+            transaction_regulatory_fee TEXT NOT NULL DEFAULT '0',  -- 交易证管费
+            transaction_brokerage_fee TEXT NOT NULL DEFAULT '0',   -- 交易经手费
+            transaction_transfer_fee TEXT NOT NULL DEFAULT '0',    -- 交易过户费
+            action INTEGER NOT NULL DEFAULT 1,                     -- 操作类型 1-建仓 2-平仓 3-加仓 4-减仓
+            profit TEXT NOT NULL DEFAULT '0',                      -- 盈亏金额(忽略清仓手续费)
+            profit_rate TEXT NOT NULL DEFAULT '0',                 -- 盈亏比例(忽略清仓手续费)
+            action_time DATETIME NOT NULL DEFAULT '', -- 操作时间
+            action_info TEXT NOT NULL DEFAULT '',            -- 操作信息
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),         -- 创建时间
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))          -- 更新时间（需应用层更新）
+";
+
+fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS tb_stock_fee ({TB_STOCK_FEE_COLUMNS});"),
+        [],
+    )?;
+
+    conn.execute(
+        "
+         INSERT OR IGNORE INTO tb_stock_fee (stock_fee_id, stock_fee_name, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate) values (1,'默认费率', '0.00025', '0.001', '0.00002', '0.0000341', '0');
+        ",
+        [],
+    )?;
+
+    conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS tb_stock ({TB_STOCK_COLUMNS});"),
+        [],
+    )?;
+
+    conn.execute(
+        &format!("CREATE TABLE IF NOT EXISTS tb_stock_action ({TB_STOCK_ACTION_COLUMNS});"),
+        [],
+    )?;
+
+    // 创建索引
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tb_stock_action_stock_id ON tb_stock_action(stock_id);",
+        [],
+    )?;
+    Ok(())
+}
+
+/*************************************v2 金额字段改为TEXT**************************************/
+// 旧版本的金额字段为REAL，转换为TEXT
+fn convert_decimal_columns(conn: &Connection) -> Result<()> {
+    // 旧触发器可能引用被重建的表，重命名时不检查触发器
+    conn.pragma_update(None, "legacy_alter_table", true)?;
+    convert_real_columns(conn, "tb_stock_fee", TB_STOCK_FEE_COLUMNS)?;
+    convert_real_columns(conn, "tb_stock", TB_STOCK_COLUMNS)?;
+    convert_real_columns(conn, "tb_stock_action", TB_STOCK_ACTION_COLUMNS)?;
+    conn.pragma_update(None, "legacy_alter_table", false)?;
+    // 重建表时索引随旧表删除
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_tb_stock_action_stock_id ON tb_stock_action(stock_id);",
+        [],
+    )?;
+    Ok(())
+}
+
+// 重建含有REAL字段的表：新建表 -> 复制数据 -> 删除旧表 -> 重命名
+// REAL值写入TEXT字段时由SQLite转换为十进制字符串
+fn convert_real_columns(conn: &Connection, table: &str, columns: &str) -> Result<()> {
+    let mut column_names = Vec::new();
+    let mut has_real = false;
+    {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(1)?;
+            let column_type: String = row.get(2)?;
+            has_real |= column_type.eq_ignore_ascii_case("REAL");
+            column_names.push(name);
+        }
+    }
+    if !has_real {
+        return Ok(());
+    }
+    let column_names = column_names.join(", ");
+    conn.execute_batch(&format!(
+        "
+        CREATE TABLE {table}_new ({columns});
+        INSERT INTO {table}_new ({column_names}) SELECT {column_names} FROM {table};
+        DROP TABLE {table};
+        ALTER TABLE {table}_new RENAME TO {table};
+        "
+    ))?;
+    Ok(())
+}
+
+/*************************************v3 修复更新时间触发器**************************************/
+// 旧版本中 update_tb_stock_timestamp 声明了两次，先创建的一条挂在 tb_stock_fee 上且更新 tb_stock，
+// 导致修改费率时报错，tb_stock 自身也没有更新时间触发器
+fn fix_timestamp_triggers(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        DROP TRIGGER IF EXISTS update_tb_stock_timestamp;
+        DROP TRIGGER IF EXISTS update_tb_stock_fee_timestamp;
+        DROP TRIGGER IF EXISTS update_tb_stock_action_timestamp;
+
+        CREATE TRIGGER update_tb_stock_fee_timestamp
+        AFTER UPDATE ON tb_stock_fee
+        BEGIN
+            UPDATE tb_stock_fee SET updated_at = datetime('now', 'localtime') WHERE stock_fee_id = NEW.stock_fee_id;
+        END;
+
+        CREATE TRIGGER update_tb_stock_timestamp
+        AFTER UPDATE ON tb_stock
+        BEGIN
+            UPDATE tb_stock SET updated_at = datetime('now', 'localtime') WHERE stock_id = NEW.stock_id;
+        END;
+
+        CREATE TRIGGER update_tb_stock_action_timestamp
+        AFTER UPDATE ON tb_stock_action
+        BEGIN
+            UPDATE tb_stock_action SET updated_at = datetime('now', 'localtime') WHERE stock_action_id = NEW.stock_action_id;
+        END;
+        ",
+    )?;
+    Ok(())
+}
+
+/*************************************v4 买入批次**************************************/
+fn create_lot_tables(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS tb_stock_lot (
+            stock_lot_id INTEGER PRIMARY KEY AUTOINCREMENT,       -- ID
+            stock_id INTEGER NOT NULL,                            -- 股票ID
+            stock_action_id INTEGER NOT NULL,                     -- 买入操作ID
+            buy_price TEXT NOT NULL,                              -- 买入价格
+            buy_position TEXT NOT NULL,                           -- 买入数量
+            remaining_position TEXT NOT NULL,                     -- 剩余数量
+            buy_fee TEXT NOT NULL DEFAULT '0',                    -- 买入费用
+            source_lot_id INTEGER,                                -- 送转股的原批次ID，买入日期沿用原批次
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),    -- 创建时间
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))     -- 更新时间
+        );
+        CREATE INDEX IF NOT EXISTS idx_tb_stock_lot_stock_id ON tb_stock_lot(stock_id);
+
+        CREATE TABLE IF NOT EXISTS tb_stock_lot_sale (
+            stock_lot_sale_id INTEGER PRIMARY KEY AUTOINCREMENT,  -- ID
+            stock_id INTEGER NOT NULL,                            -- 股票ID
+            stock_action_id INTEGER NOT NULL,                     -- 卖出操作ID
+            stock_lot_id INTEGER NOT NULL,                        -- 批次ID
+            sell_position TEXT NOT NULL,                          -- 卖出数量
+            buy_price TEXT NOT NULL,                              -- 买入价格
+            sell_price TEXT NOT NULL,                             -- 卖出价格
+            realized_profit TEXT NOT NULL DEFAULT '0',            -- 已实现盈亏(未扣费用)
+            created_at DATETIME DEFAULT (datetime('now', 'localtime'))     -- 创建时间
+        );
+        CREATE INDEX IF NOT EXISTS idx_tb_stock_lot_sale_stock_id ON tb_stock_lot_sale(stock_id);
+
+        CREATE TRIGGER IF NOT EXISTS update_tb_stock_lot_timestamp
+        AFTER UPDATE ON tb_stock_lot
+        BEGIN
+            UPDATE tb_stock_lot SET updated_at = datetime('now', 'localtime') WHERE stock_lot_id = NEW.stock_lot_id;
+        END;
+        ",
+    )?;
+    backfill_lots(conn)
+}
+
+// 补齐批次时持有中的批次，迁移步骤只依赖当时的表结构，不引用之后会修改的业务代码
+#[derive(Debug, Clone, Copy)]
+struct BackfillLot {
+    stock_lot_id: i64,
+    buy_price: Decimal,
+    remaining_position: Decimal,
+}
+
+// 已有的操作记录按先进先出补齐批次，操作类型 1-建仓 2-平仓 3-加仓 4-减仓
+fn backfill_lots(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT stock_action_id, stock_id, action, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee FROM tb_stock_action ORDER BY stock_id ASC, stock_action_id ASC",
+    )?;
+    let actions = stmt
+        .query_map([], |row| {
+            let mut fee = Decimal::ZERO;
+            for idx in 5..10 {
+                fee += get_decimal(row, idx)?;
+            }
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, i32>(1)?,
+                row.get::<_, i32>(2)?,
+                get_decimal(row, 3)?,
+                get_decimal(row, 4)?,
+                fee,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut open_lots: HashMap<i32, Vec<BackfillLot>> = HashMap::new();
+    for (stock_action_id, stock_id, action, price, position, fee) in actions {
+        let lots = open_lots.entry(stock_id).or_default();
+        match action {
+            1 | 3 => {
+                conn.execute(
+                    "INSERT INTO tb_stock_lot (stock_id, stock_action_id, buy_price, buy_position, remaining_position, buy_fee) VALUES (?1, ?2, ?3, ?4, ?4, ?5)",
+                    params![stock_id, stock_action_id, price.to_string(), position.to_string(), fee.to_string()],
+                )?;
+                lots.push(BackfillLot {
+                    stock_lot_id: conn.last_insert_rowid(),
+                    buy_price: price,
+                    remaining_position: position,
+                });
+            }
+            2 | 4 => {
+                // 历史数据不一致(剩余批次不足)时跳过，不影响原有记录
+                let available: Decimal = lots.iter().map(|lot| lot.remaining_position).sum();
+                if position > available {
+                    continue;
+                }
+                let mut left = position;
+                for lot in lots
+                    .iter_mut()
+                    .filter(|lot| lot.remaining_position > Decimal::ZERO)
+                {
+                    if left <= Decimal::ZERO {
+                        break;
+                    }
+                    let sell_position = lot.remaining_position.min(left);
+                    lot.remaining_position -= sell_position;
+                    left -= sell_position;
+                    conn.execute(
+                        "UPDATE tb_stock_lot SET remaining_position = ?1 WHERE stock_lot_id = ?2",
+                        params![lot.remaining_position.to_string(), lot.stock_lot_id],
+                    )?;
+                    conn.execute(
+                        "INSERT INTO tb_stock_lot_sale (stock_id, stock_action_id, stock_lot_id, sell_position, buy_price, sell_price, realized_profit) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            stock_id,
+                            stock_action_id,
+                            lot.stock_lot_id,
+                            sell_position.to_string(),
+                            lot.buy_price.to_string(),
+                            price.to_string(),
+                            round_fee((price - lot.buy_price) * sell_position).to_string(),
+                        ],
+                    )?;
+                }
+                lots.retain(|lot| lot.remaining_position > Decimal::ZERO);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/*************************************v5 已实现、浮动盈亏**************************************/
+fn add_profit_columns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE tb_stock_action ADD COLUMN realized_profit TEXT NOT NULL DEFAULT '0';        -- 本次已实现盈亏(按批次，未扣费用)
+        ALTER TABLE tb_stock_action ADD COLUMN unrealized_profit TEXT NOT NULL DEFAULT '0';      -- 剩余批次的浮动盈亏
+        ALTER TABLE tb_stock_action ADD COLUMN net_profit_after_fees TEXT NOT NULL DEFAULT '0';  -- 累计已实现 + 浮动 - 累计费用
+        ",
+    )?;
+    backfill_profit_columns(conn)
+}
+
+// 根据批次记录回放已有的操作，补齐盈亏字段
+fn backfill_profit_columns(conn: &Connection) -> Result<()> {
+    let mut lot_by_action: HashMap<i32, BackfillLot> = HashMap::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT stock_lot_id, stock_action_id, buy_price, buy_position FROM tb_stock_lot",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            lot_by_action.insert(
+                row.get(1)?,
+                BackfillLot {
+                    stock_lot_id: row.get(0)?,
+                    buy_price: get_decimal(row, 2)?,
+                    remaining_position: get_decimal(row, 3)?,
+                },
+            );
+        }
+    }
+    // 每次卖出消耗的批次：(批次ID, 卖出数量, 已实现盈亏)
+    let mut sales_by_action: HashMap<i32, Vec<(i64, Decimal, Decimal)>> = HashMap::new();
+    {
+        let mut stmt = conn.prepare(
+            "SELECT stock_action_id, stock_lot_id, sell_position, realized_profit FROM tb_stock_lot_sale ORDER BY stock_lot_sale_id ASC",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            sales_by_action.entry(row.get(0)?).or_default().push((
+                row.get(1)?,
+                get_decimal(row, 2)?,
+                get_decimal(row, 3)?,
+            ));
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT stock_action_id, stock_id, current_price, total_fee FROM tb_stock_action ORDER BY stock_id ASC, stock_action_id ASC",
+    )?;
+    let actions = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, i32>(1)?,
+                get_decimal(row, 2)?,
+                get_decimal(row, 3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut lots: Vec<BackfillLot> = Vec::new();
+    let mut total_realized_profit = Decimal::ZERO;
+    let mut last_stock_id = None;
+    for (stock_action_id, stock_id, current_price, total_fee) in actions {
+        if last_stock_id != Some(stock_id) {
+            lots.clear();
+            total_realized_profit = Decimal::ZERO;
+            last_stock_id = Some(stock_id);
+        }
+        if let Some(lot) = lot_by_action.get(&stock_action_id) {
+            lots.push(*lot);
+        }
+        let sales = sales_by_action.remove(&stock_action_id).unwrap_or_default();
+        for (stock_lot_id, sell_position, _) in &sales {
+            if let Some(lot) = lots
+                .iter_mut()
+                .find(|lot| lot.stock_lot_id == *stock_lot_id)
+            {
+                lot.remaining_position -= *sell_position;
+            }
+        }
+        lots.retain(|lot| lot.remaining_position > Decimal::ZERO);
+        let realized_profit: Decimal = sales.iter().map(|(_, _, profit)| *profit).sum();
+        total_realized_profit += realized_profit;
+        let unrealized_profit = round_fee(
+            lots.iter()
+                .map(|lot| (current_price - lot.buy_price) * lot.remaining_position)
+                .sum(),
+        );
+        let net_profit_after_fees = total_realized_profit + unrealized_profit - total_fee;
+        conn.execute(
+            "UPDATE tb_stock_action SET realized_profit = ?1, unrealized_profit = ?2, net_profit_after_fees = ?3 WHERE stock_action_id = ?4",
+            [
+                realized_profit.to_string(),
+                unrealized_profit.to_string(),
+                net_profit_after_fees.to_string(),
+                stock_action_id.to_string(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/*************************************v6 最低佣金**************************************/
+fn add_min_commission_columns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE tb_stock_fee ADD COLUMN min_commission TEXT NOT NULL DEFAULT '5';  -- 最低佣金 默认5元 免五为0
+        ALTER TABLE tb_stock ADD COLUMN min_commission TEXT NOT NULL DEFAULT '5';      -- 最低佣金 默认5元 免五为0
+        ",
+    )
+}
+
+/*************************************v7 多费率方案**************************************/
+fn add_fee_profile_columns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE tb_stock_fee ADD COLUMN is_default INTEGER NOT NULL DEFAULT 0;  -- 是否默认费率 1-是 0-否
+        ALTER TABLE tb_stock ADD COLUMN stock_fee_id INTEGER;                        -- 开仓时使用的费率方案
+        UPDATE tb_stock_fee SET is_default = 1
+            WHERE stock_fee_id = (SELECT MIN(stock_fee_id) FROM tb_stock_fee);
+        ",
+    )
+}
+
+/*************************************v8 交易所费率按费率表收取**************************************/
+// 印花税、证管费、经手费、过户费为空时按 FeeRates 费率表收取，有值时作为费率方案的自定义费率
+const TB_STOCK_FEE_V8_COLUMNS: &str = "
+            stock_fee_id INTEGER PRIMARY KEY AUTOINCREMENT,       -- ID
+            stock_fee_name TEXT NOT NULL,                         -- 费用名称
+            commission_fee_rate TEXT NOT NULL DEFAULT '0.0003',   -- 佣金费率 万0.1~万3
+            tax_fee_rate TEXT,                                    -- 印花税 仅卖出收取 空为按费率表
+            regulatory_fee_rate TEXT,                             -- 证管费 空为按费率表
+            brokerage_fee_rate TEXT,                              -- 经手费 空为按费率表
+            transfer_fee_rate TEXT,                               -- 过户费 空为按费率表
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),    -- 创建时间
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime')),    -- 更新时间
+            min_commission TEXT NOT NULL DEFAULT '5',             -- 最低佣金 免五为0
+            is_default INTEGER NOT NULL DEFAULT 0                 -- 是否默认费率 1-是 0-否
+";
+
+fn make_market_rates_optional(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "
+        CREATE TABLE tb_stock_fee_new ({TB_STOCK_FEE_V8_COLUMNS});
+        INSERT INTO tb_stock_fee_new SELECT stock_fee_id, stock_fee_name, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, created_at, updated_at, min_commission, is_default FROM tb_stock_fee;
+        DROP TABLE tb_stock_fee;
+        ALTER TABLE tb_stock_fee_new RENAME TO tb_stock_fee;
+
+        CREATE TRIGGER update_tb_stock_fee_timestamp
+        AFTER UPDATE ON tb_stock_fee
+        BEGIN
+            UPDATE tb_stock_fee SET updated_at = datetime('now', 'localtime') WHERE stock_fee_id = NEW.stock_fee_id;
+        END;
+
+        -- 初始默认费率未修改过的项改为按费率表收取(旧印花税0.001已过期)，旧库中可能为科学计数法，按数值比较
+        UPDATE tb_stock_fee SET tax_fee_rate = NULL WHERE stock_fee_id = 1 AND CAST(tax_fee_rate AS REAL) = 0.001;
+        UPDATE tb_stock_fee SET regulatory_fee_rate = NULL WHERE stock_fee_id = 1 AND CAST(regulatory_fee_rate AS REAL) = 0.00002;
+        UPDATE tb_stock_fee SET brokerage_fee_rate = NULL WHERE stock_fee_id = 1 AND CAST(brokerage_fee_rate AS REAL) = 0.0000341;
+        UPDATE tb_stock_fee SET transfer_fee_rate = NULL WHERE stock_fee_id = 1 AND CAST(transfer_fee_rate AS REAL) = 0;
+        "
+    ))
+}
+
+/*************************************v9 费率历史**************************************/
+fn create_fee_rate_history(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS tb_fee_rate_history (
+            fee_rate_history_id INTEGER PRIMARY KEY AUTOINCREMENT, -- ID
+            stock_fee_id INTEGER,                                  -- 费率方案ID(与stock_id二选一)
+            stock_id INTEGER,                                      -- 股票ID(股票自定义费率)
+            commission_fee_rate TEXT NOT NULL,                     -- 佣金费率
+            tax_fee_rate TEXT,                                     -- 印花税 费率方案中为空时按费率表
+            regulatory_fee_rate TEXT,                              -- 证管费 费率方案中为空时按费率表
+            brokerage_fee_rate TEXT,                               -- 经手费 费率方案中为空时按费率表
+            transfer_fee_rate TEXT,                                -- 过户费 费率方案中为空时按费率表
+            min_commission TEXT NOT NULL,                          -- 最低佣金
+            valid_from DATETIME NOT NULL,                          -- 生效时间(含)
+            valid_to DATETIME,                                     -- 失效时间(不含) 空为至今有效
+            created_at DATETIME DEFAULT (datetime('now', 'localtime'))  -- 创建时间
+        );
+        CREATE INDEX IF NOT EXISTS idx_fee_rate_history_fee ON tb_fee_rate_history(stock_fee_id, valid_from);
+        CREATE INDEX IF NOT EXISTS idx_fee_rate_history_stock ON tb_fee_rate_history(stock_id, valid_from);
+
+        -- 现有费率方案一直有效
+        INSERT INTO tb_fee_rate_history (stock_fee_id, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission, valid_from)
+            SELECT stock_fee_id, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission, '{BEGINNING}' FROM tb_stock_fee;
+        "
+    ))
+}
+
+/*************************************v10 操作事件**************************************/
+// 股票、操作记录、批次及卖出记录改为由事件重放得到，现有数据复制为初始快照，作为第一个事件
+// 股票自定义费率历史同样由修改费率事件重建，费率方案此时只有新建时的费率，之后的修改由修改费率方案事件重建，不需要快照
+fn create_event_log(conn: &Connection) -> Result<()> {
+    // 该版本由事件重放得到的表，即当时的 PROJECTION_TABLES
+    let projection_tables = [
+        "tb_stock",
+        "tb_stock_action",
+        "tb_stock_lot",
+        "tb_stock_lot_sale",
+    ];
+    for table in projection_tables {
+        let snapshot = snapshot_table(table);
+        conn.execute_batch(&format!(
+            "CREATE TABLE {snapshot} AS SELECT * FROM {table};"
+        ))?;
+    }
+    let tables = projection_tables
+        .map(|table| format!("'{table}'"))
+        .join(", ");
+    conn.execute_batch(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS tb_event (
+            event_id INTEGER PRIMARY KEY AUTOINCREMENT, -- ID
+            event_type TEXT NOT NULL,                   -- 事件类型
+            payload TEXT NOT NULL,                      -- 事件内容(JSON)
+            undone INTEGER NOT NULL DEFAULT 0,          -- 是否已撤销 0-否 1-是
+            created_at DATETIME DEFAULT (datetime('now', 'localtime'))  -- 发生时间
+        );
+        CREATE TABLE tb_snapshot_sequence AS SELECT name, seq FROM sqlite_sequence WHERE name IN ({tables});
+
+        INSERT INTO tb_event (event_type, payload)
+            SELECT '{SNAPSHOT_EVENT}', '{{\"type\":\"{SNAPSHOT_EVENT}\"}}' WHERE EXISTS (SELECT 1 FROM tb_snapshot_sequence);
+        "
+    ))
+}
+
+/*************************************v11 ST标记**************************************/
+// ST股票涨跌幅限制不同，初始快照中的股票取默认值
+fn add_st_column(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE tb_stock ADD COLUMN is_st INTEGER NOT NULL DEFAULT 0;  -- 是否ST 1-是 0-否
+        ",
+    )
+}
+
+/*************************************v12 交易日历**************************************/
+// 导入的休市安排，每年一行，替换内置交易日历中的同一年
+fn create_trading_calendar(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS tb_trading_calendar (
+            year INTEGER PRIMARY KEY,                -- 年份
+            holidays TEXT NOT NULL,                  -- 休市日(JSON数组，不含周末)
+            created_at DATETIME DEFAULT (datetime('now', 'localtime'))  -- 导入时间
+        );
+        ",
+    )
+}
+
+/*************************************v13 红利税**************************************/
+// 卖出时按持股期限补扣的红利税，之前的记录没有分红，取默认值
+fn add_dividend_tax_column(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE tb_stock_action ADD COLUMN transaction_dividend_tax_fee TEXT NOT NULL DEFAULT '0';  -- 红利税
+        ",
+    )
+}
+
+/*************************************v14 拆合股比例**************************************/
+// 拆合股的比例单独记录，交易价格、数量仍为金额和股数，其余操作为1:1
+fn add_split_columns(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "
+        ALTER TABLE tb_stock_action ADD COLUMN split_from TEXT NOT NULL DEFAULT '1';  -- 拆合前股数
+        ALTER TABLE tb_stock_action ADD COLUMN split_to TEXT NOT NULL DEFAULT '1';    -- 拆合后股数
+        ",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    // 迁移之前版本的表结构：金额为REAL，update_tb_stock_timestamp 重复声明且先创建的一条挂在 tb_stock_fee 上
+    const BASELINE_SCHEMA: &str = "
+        CREATE TABLE tb_stock_fee (
+            stock_fee_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            stock_fee_name TEXT NOT NULL,
+            commission_fee_rate REAL NOT NULL DEFAULT 0.0003,
+            tax_fee_rate REAL NOT NULL DEFAULT 0.0001,
+            regulatory_fee_rate REAL NOT NULL DEFAULT 0.00002,
+            brokerage_fee_rate REAL NOT NULL DEFAULT 0.0000487,
+            transfer_fee_rate REAL NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))
+        );
+        INSERT INTO tb_stock_fee (stock_fee_id, stock_fee_name, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate) VALUES (1, '默认费率', 0.00025, 0.001, 0.00002, 0.0000341, 0);
+        CREATE TABLE tb_stock (
+            stock_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            stock_name TEXT NOT NULL,
+            type INTEGER NOT NULL DEFAULT 1,
+            commission_fee_rate REAL NOT NULL DEFAULT 0.0003,
+            tax_fee_rate REAL NOT NULL DEFAULT 0.0001,
+            regulatory_fee_rate REAL NOT NULL DEFAULT 0.00002,
+            brokerage_fee_rate REAL NOT NULL DEFAULT 0.0000487,
+            transfer_fee_rate REAL NOT NULL DEFAULT 0,
+            status INTEGER NOT NULL DEFAULT 1,
+            sort INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))
+        );
+        CREATE TABLE tb_stock_action (
+            stock_action_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            stock_id INTEGER NOT NULL,
+            current_price REAL NOT NULL,
+            current_cost REAL NOT NULL DEFAULT 0,
+            total_position REAL NOT NULL,
+            total_fee REAL NOT NULL DEFAULT 0,
+            transaction_price REAL NOT NULL,
+            transaction_position REAL NOT NULL,
+            transaction_commission_fee REAL NOT NULL DEFAULT 0,
+            transaction_tax_fee REAL NOT NULL DEFAULT 0,
+            transaction_regulatory_fee REAL NOT NULL DEFAULT 0,
+            transaction_brokerage_fee REAL NOT NULL DEFAULT 0,
+            transaction_transfer_fee REAL NOT NULL DEFAULT 0,
+            action INTEGER NOT NULL DEFAULT 1,
+            profit REAL NOT NULL DEFAULT 0,
+            profit_rate REAL NOT NULL DEFAULT 0,
+            action_time DATETIME NOT NULL DEFAULT '',
+            action_info TEXT NOT NULL DEFAULT '',
+            created_at DATETIME DEFAULT (datetime('now', 'localtime')),
+            updated_at DATETIME DEFAULT (datetime('now', 'localtime'))
+        );
+        CREATE INDEX IF NOT EXISTS idx_tb_stock_action_stock_id ON tb_stock_action(stock_id);
+        CREATE TRIGGER IF NOT EXISTS update_tb_stock_timestamp
+        AFTER UPDATE ON tb_stock_fee
+        BEGIN
+            UPDATE tb_stock SET updated_at = datetime('now', 'localtime') WHERE stock_fee_id = NEW.stock_fee_id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS update_tb_stock_timestamp
+        AFTER UPDATE ON tb_stock
+        BEGIN
+            UPDATE tb_stock SET updated_at = datetime('now', 'localtime') WHERE stock_id = NEW.stock_id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS update_tb_stock_action_timestamp
+        AFTER UPDATE ON tb_stock_action
+        BEGIN
+            UPDATE tb_stock_action SET updated_at = datetime('now', 'localtime') WHERE stock_action_id = NEW.stock_action_id;
+        END;
+
+        -- 建仓1000股@10，加仓1000股@12，减仓500股@13
+        INSERT INTO tb_stock (stock_name, type, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate) VALUES ('a', 1, 0.00025, 0.0005, 0.00002, 0.0000341, 0.00001);
+        INSERT INTO tb_stock_action (stock_id, current_price, current_cost, total_position, total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action) VALUES
+            (1, 10, 10, 1000, 5.64, 10, 1000, 5, 0, 0.2, 0.34, 0.1, 1),
+            (1, 12, 11, 2000, 11.41, 12, 1000, 5, 0, 0.24, 0.41, 0.12, 3),
+            (1, 13, 10.333, 1500, 20.08, 13, 500, 5, 3.25, 0.13, 0.22, 0.07, 4);
+    ";
+
+    fn column_types(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("SELECT type FROM pragma_table_info('{table}')"))
+            .unwrap();
+        let types = stmt.query_map([], |row| row.get(0)).unwrap();
+        types.collect::<Result<Vec<String>>>().unwrap()
+    }
+
+    fn decimals(conn: &Connection, sql: &str) -> Vec<Vec<Decimal>> {
+        let mut stmt = conn.prepare(sql).unwrap();
+        let columns = stmt.column_count();
+        let rows = stmt
+            .query_map([], |row| {
+                (0..columns).map(|idx| get_decimal(row, idx)).collect()
+            })
+            .unwrap();
+        rows.collect::<Result<Vec<_>>>().unwrap()
+    }
+
+    #[test]
+    fn migrate_baseline_to_latest() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        run_migrations(&mut conn).unwrap();
+
+        let version: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.last().unwrap().version);
+
+        // 金额字段全部改为TEXT，REAL转换后的科学计数法可以解析
+        for table in ["tb_stock_fee", "tb_stock", "tb_stock_action"] {
+            assert!(!column_types(&conn, table).contains(&"REAL".to_string()));
+        }
+        assert_eq!(
+            decimals(
+                &conn,
+                "SELECT regulatory_fee_rate, brokerage_fee_rate FROM tb_stock"
+            ),
+            vec![vec![dec!(0.00002), dec!(0.0000341)]]
+        );
+
+        // 修改费率方案不再触发错误的更新时间触发器
+        conn.execute("UPDATE tb_stock_fee SET commission_fee_rate = '0.0001'", [])
+            .unwrap();
+
+        // 按先进先出补齐批次：第一批卖出500股
+        assert_eq!(
+            decimals(
+                &conn,
+                "SELECT stock_action_id, buy_price, buy_position, remaining_position, buy_fee FROM tb_stock_lot ORDER BY stock_lot_id"
+            ),
+            vec![
+                vec![dec!(1), dec!(10), dec!(1000), dec!(500), dec!(5.64)],
+                vec![dec!(2), dec!(12), dec!(1000), dec!(1000), dec!(5.77)],
+            ]
+        );
+        assert_eq!(
+            decimals(
+                &conn,
+                "SELECT stock_action_id, stock_lot_id, sell_position, realized_profit FROM tb_stock_lot_sale"
+            ),
+            vec![vec![dec!(3), dec!(1), dec!(500), dec!(1500)]]
+        );
+        assert_eq!(
+            decimals(
+                &conn,
+                "SELECT realized_profit, unrealized_profit, net_profit_after_fees FROM tb_stock_action WHERE stock_action_id = 3"
+            ),
+            vec![vec![dec!(1500), dec!(2500), dec!(3979.92)]]
+        );
+
+        // 现有数据作为初始快照
+        let snapshot_events: i32 = conn
+            .query_row(
+                &format!("SELECT COUNT(*) FROM tb_event WHERE event_type = '{SNAPSHOT_EVENT}'"),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(snapshot_events, 1);
+
+        // 已是最新版本时不再执行
+        run_migrations(&mut conn).unwrap();
+        let lots: i32 = conn
+            .query_row("SELECT COUNT(*) FROM tb_stock_lot", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(lots, 2);
+    }
+}
@@ -1,4 +1,12 @@
 pub mod db_connect;
+pub mod db_path;
+pub mod decimal;
+pub mod event;
+pub mod fee_rate_history;
+pub mod migration;
+pub mod repository;
 pub mod stock;
 pub mod stock_action;
 pub mod stock_fee;
+pub mod stock_lot;
+pub mod trading_calendar;
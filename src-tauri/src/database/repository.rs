@@ -0,0 +1,433 @@
+use crate::calc::calendar::HolidayYears;
+use crate::calc::lot::{LotBonus, LotSale};
+use crate::calc::trade_engine::{TradeFee, TradeFeeRate, TradeResult};
+use crate::database::event::EventRecord;
+use crate::database::fee_rate_history::{FeeRateHistoryRecord, RateOwner};
+use crate::database::stock::StockRecord;
+use crate::database::stock_action::{KeptLotChange, ReplayedAction, StockActionRecord};
+use crate::database::stock_fee::StockFeeRate;
+use crate::database::stock_lot::{StockLotRecord, StockLotSaleRecord};
+use crate::database::trading_calendar::TradingCalendarRecord;
+use crate::error::AppError;
+use chrono::NaiveDateTime;
+use rusqlite::{Connection, Result};
+use rust_decimal::Decimal;
+
+/*************************************仓储接口**************************************/
+// 业务逻辑只通过以下接口读写数据，不直接依赖全局数据库连接
+// SQLite实现见本文件末尾，文件数据库与内存数据库(:memory:)共用同一实现
+
+// 股票
+pub trait StockRepo {
+    fn insert_stock(
+        &self,
+        stock_name: &str,
+        stock_type: i32,
+        stock_fee_id: Option<i32>,
+        is_st: bool,
+        fee_rate: &TradeFeeRate,
+        created_at: &str,
+    ) -> Result<i64>;
+    fn get_all_stocks(&self) -> Result<Vec<StockRecord>>;
+    fn get_stock_by_id(&self, stock_id: i32) -> Result<Option<StockRecord>>;
+    fn update_stock_status(&self, stock_id: i32, status: i32) -> Result<()>;
+    fn update_stock_st(&self, stock_id: i32, is_st: bool) -> Result<()>;
+    fn update_stock_sort(&self, stock_id: i32, sort: i32) -> Result<()>;
+    fn update_fee_rate(&self, stock_id: i32, fee_rate: &TradeFeeRate) -> Result<usize>;
+    fn delete_stock(&self, stock_id: i32) -> Result<()>;
+}
+
+// 操作记录及买入批次
+pub trait ActionRepo {
+    fn insert_action(&self, stock_id: i32, result: &TradeResult, created_at: &str) -> Result<i64>;
+    fn get_actions_by_stock_id(&self, stock_id: i32) -> Result<Vec<StockActionRecord>>;
+    fn get_last_action(&self, stock_id: i32) -> Result<Option<StockActionRecord>>;
+    fn get_action_by_id(&self, stock_action_id: i32) -> Result<Option<StockActionRecord>>;
+    fn save_stock_action_info(
+        &self,
+        stock_action_id: i32,
+        action_time: String,
+        action_info: String,
+    ) -> Result<()>;
+    fn delete_last_action(&self, stock_id: i32) -> Result<()>;
+    fn rewrite_fees(&self, actions: &[StockActionRecord], fees: &[TradeFee]) -> Result<usize>;
+    fn replace_history(
+        &self,
+        stock_id: i32,
+        kept: &[KeptLotChange],
+        replayed: &[ReplayedAction],
+    ) -> Result<()>;
+    fn insert_lot(
+        &self,
+        stock_id: i32,
+        stock_action_id: i32,
+        buy_price: Decimal,
+        buy_position: Decimal,
+        buy_fee: Decimal,
+    ) -> Result<i64>;
+    fn insert_bonus_lot(
+        &self,
+        stock_id: i32,
+        stock_action_id: i32,
+        bonus: &LotBonus,
+    ) -> Result<i64>;
+    fn get_lots_by_stock_id(&self, stock_id: i32) -> Result<Vec<StockLotRecord>>;
+    fn insert_sales(&self, stock_id: i32, stock_action_id: i32, sales: &[LotSale]) -> Result<()>;
+    fn split_lots(
+        &self,
+        stock_id: i32,
+        stock_action_id: i32,
+        split_from: Decimal,
+        split_to: Decimal,
+    ) -> Result<()>;
+    fn get_sales_by_stock_id(&self, stock_id: i32) -> Result<Vec<StockLotSaleRecord>>;
+    fn revert_action(&self, stock_action_id: i32) -> Result<()>;
+}
+
+// 费率方案及费率历史
+pub trait FeeRepo {
+    fn get_fee(&self) -> Result<StockFeeRate>;
+    fn get_fee_by_id(&self, stock_fee_id: i32) -> Result<Option<StockFeeRate>>;
+    fn get_fee_list(&self) -> Result<Vec<StockFeeRate>>;
+    fn insert_fee(
+        &self,
+        stock_fee_name: &str,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+    ) -> Result<i64>;
+    fn update_fee(
+        &self,
+        stock_fee_id: i32,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+    ) -> Result<usize>;
+    fn rename_fee(&self, stock_fee_id: i32, stock_fee_name: &str) -> Result<usize>;
+    fn set_default_fee(&self, stock_fee_id: i32) -> Result<usize>;
+    fn delete_fee(&self, stock_fee_id: i32) -> Result<usize>;
+    fn record_rate_change(
+        &self,
+        owner: RateOwner,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+        valid_from: NaiveDateTime,
+    ) -> Result<i64, AppError>;
+    fn get_rate_at(
+        &self,
+        owner: RateOwner,
+        at: NaiveDateTime,
+    ) -> Result<Option<FeeRateHistoryRecord>>;
+    fn get_rate_history(&self, owner: RateOwner) -> Result<Vec<FeeRateHistoryRecord>>;
+    fn resolve_fee_rate(&self, stock: &StockRecord, at: NaiveDateTime) -> Result<TradeFeeRate>;
+}
+
+// 操作事件
+pub trait EventRepo {
+    fn append_event(&self, event_type: &str, payload: &str, created_at: &str) -> Result<i64>;
+    fn get_events(&self) -> Result<Vec<EventRecord>>;
+    fn get_active_events(&self) -> Result<Vec<EventRecord>>;
+    fn get_undo_events(&self, limit: i64) -> Result<Vec<EventRecord>>;
+    fn get_redo_events(&self, limit: i64) -> Result<Vec<EventRecord>>;
+    fn set_undone(&self, event_ids: &[i32], undone: bool) -> Result<()>;
+    fn clear_projection(&self) -> Result<()>;
+    fn restore_snapshot(&self) -> Result<()>;
+}
+
+// 导入的交易日历
+pub trait CalendarRepo {
+    fn get_holidays(&self) -> Result<HolidayYears>;
+    fn save_holidays(&self, years: &HolidayYears) -> Result<()>;
+}
+
+// 命令需要的全部仓储
+pub trait Repository: StockRepo + ActionRepo + FeeRepo + EventRepo + CalendarRepo {}
+
+impl<T: StockRepo + ActionRepo + FeeRepo + EventRepo + CalendarRepo> Repository for T {}
+
+/*************************************SQLite实现**************************************/
+// 事务(Transaction)可解引用为Connection，命令在事务中使用同一实现
+
+impl StockRepo for Connection {
+    fn insert_stock(
+        &self,
+        stock_name: &str,
+        stock_type: i32,
+        stock_fee_id: Option<i32>,
+        is_st: bool,
+        fee_rate: &TradeFeeRate,
+        created_at: &str,
+    ) -> Result<i64> {
+        StockRecord::insert_stock(
+            self,
+            stock_name,
+            stock_type,
+            stock_fee_id,
+            is_st,
+            fee_rate,
+            created_at,
+        )
+    }
+
+    fn get_all_stocks(&self) -> Result<Vec<StockRecord>> {
+        StockRecord::get_all_stocks(self)
+    }
+
+    fn get_stock_by_id(&self, stock_id: i32) -> Result<Option<StockRecord>> {
+        StockRecord::get_stock_by_id(self, stock_id)
+    }
+
+    fn update_stock_status(&self, stock_id: i32, status: i32) -> Result<()> {
+        StockRecord::update_stock_status(self, stock_id, status)
+    }
+
+    fn update_stock_st(&self, stock_id: i32, is_st: bool) -> Result<()> {
+        StockRecord::update_stock_st(self, stock_id, is_st)
+    }
+
+    fn update_stock_sort(&self, stock_id: i32, sort: i32) -> Result<()> {
+        StockRecord::update_stock_sort(self, stock_id, sort)
+    }
+
+    fn update_fee_rate(&self, stock_id: i32, fee_rate: &TradeFeeRate) -> Result<usize> {
+        StockRecord::update_fee_rate(self, stock_id, fee_rate)
+    }
+
+    fn delete_stock(&self, stock_id: i32) -> Result<()> {
+        StockRecord::delete_stock(self, stock_id)
+    }
+}
+
+impl ActionRepo for Connection {
+    fn insert_action(&self, stock_id: i32, result: &TradeResult, created_at: &str) -> Result<i64> {
+        StockActionRecord::insert_action(self, stock_id, result, created_at)
+    }
+
+    fn get_actions_by_stock_id(&self, stock_id: i32) -> Result<Vec<StockActionRecord>> {
+        StockActionRecord::get_actions_by_stock_id(self, stock_id)
+    }
+
+    fn get_last_action(&self, stock_id: i32) -> Result<Option<StockActionRecord>> {
+        StockActionRecord::get_last_action(self, stock_id)
+    }
+
+    fn get_action_by_id(&self, stock_action_id: i32) -> Result<Option<StockActionRecord>> {
+        StockActionRecord::get_action_by_id(self, stock_action_id)
+    }
+
+    fn save_stock_action_info(
+        &self,
+        stock_action_id: i32,
+        action_time: String,
+        action_info: String,
+    ) -> Result<()> {
+        StockActionRecord::save_stock_action_info(self, stock_action_id, action_time, action_info)
+    }
+
+    fn delete_last_action(&self, stock_id: i32) -> Result<()> {
+        StockActionRecord::delete_last_action(self, stock_id)
+    }
+
+    fn rewrite_fees(&self, actions: &[StockActionRecord], fees: &[TradeFee]) -> Result<usize> {
+        StockActionRecord::rewrite_fees(self, actions, fees)
+    }
+
+    fn replace_history(
+        &self,
+        stock_id: i32,
+        kept: &[KeptLotChange],
+        replayed: &[ReplayedAction],
+    ) -> Result<()> {
+        StockActionRecord::replace_history(self, stock_id, kept, replayed)
+    }
+
+    fn insert_lot(
+        &self,
+        stock_id: i32,
+        stock_action_id: i32,
+        buy_price: Decimal,
+        buy_position: Decimal,
+        buy_fee: Decimal,
+    ) -> Result<i64> {
+        StockLotRecord::insert_lot(
+            self,
+            stock_id,
+            stock_action_id,
+            buy_price,
+            buy_position,
+            buy_fee,
+        )
+    }
+
+    fn insert_bonus_lot(
+        &self,
+        stock_id: i32,
+        stock_action_id: i32,
+        bonus: &LotBonus,
+    ) -> Result<i64> {
+        StockLotRecord::insert_bonus_lot(self, stock_id, stock_action_id, bonus)
+    }
+
+    fn get_lots_by_stock_id(&self, stock_id: i32) -> Result<Vec<StockLotRecord>> {
+        StockLotRecord::get_lots_by_stock_id(self, stock_id)
+    }
+
+    fn insert_sales(&self, stock_id: i32, stock_action_id: i32, sales: &[LotSale]) -> Result<()> {
+        StockLotRecord::insert_sales(self, stock_id, stock_action_id, sales)
+    }
+
+    fn split_lots(
+        &self,
+        stock_id: i32,
+        stock_action_id: i32,
+        split_from: Decimal,
+        split_to: Decimal,
+    ) -> Result<()> {
+        StockLotRecord::split_lots(self, stock_id, stock_action_id, split_from, split_to)
+    }
+
+    fn get_sales_by_stock_id(&self, stock_id: i32) -> Result<Vec<StockLotSaleRecord>> {
+        StockLotRecord::get_sales_by_stock_id(self, stock_id)
+    }
+
+    fn revert_action(&self, stock_action_id: i32) -> Result<()> {
+        StockLotRecord::revert_action(self, stock_action_id)
+    }
+}
+
+impl FeeRepo for Connection {
+    fn get_fee(&self) -> Result<StockFeeRate> {
+        StockFeeRate::get_fee(self)
+    }
+
+    fn get_fee_by_id(&self, stock_fee_id: i32) -> Result<Option<StockFeeRate>> {
+        StockFeeRate::get_fee_by_id(self, stock_fee_id)
+    }
+
+    fn get_fee_list(&self) -> Result<Vec<StockFeeRate>> {
+        StockFeeRate::get_fee_list(self)
+    }
+
+    fn insert_fee(
+        &self,
+        stock_fee_name: &str,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+    ) -> Result<i64> {
+        StockFeeRate::insert(
+            self,
+            stock_fee_name,
+            commission_fee_rate,
+            market_rates,
+            min_commission,
+        )
+    }
+
+    fn update_fee(
+        &self,
+        stock_fee_id: i32,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+    ) -> Result<usize> {
+        StockFeeRate::update(
+            self,
+            stock_fee_id,
+            commission_fee_rate,
+            market_rates,
+            min_commission,
+        )
+    }
+
+    fn rename_fee(&self, stock_fee_id: i32, stock_fee_name: &str) -> Result<usize> {
+        StockFeeRate::rename(self, stock_fee_id, stock_fee_name)
+    }
+
+    fn set_default_fee(&self, stock_fee_id: i32) -> Result<usize> {
+        StockFeeRate::set_default(self, stock_fee_id)
+    }
+
+    fn delete_fee(&self, stock_fee_id: i32) -> Result<usize> {
+        StockFeeRate::delete(self, stock_fee_id)
+    }
+
+    fn record_rate_change(
+        &self,
+        owner: RateOwner,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+        valid_from: NaiveDateTime,
+    ) -> Result<i64, AppError> {
+        FeeRateHistoryRecord::record_change(
+            self,
+            owner,
+            commission_fee_rate,
+            market_rates,
+            min_commission,
+            valid_from,
+        )
+    }
+
+    fn get_rate_at(
+        &self,
+        owner: RateOwner,
+        at: NaiveDateTime,
+    ) -> Result<Option<FeeRateHistoryRecord>> {
+        FeeRateHistoryRecord::get_rate_at(self, owner, at)
+    }
+
+    fn get_rate_history(&self, owner: RateOwner) -> Result<Vec<FeeRateHistoryRecord>> {
+        FeeRateHistoryRecord::get_history(self, owner)
+    }
+
+    fn resolve_fee_rate(&self, stock: &StockRecord, at: NaiveDateTime) -> Result<TradeFeeRate> {
+        FeeRateHistoryRecord::resolve(self, stock, at)
+    }
+}
+
+impl EventRepo for Connection {
+    fn append_event(&self, event_type: &str, payload: &str, created_at: &str) -> Result<i64> {
+        EventRecord::append(self, event_type, payload, created_at)
+    }
+
+    fn get_events(&self) -> Result<Vec<EventRecord>> {
+        EventRecord::get_events(self)
+    }
+
+    fn get_active_events(&self) -> Result<Vec<EventRecord>> {
+        EventRecord::get_active_events(self)
+    }
+
+    fn get_undo_events(&self, limit: i64) -> Result<Vec<EventRecord>> {
+        EventRecord::get_undo_events(self, limit)
+    }
+
+    fn get_redo_events(&self, limit: i64) -> Result<Vec<EventRecord>> {
+        EventRecord::get_redo_events(self, limit)
+    }
+
+    fn set_undone(&self, event_ids: &[i32], undone: bool) -> Result<()> {
+        EventRecord::set_undone(self, event_ids, undone)
+    }
+
+    fn clear_projection(&self) -> Result<()> {
+        EventRecord::clear_projection(self)
+    }
+
+    fn restore_snapshot(&self) -> Result<()> {
+        EventRecord::restore_snapshot(self)
+    }
+}
+
+impl CalendarRepo for Connection {
+    fn get_holidays(&self) -> Result<HolidayYears> {
+        TradingCalendarRecord::get_holidays(self)
+    }
+
+    fn save_holidays(&self, years: &HolidayYears) -> Result<()> {
+        TradingCalendarRecord::save_holidays(self, years)
+    }
+}
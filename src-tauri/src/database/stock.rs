@@ -1,5 +1,8 @@
-use crate::database::db_connect::get_db_state;
-use rusqlite::Result;
+use crate::calc::trade_engine::TradeFeeRate;
+use crate::database::decimal::get_decimal;
+use crate::database::fee_rate_history::{FeeRateHistoryRecord, RateOwner};
+use rusqlite::{params, Connection, Result};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
 // Stock结构体
@@ -8,13 +11,16 @@ pub struct StockRecord {
     pub stock_id: i32,
     pub stock_name: String,
     pub stock_type: i32,
-    pub commission_fee_rate: f64, // 佣金
-    pub tax_fee_rate: f64,        // 印花税
-    pub regulatory_fee_rate: f64, // 证管费
-    pub brokerage_fee_rate: f64,  // 经手费
-    pub transfer_fee_rate: f64,   // 过户费
-    pub status: i32,              // 状态 1-正常买卖中 2-已经平仓
-    pub sort: i32,                // 排序
+    pub commission_fee_rate: Decimal, // 佣金
+    pub tax_fee_rate: Decimal,        // 印花税
+    pub regulatory_fee_rate: Decimal, // 证管费
+    pub brokerage_fee_rate: Decimal,  // 经手费
+    pub transfer_fee_rate: Decimal,   // 过户费
+    pub min_commission: Decimal,      // 最低佣金
+    pub stock_fee_id: Option<i32>,    // 开仓时使用的费率方案
+    pub is_st: bool,                  // 是否ST股票
+    pub status: i32,                  // 状态 1-正常买卖中 2-已经平仓
+    pub sort: i32,                    // 排序
     pub created_at: String,
     pub updated_at: String,
 }
@@ -22,39 +28,39 @@ pub struct StockRecord {
 // 操作记录处理
 #[allow(dead_code)]
 impl StockRecord {
-    /// 插入股票数据
+    /// 插入股票数据，created_at为开仓(事件)的时间
     pub fn insert_stock(
+        conn: &Connection,
         stock_name: &str,
         stock_type: i32,
-        commission_fee_rate: f64,
-        tax_fee_rate: f64,
-        regulatory_fee_rate: f64,
-        brokerage_fee_rate: f64,
-        transfer_fee_rate: f64,
+        stock_fee_id: Option<i32>,
+        is_st: bool,
+        fee_rate: &TradeFeeRate,
+        created_at: &str,
     ) -> Result<i64, rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO tb_stock (stock_name, type, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            [
+            "INSERT INTO tb_stock (stock_name, type, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission, stock_fee_id, is_st, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
                 stock_name,
-                &stock_type.to_string(),
-                &commission_fee_rate.to_string(),
-                &tax_fee_rate.to_string(),
-                &regulatory_fee_rate.to_string(),
-                &brokerage_fee_rate.to_string(),
-                &transfer_fee_rate.to_string(),
+                stock_type,
+                fee_rate.commission_fee_rate.to_string(),
+                fee_rate.tax_fee_rate.to_string(),
+                fee_rate.regulatory_fee_rate.to_string(),
+                fee_rate.brokerage_fee_rate.to_string(),
+                fee_rate.transfer_fee_rate.to_string(),
+                fee_rate.min_commission.to_string(),
+                stock_fee_id,
+                is_st,
+                created_at,
             ],
         )?;
         Ok(conn.last_insert_rowid())
     }
 
     /// 查询所有股票数据
-    pub fn get_all_stocks() -> Result<Vec<StockRecord>> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn get_all_stocks(conn: &Connection) -> Result<Vec<StockRecord>> {
         let mut stmt = conn.prepare(
-            "SELECT stock_id, stock_name, type, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, status, sort, created_at, updated_at FROM tb_stock ORDER BY sort ASC, stock_id DESC;"
+            "SELECT stock_id, stock_name, type, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, status, sort, created_at, updated_at, min_commission, stock_fee_id, is_st FROM tb_stock ORDER BY sort ASC, stock_id DESC;"
         )?;
 
         let stock_iter = stmt.query_map([], |row| {
@@ -62,11 +68,14 @@ impl StockRecord {
                 stock_id: row.get(0)?,
                 stock_name: row.get(1)?,
                 stock_type: row.get(2)?,
-                commission_fee_rate: row.get(3)?,
-                tax_fee_rate: row.get(4)?,
-                regulatory_fee_rate: row.get(5)?,
-                brokerage_fee_rate: row.get(6)?,
-                transfer_fee_rate: row.get(7)?,
+                commission_fee_rate: get_decimal(row, 3)?,
+                tax_fee_rate: get_decimal(row, 4)?,
+                regulatory_fee_rate: get_decimal(row, 5)?,
+                brokerage_fee_rate: get_decimal(row, 6)?,
+                transfer_fee_rate: get_decimal(row, 7)?,
+                min_commission: get_decimal(row, 12)?,
+                stock_fee_id: row.get(13)?,
+                is_st: row.get(14)?,
                 status: row.get(8)?,
                 sort: row.get(9)?,
                 created_at: row.get(10)?,
@@ -82,11 +91,9 @@ impl StockRecord {
     }
 
     /// 根据ID查询股票
-    pub fn get_stock_by_id(stock_id: i32) -> Result<Option<StockRecord>> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn get_stock_by_id(conn: &Connection, stock_id: i32) -> Result<Option<StockRecord>> {
         let mut stmt = conn.prepare(
-            "SELECT stock_id, stock_name, type, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, status, sort, created_at, updated_at FROM tb_stock WHERE stock_id = ?"
+            "SELECT stock_id, stock_name, type, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, status, sort, created_at, updated_at, min_commission, stock_fee_id, is_st FROM tb_stock WHERE stock_id = ?"
         )?;
 
         let mut rows = stmt.query_map([stock_id], |row| {
@@ -94,11 +101,14 @@ impl StockRecord {
                 stock_id: row.get(0)?,
                 stock_name: row.get(1)?,
                 stock_type: row.get(2)?,
-                commission_fee_rate: row.get(3)?,
-                tax_fee_rate: row.get(4)?,
-                regulatory_fee_rate: row.get(5)?,
-                brokerage_fee_rate: row.get(6)?,
-                transfer_fee_rate: row.get(7)?,
+                commission_fee_rate: get_decimal(row, 3)?,
+                tax_fee_rate: get_decimal(row, 4)?,
+                regulatory_fee_rate: get_decimal(row, 5)?,
+                brokerage_fee_rate: get_decimal(row, 6)?,
+                transfer_fee_rate: get_decimal(row, 7)?,
+                min_commission: get_decimal(row, 12)?,
+                stock_fee_id: row.get(13)?,
+                is_st: row.get(14)?,
                 status: row.get(8)?,
                 sort: row.get(9)?,
                 created_at: row.get(10)?,
@@ -113,9 +123,7 @@ impl StockRecord {
     }
 
     // 修改股票状态
-    pub fn update_stock_status(stock_id: i32, status: i32) -> Result<()> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn update_stock_status(conn: &Connection, stock_id: i32, status: i32) -> Result<()> {
         conn.execute(
             "UPDATE tb_stock SET status = ? WHERE stock_id = ?",
             [status, stock_id],
@@ -123,10 +131,17 @@ impl StockRecord {
         Ok(())
     }
 
+    // 修改ST标记
+    pub fn update_stock_st(conn: &Connection, stock_id: i32, is_st: bool) -> Result<()> {
+        conn.execute(
+            "UPDATE tb_stock SET is_st = ?1 WHERE stock_id = ?2",
+            params![is_st, stock_id],
+        )?;
+        Ok(())
+    }
+
     // 排序
-    pub fn update_stock_sort(stock_id: i32, sort: i32) -> Result<()> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn update_stock_sort(conn: &Connection, stock_id: i32, sort: i32) -> Result<()> {
         conn.execute(
             "UPDATE tb_stock SET sort = ? WHERE stock_id = ?",
             [sort, stock_id],
@@ -134,14 +149,50 @@ impl StockRecord {
         Ok(())
     }
 
-    /// 删除股票数据
-    pub fn delete_stock(stock_id: i32) -> Result<()> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    // 股票费率
+    pub fn fee_rate(&self) -> TradeFeeRate {
+        TradeFeeRate {
+            commission_fee_rate: self.commission_fee_rate,
+            tax_fee_rate: self.tax_fee_rate,
+            regulatory_fee_rate: self.regulatory_fee_rate,
+            brokerage_fee_rate: self.brokerage_fee_rate,
+            transfer_fee_rate: self.transfer_fee_rate,
+            min_commission: self.min_commission,
+        }
+    }
+
+    /// 删除股票数据，包括股票的自定义费率历史
+    pub fn delete_stock(conn: &Connection, stock_id: i32) -> Result<()> {
         conn.execute("DELETE FROM tb_stock WHERE stock_id = ?", [stock_id])?;
         conn.execute("DELETE FROM tb_stock_action WHERE stock_id = ?", [stock_id])?;
+        conn.execute("DELETE FROM tb_stock_lot WHERE stock_id = ?", [stock_id])?;
+        conn.execute(
+            "DELETE FROM tb_stock_lot_sale WHERE stock_id = ?",
+            [stock_id],
+        )?;
+        FeeRateHistoryRecord::delete_history(conn, RateOwner::Stock(stock_id))?;
         Ok(())
     }
 
+    /// 修改股票费率(当前费率)
+    pub fn update_fee_rate(
+        conn: &Connection,
+        stock_id: i32,
+        fee_rate: &TradeFeeRate,
+    ) -> Result<usize> {
+        conn.execute(
+            "UPDATE tb_stock SET commission_fee_rate = ?1, tax_fee_rate = ?2, regulatory_fee_rate = ?3, brokerage_fee_rate = ?4, transfer_fee_rate = ?5, min_commission = ?6 WHERE stock_id = ?7",
+            params![
+                fee_rate.commission_fee_rate.to_string(),
+                fee_rate.tax_fee_rate.to_string(),
+                fee_rate.regulatory_fee_rate.to_string(),
+                fee_rate.brokerage_fee_rate.to_string(),
+                fee_rate.transfer_fee_rate.to_string(),
+                fee_rate.min_commission.to_string(),
+                stock_id,
+            ],
+        )
+    }
+
     //
 }
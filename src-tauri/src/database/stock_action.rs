@@ -1,4 +1,10 @@
-use crate::database::db_connect::get_db_state;
+use crate::calc::lot::LotSale;
+use crate::calc::trade_engine::{TradeFee, TradeResult};
+use crate::constant::action_type::ActionType;
+use crate::database::decimal::get_decimal;
+use crate::database::stock_lot::StockLotRecord;
+use rusqlite::{params, Connection, OptionalExtension};
+use rust_decimal::Decimal;
 use serde::Serialize;
 
 // 数据结构定义
@@ -6,20 +12,26 @@ use serde::Serialize;
 pub struct StockActionRecord {
     pub stock_action_id: i32,
     pub stock_id: i32,
-    pub current_price: f64,
-    pub current_cost: f64,
-    pub total_position: f64,
-    pub total_fee:f64,
-    pub transaction_price: f64,
-    pub transaction_position: f64,
-    pub transaction_commission_fee: f64, // 佣金
-    pub transaction_tax_fee:f64, // 印花税
-    pub transaction_regulatory_fee:f64, // 证管费
-    pub transaction_brokerage_fee:f64, // 经手费
-    pub transaction_transfer_fee:f64, // 过户费
+    pub current_price: Decimal,
+    pub current_cost: Decimal,
+    pub total_position: Decimal,
+    pub total_fee: Decimal,
+    pub transaction_price: Decimal,
+    pub transaction_position: Decimal,
+    pub transaction_commission_fee: Decimal, // 佣金
+    pub transaction_tax_fee: Decimal, // 印花税
+    pub transaction_regulatory_fee: Decimal, // 证管费
+    pub transaction_brokerage_fee: Decimal, // 经手费
+    pub transaction_transfer_fee: Decimal, // 过户费
+    pub transaction_dividend_tax_fee: Decimal, // 红利税
+    pub split_from: Decimal, // 拆合前股数
+    pub split_to: Decimal, // 拆合后股数
     pub action: i32,
-    pub profit: f64,
-    pub profit_rate: f64,
+    pub profit: Decimal,                // 持仓盈亏(摊薄成本)
+    pub profit_rate: Decimal,           // 持仓盈亏比例(摊薄成本)
+    pub realized_profit: Decimal,       // 本次已实现盈亏(按批次，未扣费用)
+    pub unrealized_profit: Decimal,     // 剩余批次的浮动盈亏
+    pub net_profit_after_fees: Decimal, // 累计已实现 + 浮动 - 累计费用
     pub action_time: String,
     pub action_info: String,
     pub created_at: String,
@@ -29,74 +41,72 @@ pub struct StockActionRecord {
 // 操作记录处理
     #[allow(dead_code)]
     impl StockActionRecord {
-    /// 插入股票操作数据
+    /// 插入股票操作数据，created_at为操作发生(事件)的时间
     pub fn insert_action(
-        stock_id: i32, 
-        current_price: f64, 
-        current_cost: f64, 
-        total_position: f64, 
-        total_fee:f64,
-        transaction_price: f64, 
-        transaction_position: f64, 
-        transaction_commission_fee: f64, 
-        transaction_tax_fee: f64,
-        transaction_regulatory_fee: f64,
-        transaction_brokerage_fee: f64,
-        transaction_transfer_fee: f64,
-        action: i32, 
-        profit: f64, 
-        profit_rate: f64, 
+        conn: &Connection,
+        stock_id: i32,
+        result: &TradeResult,
+        created_at: &str,
     ) -> Result<i64, rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO tb_stock_action (stock_id, current_price, current_cost, total_position, total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action, profit, profit_rate) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            "INSERT INTO tb_stock_action (stock_id, current_price, current_cost, total_position, total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action, profit, profit_rate, realized_profit, unrealized_profit, net_profit_after_fees, created_at, transaction_dividend_tax_fee, split_from, split_to) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
             [
                 &stock_id.to_string(), 
-                &current_price.to_string(), 
-                &current_cost.to_string(), 
-                &total_position.to_string(), 
-                &total_fee.to_string(),
-                &transaction_price.to_string(), 
-                &transaction_position.to_string(), 
-                &transaction_commission_fee.to_string(), 
-                &transaction_tax_fee.to_string(),
-                &transaction_regulatory_fee.to_string(),
-                &transaction_brokerage_fee.to_string(),
-                &transaction_transfer_fee.to_string(),
-                &action.to_string(), 
-                &profit.to_string(), 
-                &profit_rate.to_string()
+                &result.current_price.to_string(), 
+                &result.current_cost.to_string(), 
+                &result.total_position.to_string(), 
+                &result.total_fee.to_string(),
+                &result.transaction_price.to_string(), 
+                &result.transaction_position.to_string(), 
+                &result.transaction_commission_fee.to_string(), 
+                &result.transaction_tax_fee.to_string(),
+                &result.transaction_regulatory_fee.to_string(),
+                &result.transaction_brokerage_fee.to_string(),
+                &result.transaction_transfer_fee.to_string(),
+                &result.action.to_string(), 
+                &result.profit.to_string(), 
+                &result.profit_rate.to_string(),
+                &result.realized_profit.to_string(),
+                &result.unrealized_profit.to_string(),
+                &result.net_profit_after_fees.to_string(),
+                created_at,
+                &result.transaction_dividend_tax_fee.to_string(),
+                &result.split_from.to_string(),
+                &result.split_to.to_string(),
             ],
         )?;
         Ok(conn.last_insert_rowid())
     }
     /// 根据股票ID查询操作记录
-    pub fn get_actions_by_stock_id( stock_id: i32) -> Result<Vec<StockActionRecord>,rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn get_actions_by_stock_id(conn: &Connection, stock_id: i32) -> Result<Vec<StockActionRecord>,rusqlite::Error> {
         let mut stmt = conn.prepare(
-            "SELECT stock_action_id, stock_id, current_price, current_cost, total_position, total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action, profit, profit_rate,action_time,action_info, created_at, updated_at FROM tb_stock_action WHERE stock_id = ? ORDER BY stock_action_id ASC"
+            "SELECT stock_action_id, stock_id, current_price, current_cost, total_position, total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action, profit, profit_rate,action_time,action_info, created_at, updated_at, realized_profit, unrealized_profit, net_profit_after_fees, transaction_dividend_tax_fee, split_from, split_to FROM tb_stock_action WHERE stock_id = ? ORDER BY stock_action_id ASC"
         )?;
 
         let stock_action_iter = stmt.query_map([stock_id], |row| {
             Ok(StockActionRecord {
                 stock_action_id: row.get(0)?,
                 stock_id: row.get(1)?,
-                current_price: row.get(2)?,
-                current_cost: row.get(3)?,
-                total_position: row.get(4)?,
-                total_fee: row.get(5)?,
-                transaction_price: row.get(6)?,
-                transaction_position: row.get(7)?,
-                transaction_commission_fee: row.get(8)?,
-                transaction_tax_fee: row.get(9)?,
-                transaction_regulatory_fee: row.get(10)?,
-                transaction_brokerage_fee: row.get(11)?,
-                transaction_transfer_fee: row.get(12)?,
+                current_price: get_decimal(row, 2)?,
+                current_cost: get_decimal(row, 3)?,
+                total_position: get_decimal(row, 4)?,
+                total_fee: get_decimal(row, 5)?,
+                transaction_price: get_decimal(row, 6)?,
+                transaction_position: get_decimal(row, 7)?,
+                transaction_commission_fee: get_decimal(row, 8)?,
+                transaction_tax_fee: get_decimal(row, 9)?,
+                transaction_regulatory_fee: get_decimal(row, 10)?,
+                transaction_brokerage_fee: get_decimal(row, 11)?,
+                transaction_transfer_fee: get_decimal(row, 12)?,
+                transaction_dividend_tax_fee: get_decimal(row, 23)?,
+                split_from: get_decimal(row, 24)?,
+                split_to: get_decimal(row, 25)?,
                 action: row.get(13)?,
-                profit: row.get(14)?,
-                profit_rate: row.get(15)?,
+                profit: get_decimal(row, 14)?,
+                profit_rate: get_decimal(row, 15)?,
+                realized_profit: get_decimal(row, 20)?,
+                unrealized_profit: get_decimal(row, 21)?,
+                net_profit_after_fees: get_decimal(row, 22)?,
                 action_time: row.get(16)?,
                 action_info: row.get(17)?,
                 created_at: row.get(18)?,
@@ -112,50 +122,258 @@ pub struct StockActionRecord {
     }
     
     // 获取最后一次操作
-    pub fn get_last_action(stock_id:i32) -> Result<StockActionRecord,rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT stock_action_id, stock_id, current_price, current_cost, total_position,total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action, profit, profit_rate,action_time,action_info, created_at, updated_at FROM tb_stock_action WHERE stock_id = ? ORDER BY stock_action_id DESC LIMIT 1")?;
+    pub fn get_last_action(conn: &Connection, stock_id:i32) -> Result<Option<StockActionRecord>,rusqlite::Error> {
+        let mut stmt = conn.prepare("SELECT stock_action_id, stock_id, current_price, current_cost, total_position,total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action, profit, profit_rate,action_time,action_info, created_at, updated_at, realized_profit, unrealized_profit, net_profit_after_fees, transaction_dividend_tax_fee, split_from, split_to FROM tb_stock_action WHERE stock_id = ? ORDER BY stock_action_id DESC LIMIT 1")?;
         let stock_action = stmt.query_row([stock_id], |row| {
             Ok(StockActionRecord {
                 stock_action_id: row.get(0)?,   
                 stock_id: row.get(1)?,
-                current_price: row.get(2)?,
-                current_cost: row.get(3)?,
-                total_position: row.get(4)?,
-                total_fee: row.get(5)?,
-                transaction_price: row.get(6)?,
-                transaction_position: row.get(7)?,
-                transaction_commission_fee: row.get(8)?,
-                transaction_tax_fee: row.get(9)?,
-                transaction_regulatory_fee: row.get(10)?,
-                transaction_brokerage_fee: row.get(11)?,
-                transaction_transfer_fee: row.get(12)?,
+                current_price: get_decimal(row, 2)?,
+                current_cost: get_decimal(row, 3)?,
+                total_position: get_decimal(row, 4)?,
+                total_fee: get_decimal(row, 5)?,
+                transaction_price: get_decimal(row, 6)?,
+                transaction_position: get_decimal(row, 7)?,
+                transaction_commission_fee: get_decimal(row, 8)?,
+                transaction_tax_fee: get_decimal(row, 9)?,
+                transaction_regulatory_fee: get_decimal(row, 10)?,
+                transaction_brokerage_fee: get_decimal(row, 11)?,
+                transaction_transfer_fee: get_decimal(row, 12)?,
+                transaction_dividend_tax_fee: get_decimal(row, 23)?,
+                split_from: get_decimal(row, 24)?,
+                split_to: get_decimal(row, 25)?,
                 action: row.get(13)?,
-                profit: row.get(14)?,
-                profit_rate: row.get(15)?,
+                profit: get_decimal(row, 14)?,
+                profit_rate: get_decimal(row, 15)?,
+                realized_profit: get_decimal(row, 20)?,
+                unrealized_profit: get_decimal(row, 21)?,
+                net_profit_after_fees: get_decimal(row, 22)?,
                 action_time: row.get(16)?,
                 action_info: row.get(17)?,
                 created_at: row.get(18)?,
                 updated_at: row.get(19)?,
             })
-        })?;
+        }).optional()?;
         Ok(stock_action)
     }   
 
+    // 根据ID获取操作记录
+    pub fn get_action_by_id(conn: &Connection, stock_action_id:i32) -> Result<Option<StockActionRecord>,rusqlite::Error> {
+        let mut stmt = conn.prepare("SELECT stock_action_id, stock_id, current_price, current_cost, total_position,total_fee, transaction_price, transaction_position, transaction_commission_fee, transaction_tax_fee, transaction_regulatory_fee, transaction_brokerage_fee, transaction_transfer_fee, action, profit, profit_rate,action_time,action_info, created_at, updated_at, realized_profit, unrealized_profit, net_profit_after_fees, transaction_dividend_tax_fee, split_from, split_to FROM tb_stock_action WHERE stock_action_id = ?")?;
+        let mut rows = stmt.query_map([stock_action_id], |row| {
+            Ok(StockActionRecord {
+                stock_action_id: row.get(0)?,   
+                stock_id: row.get(1)?,
+                current_price: get_decimal(row, 2)?,
+                current_cost: get_decimal(row, 3)?,
+                total_position: get_decimal(row, 4)?,
+                total_fee: get_decimal(row, 5)?,
+                transaction_price: get_decimal(row, 6)?,
+                transaction_position: get_decimal(row, 7)?,
+                transaction_commission_fee: get_decimal(row, 8)?,
+                transaction_tax_fee: get_decimal(row, 9)?,
+                transaction_regulatory_fee: get_decimal(row, 10)?,
+                transaction_brokerage_fee: get_decimal(row, 11)?,
+                transaction_transfer_fee: get_decimal(row, 12)?,
+                transaction_dividend_tax_fee: get_decimal(row, 23)?,
+                split_from: get_decimal(row, 24)?,
+                split_to: get_decimal(row, 25)?,
+                action: row.get(13)?,
+                profit: get_decimal(row, 14)?,
+                profit_rate: get_decimal(row, 15)?,
+                realized_profit: get_decimal(row, 20)?,
+                unrealized_profit: get_decimal(row, 21)?,
+                net_profit_after_fees: get_decimal(row, 22)?,
+                action_time: row.get(16)?,
+                action_info: row.get(17)?,
+                created_at: row.get(18)?,
+                updated_at: row.get(19)?,
+            })
+        })?;
+        match rows.next() {
+            Some(stock_action) => Ok(Some(stock_action?)),
+            None => Ok(None),
+        }
+    }   
+
     /// 保存操作信息
-    pub fn save_stock_action_info(stock_action_id:i32,action_time:String,action_info:String) -> Result<(),rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn save_stock_action_info(conn: &Connection, stock_action_id:i32,action_time:String,action_info:String) -> Result<(),rusqlite::Error> {
         conn.execute("UPDATE tb_stock_action SET action_time = ?, action_info = ? WHERE stock_action_id = ?", [action_time,action_info,stock_action_id.to_string()])?;
         Ok(())
     }
     
     /// 删除最后一条操作记录
-    pub fn delete_last_action(stock_id:i32) -> Result<(),rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn delete_last_action(conn: &Connection, stock_id:i32) -> Result<(),rusqlite::Error> {
         conn.execute("DELETE FROM tb_stock_action WHERE stock_action_id = (SELECT MAX(stock_action_id) FROM tb_stock_action WHERE stock_id = ?)", [stock_id])?;
         Ok(())
     }
+
+    /// 本次交易的各项费用
+    pub fn fee(&self) -> TradeFee {
+        TradeFee {
+            commission_fee: self.transaction_commission_fee,
+            tax_fee: self.transaction_tax_fee,
+            regulatory_fee: self.transaction_regulatory_fee,
+            brokerage_fee: self.transaction_brokerage_fee,
+            transfer_fee: self.transaction_transfer_fee,
+            dividend_tax_fee: self.transaction_dividend_tax_fee,
+        }
+    }
+
+    /// 按新的每次交易费用(与actions一一对应)重写费用并重新累计总费用，
+    /// 同步修正买入批次费用和扣费后净盈亏，返回修正的记录数
+    pub fn rewrite_fees(
+        conn: &Connection,
+        actions: &[StockActionRecord],
+        fees: &[TradeFee],
+    ) -> Result<usize, rusqlite::Error> {
+        let mut total_fee = Decimal::ZERO;
+        let mut rewritten = 0;
+        for (action, fee) in actions.iter().zip(fees) {
+            total_fee += fee.total();
+            if *fee == action.fee() && total_fee == action.total_fee {
+                continue;
+            }
+            // 净盈亏 = 累计已实现 + 浮动 - 累计费用，只需按费用差额调整
+            let net_profit_after_fees = action.net_profit_after_fees + action.total_fee - total_fee;
+            conn.execute(
+                "UPDATE tb_stock_action SET transaction_commission_fee = ?1, transaction_tax_fee = ?2, transaction_regulatory_fee = ?3, transaction_brokerage_fee = ?4, transaction_transfer_fee = ?5, total_fee = ?6, net_profit_after_fees = ?7, transaction_dividend_tax_fee = ?8 WHERE stock_action_id = ?9",
+                params![
+                    fee.commission_fee.to_string(),
+                    fee.tax_fee.to_string(),
+                    fee.regulatory_fee.to_string(),
+                    fee.brokerage_fee.to_string(),
+                    fee.transfer_fee.to_string(),
+                    total_fee.to_string(),
+                    net_profit_after_fees.to_string(),
+                    fee.dividend_tax_fee.to_string(),
+                    action.stock_action_id,
+                ],
+            )?;
+            if ActionType::from(action.action).adds_lot() {
+                conn.execute(
+                    "UPDATE tb_stock_lot SET buy_fee = ?1 WHERE stock_action_id = ?2",
+                    params![fee.total().to_string(), action.stock_action_id],
+                )?;
+            }
+            rewritten += 1;
+        }
+        Ok(rewritten)
+    }
+
+    /// 重放后改写操作记录，并重建买入批次剩余数量、买入价格和批次卖出记录
+    /// kept为重放之前的卖出和拆合股，按先后顺序排列
+    pub fn replace_history(
+        conn: &Connection,
+        stock_id: i32,
+        kept: &[KeptLotChange],
+        replayed: &[ReplayedAction],
+    ) -> Result<(), rusqlite::Error> {
+        conn.execute("UPDATE tb_stock_lot SET remaining_position = buy_position WHERE stock_id = ?", [stock_id])?;
+        conn.execute("DELETE FROM tb_stock_lot_sale WHERE stock_id = ?", [stock_id])?;
+        for change in kept {
+            match change {
+                KeptLotChange::Sales {
+                    stock_action_id,
+                    sales,
+                } => StockLotRecord::insert_sales(conn, stock_id, *stock_action_id, sales)?,
+                KeptLotChange::Split {
+                    stock_action_id,
+                    split_from,
+                    split_to,
+                } => StockLotRecord::split_lots(
+                    conn,
+                    stock_id,
+                    *stock_action_id,
+                    *split_from,
+                    *split_to,
+                )?,
+            }
+        }
+        for ReplayedAction { stock_action_id, action_time, result } in replayed {
+            conn.execute(
+                "UPDATE tb_stock_action SET current_price = ?1, current_cost = ?2, total_position = ?3, total_fee = ?4, transaction_price = ?5, transaction_position = ?6, transaction_commission_fee = ?7, transaction_tax_fee = ?8, transaction_regulatory_fee = ?9, transaction_brokerage_fee = ?10, transaction_transfer_fee = ?11, profit = ?12, profit_rate = ?13, realized_profit = ?14, unrealized_profit = ?15, net_profit_after_fees = ?16, action_time = ?17, transaction_dividend_tax_fee = ?18 WHERE stock_action_id = ?19",
+                params![
+                    result.current_price.to_string(),
+                    result.current_cost.to_string(),
+                    result.total_position.to_string(),
+                    result.total_fee.to_string(),
+                    result.transaction_price.to_string(),
+                    result.transaction_position.to_string(),
+                    result.transaction_commission_fee.to_string(),
+                    result.transaction_tax_fee.to_string(),
+                    result.transaction_regulatory_fee.to_string(),
+                    result.transaction_brokerage_fee.to_string(),
+                    result.transaction_transfer_fee.to_string(),
+                    result.profit.to_string(),
+                    result.profit_rate.to_string(),
+                    result.realized_profit.to_string(),
+                    result.unrealized_profit.to_string(),
+                    result.net_profit_after_fees.to_string(),
+                    action_time,
+                    result.transaction_dividend_tax_fee.to_string(),
+                    stock_action_id,
+                ],
+            )?;
+            if ActionType::from(result.action).is_sell() {
+                // 按顺序记录卖出，同时扣减批次剩余数量
+                StockLotRecord::insert_sales(conn, stock_id, *stock_action_id, &result.lot_sales)?;
+            } else if ActionType::from(result.action) == ActionType::Split {
+                StockLotRecord::split_lots(
+                    conn,
+                    stock_id,
+                    *stock_action_id,
+                    result.split_from,
+                    result.split_to,
+                )?;
+            } else if ActionType::from(result.action) == ActionType::BonusShares {
+                // 送转股批次按原批次更新，重放后没有分到股份的批次数量为0
+                conn.execute(
+                    "UPDATE tb_stock_lot SET buy_position = '0', remaining_position = '0' WHERE stock_action_id = ?",
+                    [stock_action_id],
+                )?;
+                for bonus in &result.lot_bonuses {
+                    conn.execute(
+                        "UPDATE tb_stock_lot SET buy_position = ?1, remaining_position = ?1 WHERE stock_action_id = ?2 AND source_lot_id = ?3",
+                        params![bonus.bonus_position.to_string(), stock_action_id, bonus.source_lot_id],
+                    )?;
+                }
+            } else if ActionType::from(result.action).adds_lot() {
+                conn.execute(
+                    "UPDATE tb_stock_lot SET buy_price = ?1, buy_position = ?2, remaining_position = ?2, buy_fee = ?3 WHERE stock_action_id = ?4",
+                    params![
+                        result.transaction_price.to_string(),
+                        result.transaction_position.to_string(),
+                        result.transaction_fee().to_string(),
+                        stock_action_id,
+                    ],
+                )?;
+            }
+        }
+        // 拆合股折算过的买入价格按操作记录重算
+        let actions = Self::get_actions_by_stock_id(conn, stock_id)?;
+        StockLotRecord::reprice_lots(conn, stock_id, &actions)
+    }
+}
+
+// 重放后的操作记录
+pub struct ReplayedAction {
+    pub stock_action_id: i32,
+    pub action_time: String,
+    pub result: TradeResult,
+}
+
+// 重放之前保持不变的批次变动
+pub enum KeptLotChange {
+    // 卖出消耗的批次
+    Sales {
+        stock_action_id: i32,
+        sales: Vec<LotSale>,
+    },
+    // 拆合股，每split_from股变为split_to股
+    Split {
+        stock_action_id: i32,
+        split_from: Decimal,
+        split_to: Decimal,
+    },
 }
@@ -1,95 +1,181 @@
-use crate::database::db_connect::get_db_state;
+use crate::calc::trade_engine::TradeFeeRate;
+use crate::constant::fee_rate::FeeRates;
+use crate::database::decimal::{get_decimal, get_optional_decimal};
+use crate::database::fee_rate_history::{FeeRateHistoryRecord, RateOwner, BEGINNING};
+use rusqlite::{params, Connection, Row};
+use rust_decimal::Decimal;
 use serde::Serialize;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct StockFeeRate {
     pub stock_fee_id: i32,
     pub stock_fee_name: String,
-    pub commission_fee_rate: f64, // 佣金
-    pub tax_fee_rate: f64,        // 印花税
-    pub regulatory_fee_rate: f64, // 证管费
-    pub brokerage_fee_rate: f64,  // 经手费
-    pub transfer_fee_rate: f64,   // 过户费
+    pub commission_fee_rate: Decimal, // 佣金
+    // 以下四项为空时按费率表(FeeRates)中交易日适用的标准收取
+    pub tax_fee_rate: Option<Decimal>,        // 印花税
+    pub regulatory_fee_rate: Option<Decimal>, // 证管费
+    pub brokerage_fee_rate: Option<Decimal>,  // 经手费
+    pub transfer_fee_rate: Option<Decimal>,   // 过户费
+    pub min_commission: Decimal,              // 最低佣金(免五为0)
+    pub is_default: bool,                     // 是否默认费率
     pub created_at: String,
     pub updated_at: String,
 }
 
+const STOCK_FEE_SELECT: &str = "SELECT stock_fee_id, stock_fee_name, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission, is_default, created_at, updated_at FROM tb_stock_fee";
+
 #[allow(dead_code)]
 impl StockFeeRate {
-    pub fn get_fee() -> Result<StockFeeRate, rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT stock_fee_id, stock_fee_name,  commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate,created_at, updated_at FROM tb_stock_fee WHERE stock_fee_id = 1;"
-        )?;
+    fn from_row(row: &Row) -> rusqlite::Result<StockFeeRate> {
+        Ok(StockFeeRate {
+            stock_fee_id: row.get(0)?,
+            stock_fee_name: row.get(1)?,
+            commission_fee_rate: get_decimal(row, 2)?,
+            tax_fee_rate: get_optional_decimal(row, 3)?,
+            regulatory_fee_rate: get_optional_decimal(row, 4)?,
+            brokerage_fee_rate: get_optional_decimal(row, 5)?,
+            transfer_fee_rate: get_optional_decimal(row, 6)?,
+            min_commission: get_decimal(row, 7)?,
+            is_default: row.get(8)?,
+            created_at: row.get(9)?,
+            updated_at: row.get(10)?,
+        })
+    }
 
-        let stock_fee = stmt.query_row([], |row| {
-            Ok(StockFeeRate {
-                stock_fee_id: row.get(0)?,
-                stock_fee_name: row.get(1)?,
-                commission_fee_rate: row.get(2)?,
-                tax_fee_rate: row.get(3)?,
-                regulatory_fee_rate: row.get(4)?,
-                brokerage_fee_rate: row.get(5)?,
-                transfer_fee_rate: row.get(6)?,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
-            })
-        })?;
+    /// 以费率表为基础，叠加费率方案中自行设置的费率，得到计算引擎使用的费率
+    pub fn fee_rate(&self, market: &FeeRates) -> TradeFeeRate {
+        TradeFeeRate {
+            commission_fee_rate: self.commission_fee_rate,
+            tax_fee_rate: self.tax_fee_rate.unwrap_or(market.tax),
+            regulatory_fee_rate: self.regulatory_fee_rate.unwrap_or(market.regulatory),
+            brokerage_fee_rate: self.brokerage_fee_rate.unwrap_or(market.brokerage),
+            transfer_fee_rate: self.transfer_fee_rate.unwrap_or(market.transfer),
+            min_commission: self.min_commission,
+        }
+    }
 
+    /// 获取默认费率
+    pub fn get_fee(conn: &Connection) -> Result<StockFeeRate, rusqlite::Error> {
+        let mut stmt = conn.prepare(&format!(
+            "{STOCK_FEE_SELECT} ORDER BY is_default DESC, stock_fee_id ASC LIMIT 1;"
+        ))?;
+        let stock_fee = stmt.query_row([], Self::from_row)?;
         Ok(stock_fee)
     }
+
+    /// 根据ID获取费率
+    pub fn get_fee_by_id(
+        conn: &Connection,
+        stock_fee_id: i32,
+    ) -> Result<Option<StockFeeRate>, rusqlite::Error> {
+        let mut stmt = conn.prepare(&format!("{STOCK_FEE_SELECT} WHERE stock_fee_id = ?1;"))?;
+        let mut rows = stmt.query_map([stock_fee_id], Self::from_row)?;
+        match rows.next() {
+            Some(stock_fee) => Ok(Some(stock_fee?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 获取所有费率方案，默认费率排在最前
+    pub fn get_fee_list(conn: &Connection) -> Result<Vec<StockFeeRate>, rusqlite::Error> {
+        let mut stmt = conn.prepare(&format!(
+            "{STOCK_FEE_SELECT} ORDER BY is_default DESC, stock_fee_id ASC;"
+        ))?;
+        let fee_iter = stmt.query_map([], Self::from_row)?;
+        let mut fees = Vec::new();
+        for fee in fee_iter {
+            fees.push(fee?);
+        }
+        Ok(fees)
+    }
+
+    /// 新建费率方案，market_rates依次为印花税、证管费、经手费、过户费
     pub fn insert(
+        conn: &Connection,
         stock_fee_name: &str,
-        commission_fee_rate: f64,
-        tax_fee_rate: f64,
-        regulatory_fee_rate: f64,
-        brokerage_fee_rate: f64,
-        transfer_fee_rate: f64,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
     ) -> Result<i64, rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+        let [tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate] =
+            market_rates.map(|rate| rate.map(|rate| rate.to_string()));
         conn.execute(
-            "INSERT INTO tb_stock_fee (stock_fee_name, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            [
+            "INSERT INTO tb_stock_fee (stock_fee_name, commission_fee_rate, tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate, min_commission) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
                 stock_fee_name,
-                &commission_fee_rate.to_string(),
-                &tax_fee_rate.to_string(),
-                &regulatory_fee_rate.to_string(),
-                &brokerage_fee_rate.to_string(),
-                &transfer_fee_rate.to_string(),
+                commission_fee_rate.to_string(),
+                tax_fee_rate,
+                regulatory_fee_rate,
+                brokerage_fee_rate,
+                transfer_fee_rate,
+                min_commission.to_string(),
             ],
         )?;
-        Ok(conn.last_insert_rowid())
+        let stock_fee_id = conn.last_insert_rowid();
+        // 新方案的费率一直有效，直到修改
+        FeeRateHistoryRecord::insert(
+            conn,
+            RateOwner::Fee(stock_fee_id as i32),
+            commission_fee_rate,
+            market_rates,
+            min_commission,
+            BEGINNING,
+        )?;
+        Ok(stock_fee_id)
     }
+    /// 修改费率方案，market_rates依次为印花税、证管费、经手费、过户费
     pub fn update(
-        commission_fee_rate: f64,
-        tax_fee_rate: f64,
-        regulatory_fee_rate: f64,
-        brokerage_fee_rate: f64,
-        transfer_fee_rate: f64,
-    ) -> Result<(), rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+        conn: &Connection,
+        stock_fee_id: i32,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+    ) -> Result<usize, rusqlite::Error> {
+        let [tax_fee_rate, regulatory_fee_rate, brokerage_fee_rate, transfer_fee_rate] =
+            market_rates.map(|rate| rate.map(|rate| rate.to_string()));
         conn.execute(
-            "UPDATE tb_stock_fee SET commission_fee_rate = ?1, tax_fee_rate = ?2, regulatory_fee_rate = ?3, brokerage_fee_rate = ?4, transfer_fee_rate = ?5 WHERE stock_fee_id = 1",
-            [
-                &commission_fee_rate.to_string(),
-                &tax_fee_rate.to_string(),
-                &regulatory_fee_rate.to_string(),
-                &brokerage_fee_rate.to_string(),
-                &transfer_fee_rate.to_string(),
+            "UPDATE tb_stock_fee SET commission_fee_rate = ?1, tax_fee_rate = ?2, regulatory_fee_rate = ?3, brokerage_fee_rate = ?4, transfer_fee_rate = ?5, min_commission = ?6 WHERE stock_fee_id = ?7",
+            params![
+                commission_fee_rate.to_string(),
+                tax_fee_rate,
+                regulatory_fee_rate,
+                brokerage_fee_rate,
+                transfer_fee_rate,
+                min_commission.to_string(),
+                stock_fee_id,
             ],
-        )?;
-        Ok(())
+        )
     }
-    pub fn delete(stock_fee_id: i32) -> Result<(), rusqlite::Error> {
-        let db_conn = get_db_state();
-        let conn = db_conn.lock().unwrap();
+    pub fn rename(
+        conn: &Connection,
+        stock_fee_id: i32,
+        stock_fee_name: &str,
+    ) -> Result<usize, rusqlite::Error> {
         conn.execute(
+            "UPDATE tb_stock_fee SET stock_fee_name = ?1 WHERE stock_fee_id = ?2",
+            params![stock_fee_name, stock_fee_id],
+        )
+    }
+    /// 设为默认费率，同时取消其他方案的默认标记
+    pub fn set_default(conn: &Connection, stock_fee_id: i32) -> Result<usize, rusqlite::Error> {
+        let updated = conn.execute(
+            "UPDATE tb_stock_fee SET is_default = 1 WHERE stock_fee_id = ?1",
+            [stock_fee_id],
+        )?;
+        if updated > 0 {
+            conn.execute(
+                "UPDATE tb_stock_fee SET is_default = 0 WHERE stock_fee_id <> ?1 AND is_default <> 0",
+                [stock_fee_id],
+            )?;
+        }
+        Ok(updated)
+    }
+    /// 删除费率方案，费率历史保留，之前的操作仍按当时的费率
+    pub fn delete(conn: &Connection, stock_fee_id: i32) -> Result<usize, rusqlite::Error> {
+        let deleted = conn.execute(
             "DELETE FROM tb_stock_fee WHERE stock_fee_id = ?1",
-            [stock_fee_id.to_string()],
+            [stock_fee_id],
         )?;
-        Ok(())
+        Ok(deleted)
     }
 }
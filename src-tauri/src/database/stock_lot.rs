@@ -0,0 +1,311 @@
+use crate::calc::lot::{split_position, split_price, Lot, LotBonus, LotSale};
+use crate::constant::action_type::ActionType;
+use crate::database::decimal::get_decimal;
+use crate::database::stock_action::StockActionRecord;
+use rusqlite::Connection;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+// 买入批次
+#[derive(Debug, Clone, Serialize)]
+pub struct StockLotRecord {
+    pub stock_lot_id: i32,
+    pub stock_id: i32,
+    pub stock_action_id: i32,        // 买入操作ID
+    pub buy_price: Decimal,          // 买入价格(按之后的拆合股折算)
+    pub buy_position: Decimal,       // 买入数量(买入时的数量)
+    pub remaining_position: Decimal, // 剩余数量(按之后的拆合股折算)
+    pub buy_fee: Decimal,            // 买入费用
+    pub source_lot_id: Option<i32>,  // 送转股的原批次，买入日期沿用原批次
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+// 批次卖出记录
+#[derive(Debug, Clone, Serialize)]
+pub struct StockLotSaleRecord {
+    pub stock_lot_sale_id: i32,
+    pub stock_id: i32,
+    pub stock_action_id: i32, // 卖出操作ID
+    pub stock_lot_id: i32,
+    pub sell_position: Decimal,   // 卖出数量
+    pub buy_price: Decimal,       // 买入价格
+    pub sell_price: Decimal,      // 卖出价格
+    pub realized_profit: Decimal, // 已实现盈亏(未扣费用)
+    pub created_at: String,
+}
+
+impl StockLotRecord {
+    /// 插入买入批次
+    pub fn insert_lot(
+        conn: &Connection,
+        stock_id: i32,
+        stock_action_id: i32,
+        buy_price: Decimal,
+        buy_position: Decimal,
+        buy_fee: Decimal,
+    ) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO tb_stock_lot (stock_id, stock_action_id, buy_price, buy_position, remaining_position, buy_fee) VALUES (?1, ?2, ?3, ?4, ?4, ?5)",
+            [
+                stock_id.to_string(),
+                stock_action_id.to_string(),
+                buy_price.to_string(),
+                buy_position.to_string(),
+                buy_fee.to_string(),
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// 插入送转股批次，0成本，不收取费用
+    pub fn insert_bonus_lot(
+        conn: &Connection,
+        stock_id: i32,
+        stock_action_id: i32,
+        bonus: &LotBonus,
+    ) -> Result<i64, rusqlite::Error> {
+        conn.execute(
+            "INSERT INTO tb_stock_lot (stock_id, stock_action_id, buy_price, buy_position, remaining_position, buy_fee, source_lot_id) VALUES (?1, ?2, '0', ?3, ?3, '0', ?4)",
+            [
+                stock_id.to_string(),
+                stock_action_id.to_string(),
+                bonus.bonus_position.to_string(),
+                bonus.source_lot_id.to_string(),
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// 查询股票的全部批次
+    pub fn get_lots_by_stock_id(
+        conn: &Connection,
+        stock_id: i32,
+    ) -> Result<Vec<StockLotRecord>, rusqlite::Error> {
+        let mut stmt = conn.prepare(
+            "SELECT stock_lot_id, stock_id, stock_action_id, buy_price, buy_position, remaining_position, buy_fee, created_at, updated_at, source_lot_id FROM tb_stock_lot WHERE stock_id = ? ORDER BY stock_lot_id ASC",
+        )?;
+        let lot_iter = stmt.query_map([stock_id], |row| {
+            Ok(StockLotRecord {
+                stock_lot_id: row.get(0)?,
+                stock_id: row.get(1)?,
+                stock_action_id: row.get(2)?,
+                buy_price: get_decimal(row, 3)?,
+                buy_position: get_decimal(row, 4)?,
+                remaining_position: get_decimal(row, 5)?,
+                buy_fee: get_decimal(row, 6)?,
+                source_lot_id: row.get(9)?,
+                created_at: row.get(7)?,
+                updated_at: row.get(8)?,
+            })
+        })?;
+        let mut lots = Vec::new();
+        for lot in lot_iter {
+            lots.push(lot?);
+        }
+        Ok(lots)
+    }
+
+    // 拆合股之前的买入价格：送转股批次为0，其余为买入操作的交易价格
+    fn buy_base_price(&self, bought: &StockActionRecord) -> Decimal {
+        match self.source_lot_id {
+            Some(_) => Decimal::ZERO,
+            None => bought.transaction_price,
+        }
+    }
+
+    /// 批次的买入价格和剩余数量，买入日期和持有期间的分红由操作记录补充
+    pub fn lot(&self) -> Lot {
+        Lot {
+            stock_lot_id: self.stock_lot_id,
+            buy_price: self.buy_price,
+            remaining_position: self.remaining_position,
+            buy_date: None,
+            dividend_per_share: Decimal::ZERO,
+        }
+    }
+
+    /// 记录卖出对批次的消耗
+    pub fn insert_sales(
+        conn: &Connection,
+        stock_id: i32,
+        stock_action_id: i32,
+        sales: &[LotSale],
+    ) -> Result<(), rusqlite::Error> {
+        for sale in sales {
+            let remaining: Decimal = conn.query_row(
+                "SELECT remaining_position FROM tb_stock_lot WHERE stock_lot_id = ?",
+                [sale.stock_lot_id],
+                |row| get_decimal(row, 0),
+            )?;
+            conn.execute(
+                "UPDATE tb_stock_lot SET remaining_position = ?1 WHERE stock_lot_id = ?2",
+                [
+                    (remaining - sale.sell_position).to_string(),
+                    sale.stock_lot_id.to_string(),
+                ],
+            )?;
+            conn.execute(
+                "INSERT INTO tb_stock_lot_sale (stock_id, stock_action_id, stock_lot_id, sell_position, buy_price, sell_price, realized_profit) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                [
+                    stock_id.to_string(),
+                    stock_action_id.to_string(),
+                    sale.stock_lot_id.to_string(),
+                    sale.sell_position.to_string(),
+                    sale.buy_price.to_string(),
+                    sale.sell_price.to_string(),
+                    sale.realized_profit.to_string(),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 拆合股：stock_action_id之前买入且还有剩余的批次，剩余数量和买入价格按比例折算
+    /// 买入数量保持原来的数量
+    pub fn split_lots(
+        conn: &Connection,
+        stock_id: i32,
+        stock_action_id: i32,
+        split_from: Decimal,
+        split_to: Decimal,
+    ) -> Result<(), rusqlite::Error> {
+        let lots = StockLotRecord::get_lots_by_stock_id(conn, stock_id)?;
+        for lot in lots.iter().filter(|lot| {
+            lot.stock_action_id < stock_action_id && lot.remaining_position > Decimal::ZERO
+        }) {
+            conn.execute(
+                "UPDATE tb_stock_lot SET buy_price = ?1, remaining_position = ?2 WHERE stock_lot_id = ?3",
+                [
+                    split_price(lot.buy_price, split_from, split_to).to_string(),
+                    split_position(lot.remaining_position, split_from, split_to).to_string(),
+                    lot.stock_lot_id.to_string(),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// 按买入操作的交易价格和之后的各次拆合股重算批次买入价格，actions为股票的操作记录(按先后)
+    /// 送转股批次为0成本
+    pub fn reprice_lots(
+        conn: &Connection,
+        stock_id: i32,
+        actions: &[StockActionRecord],
+    ) -> Result<(), rusqlite::Error> {
+        for lot in StockLotRecord::get_lots_by_stock_id(conn, stock_id)? {
+            let Some(index) = actions
+                .iter()
+                .position(|action| action.stock_action_id == lot.stock_action_id)
+            else {
+                continue;
+            };
+            let buy_price = actions[index + 1..]
+                .iter()
+                .filter(|action| ActionType::from(action.action) == ActionType::Split)
+                .fold(lot.buy_base_price(&actions[index]), |price, split| {
+                    split_price(price, split.split_from, split.split_to)
+                });
+            if buy_price != lot.buy_price {
+                conn.execute(
+                    "UPDATE tb_stock_lot SET buy_price = ?1 WHERE stock_lot_id = ?2",
+                    [buy_price.to_string(), lot.stock_lot_id.to_string()],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 查询股票的批次卖出记录
+    pub fn get_sales_by_stock_id(
+        conn: &Connection,
+        stock_id: i32,
+    ) -> Result<Vec<StockLotSaleRecord>, rusqlite::Error> {
+        let mut stmt = conn.prepare(
+            "SELECT stock_lot_sale_id, stock_id, stock_action_id, stock_lot_id, sell_position, buy_price, sell_price, realized_profit, created_at FROM tb_stock_lot_sale WHERE stock_id = ? ORDER BY stock_lot_sale_id ASC",
+        )?;
+        let sale_iter = stmt.query_map([stock_id], |row| {
+            Ok(StockLotSaleRecord {
+                stock_lot_sale_id: row.get(0)?,
+                stock_id: row.get(1)?,
+                stock_action_id: row.get(2)?,
+                stock_lot_id: row.get(3)?,
+                sell_position: get_decimal(row, 4)?,
+                buy_price: get_decimal(row, 5)?,
+                sell_price: get_decimal(row, 6)?,
+                realized_profit: get_decimal(row, 7)?,
+                created_at: row.get(8)?,
+            })
+        })?;
+        let mut sales = Vec::new();
+        for sale in sale_iter {
+            sales.push(sale?);
+        }
+        Ok(sales)
+    }
+
+    /// 撤销某次操作对批次的影响：买入删除批次，卖出恢复批次数量，拆合股反向折算
+    pub fn revert_action(conn: &Connection, stock_action_id: i32) -> Result<(), rusqlite::Error> {
+        let split = StockActionRecord::get_action_by_id(conn, stock_action_id)?
+            .filter(|action| ActionType::from(action.action) == ActionType::Split);
+        if let Some(split) = split {
+            // 剩余数量按相反的比例折算，买入价格按之前的操作重算，避免除不尽的误差
+            StockLotRecord::split_lots(
+                conn,
+                split.stock_id,
+                stock_action_id,
+                split.split_to,
+                split.split_from,
+            )?;
+            let actions: Vec<StockActionRecord> =
+                StockActionRecord::get_actions_by_stock_id(conn, split.stock_id)?
+                    .into_iter()
+                    .filter(|action| action.stock_action_id < stock_action_id)
+                    .collect();
+            StockLotRecord::reprice_lots(conn, split.stock_id, &actions)?;
+        }
+        let sales: Vec<(i32, Decimal)> = conn
+            .prepare(
+                "SELECT stock_lot_id, sell_position FROM tb_stock_lot_sale WHERE stock_action_id = ?",
+            )?
+            .query_map([stock_action_id], |row| {
+                Ok((row.get(0)?, get_decimal(row, 1)?))
+            })?
+            .collect::<Result<_, _>>()?;
+        for (stock_lot_id, sell_position) in sales {
+            let remaining: Decimal = conn.query_row(
+                "SELECT remaining_position FROM tb_stock_lot WHERE stock_lot_id = ?",
+                [stock_lot_id],
+                |row| get_decimal(row, 0),
+            )?;
+            conn.execute(
+                "UPDATE tb_stock_lot SET remaining_position = ?1 WHERE stock_lot_id = ?2",
+                [
+                    (remaining + sell_position).to_string(),
+                    stock_lot_id.to_string(),
+                ],
+            )?;
+        }
+        conn.execute(
+            "DELETE FROM tb_stock_lot_sale WHERE stock_action_id = ?",
+            [stock_action_id],
+        )?;
+        conn.execute(
+            "DELETE FROM tb_stock_lot WHERE stock_action_id = ?",
+            [stock_action_id],
+        )?;
+        Ok(())
+    }
+}
+
+impl StockLotSaleRecord {
+    pub fn sale(&self) -> LotSale {
+        LotSale {
+            stock_lot_id: self.stock_lot_id,
+            sell_position: self.sell_position,
+            buy_price: self.buy_price,
+            sell_price: self.sell_price,
+            realized_profit: self.realized_profit,
+        }
+    }
+}
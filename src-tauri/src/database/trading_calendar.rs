@@ -0,0 +1,41 @@
+use crate::calc::calendar::HolidayYears;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection};
+use std::collections::BTreeSet;
+
+// 导入的交易日历，每年一行
+pub struct TradingCalendarRecord;
+
+impl TradingCalendarRecord {
+    /// 查询导入的各年休市日
+    pub fn get_holidays(conn: &Connection) -> Result<HolidayYears, rusqlite::Error> {
+        let mut stmt = conn.prepare("SELECT year, holidays FROM tb_trading_calendar")?;
+        let year_iter = stmt.query_map([], |row| {
+            let year: i32 = row.get(0)?;
+            let holidays: String = row.get(1)?;
+            let holidays: BTreeSet<NaiveDate> = serde_json::from_str(&holidays).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, e.into())
+            })?;
+            Ok((year, holidays))
+        })?;
+        let mut years = HolidayYears::new();
+        for year in year_iter {
+            let (year, holidays) = year?;
+            years.insert(year, holidays);
+        }
+        Ok(years)
+    }
+
+    /// 保存导入的休市日，已导入的同一年被替换
+    pub fn save_holidays(conn: &Connection, years: &HolidayYears) -> Result<(), rusqlite::Error> {
+        for (year, holidays) in years {
+            let holidays = serde_json::to_string(holidays)
+                .map_err(|e| rusqlite::Error::ToSqlConversionFailure(e.into()))?;
+            conn.execute(
+                "INSERT OR REPLACE INTO tb_trading_calendar (year, holidays) VALUES (?1, ?2)",
+                params![year, holidays],
+            )?;
+        }
+        Ok(())
+    }
+}
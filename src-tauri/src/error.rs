@@ -0,0 +1,563 @@
+use rust_decimal::Decimal;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+// 出错的数据
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Entity {
+    Stock,  // 股票
+    Action, // 操作记录
+    Fee,    // 费率方案
+    Lot,    // 买入批次
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Entity::Stock => "股票",
+            Entity::Action => "操作记录",
+            Entity::Fee => "费率方案",
+            Entity::Lot => "批次",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// 命令返回的错误
+// 前端收到 {"code": "...", "message": "...", ...各错误的字段}，按code处理，message为中文提示
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppError {
+    // 数据不存在，id为空时表示按条件查找
+    NotFound {
+        entity: Entity,
+        id: Option<i32>,
+    },
+    InvalidPrice {
+        price: Decimal,
+    },
+    InvalidQuantity {
+        position: Decimal,
+    },
+    // 现金分红的每10股派息必须大于0
+    InvalidDividend {
+        cash_per_ten: Decimal,
+    },
+    // 按每10股送转股数计算，全部持仓送转不足1股
+    InvalidBonus {
+        shares_per_ten: Decimal,
+    },
+    // 减仓数量达到全部持仓，应平仓
+    OversellPosition {
+        position: Decimal,
+        total_position: Decimal,
+    },
+    // 指定批次的剩余数量不足
+    OversellLot {
+        stock_lot_id: i32,
+        position: Decimal,
+        remaining_position: Decimal,
+    },
+    // 指定的批次不存在或已卖完
+    LotUnavailable {
+        stock_lot_id: i32,
+    },
+    // 指定批次的数量合计与卖出数量不一致
+    LotMismatch {
+        selected_position: Decimal,
+        position: Decimal,
+    },
+    // 平仓数量为当时的全部持仓，不能修改
+    CloseQuantityFixed,
+    // 分红数量为除息时的全部持仓，不能修改
+    DividendQuantityFixed,
+    // 送转股数按送转时的全部持仓计算，不能修改
+    BonusQuantityFixed,
+    // 拆合股比例不能修改
+    SplitRatioFixed,
+    // 只有卖出可以指定批次
+    LotSelectionNotSell,
+    // 拆合股后批次数量position不是整数股
+    SplitFraction {
+        position: Decimal,
+        split_from: Decimal,
+        split_to: Decimal,
+    },
+    // 股票已平仓，不能再交易
+    StockClosed {
+        stock_id: i32,
+    },
+    // 数量不符合所属板块的整手规则：须不少于min_position，超出部分为step的整数倍
+    // odd_position为卖出时须一次卖出的零股，suggested_position为建议改成的数量
+    BoardLot {
+        position: Decimal,
+        min_position: Decimal,
+        step: Decimal,
+        odd_position: Option<Decimal>,
+        suggested_position: Option<Decimal>,
+    },
+    // 卖出数量超过可卖数量，当日买入的数量尚未交收(T+1)
+    PositionFrozen {
+        position: Decimal,
+        sellable_position: Decimal,
+        frozen_position: Decimal,
+    },
+    // 价格不是最小价格变动单位的整数倍
+    PriceTick {
+        price: Decimal,
+        tick: Decimal,
+    },
+    // 价格超出按前收盘价计算的涨跌幅限制
+    PriceLimit {
+        price: Decimal,
+        prev_close: Decimal,
+        limit_down: Decimal,
+        limit_up: Decimal,
+    },
+    // 时间格式错误，field为参数名
+    InvalidTime {
+        field: String,
+        value: String,
+    },
+    // 导入的休市安排中，date不属于所在的year
+    HolidayYearMismatch {
+        year: i32,
+        date: String,
+    },
+    EmptyName,
+    // 费率须不小于0且小于1，field为参数名
+    InvalidFeeRate {
+        field: String,
+        rate: Decimal,
+    },
+    InvalidMinCommission {
+        min_commission: Decimal,
+    },
+    // 查询费率历史时未指定费率方案或股票
+    RateOwnerRequired,
+    DefaultFeeUndeletable,
+    // 费率方案仍被stock_count只股票使用，不能删除
+    FeeInUse {
+        stock_fee_id: i32,
+        stock_count: usize,
+    },
+    FeeRateTableNotFound {
+        trade_date: String,
+    },
+    // 新费率的生效时间不晚于当前费率
+    ValidFromTooEarly {
+        valid_from: String,
+        current_from: String,
+    },
+    // 修改操作后重放第index(从1开始)条操作失败
+    ActionReplay {
+        index: usize,
+        error: Box<AppError>,
+    },
+    // 事件重放产生的ID与原来不一致
+    ReplayMismatch {
+        entity: Entity,
+        recorded: i32,
+        replayed: i32,
+    },
+    // 撤销、重做时重放事件失败
+    EventReplay {
+        event_id: i32,
+        event_type: String,
+        error: Box<AppError>,
+    },
+    Db {
+        detail: String,
+    },
+    Io {
+        operation: String, // 失败的操作，如"创建数据库目录"
+        detail: String,
+    },
+    Json {
+        detail: String,
+    },
+}
+
+impl AppError {
+    pub fn not_found(entity: Entity, id: i32) -> Self {
+        AppError::NotFound {
+            entity,
+            id: Some(id),
+        }
+    }
+
+    pub fn invalid_time(field: &str, value: &str) -> Self {
+        AppError::InvalidTime {
+            field: field.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    pub fn io(operation: &str, detail: impl fmt::Display) -> Self {
+        AppError::Io {
+            operation: operation.to_string(),
+            detail: detail.to_string(),
+        }
+    }
+
+    // 错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "NotFound",
+            AppError::InvalidPrice { .. } => "InvalidPrice",
+            AppError::InvalidQuantity { .. } => "InvalidQuantity",
+            AppError::InvalidDividend { .. } => "InvalidDividend",
+            AppError::InvalidBonus { .. } => "InvalidBonus",
+            AppError::OversellPosition { .. } => "OversellPosition",
+            AppError::OversellLot { .. } => "OversellLot",
+            AppError::LotUnavailable { .. } => "LotUnavailable",
+            AppError::LotMismatch { .. } => "LotMismatch",
+            AppError::CloseQuantityFixed => "CloseQuantityFixed",
+            AppError::DividendQuantityFixed => "DividendQuantityFixed",
+            AppError::BonusQuantityFixed => "BonusQuantityFixed",
+            AppError::SplitRatioFixed => "SplitRatioFixed",
+            AppError::LotSelectionNotSell => "LotSelectionNotSell",
+            AppError::SplitFraction { .. } => "SplitFraction",
+            AppError::StockClosed { .. } => "StockClosed",
+            AppError::BoardLot { .. } => "BoardLot",
+            AppError::PositionFrozen { .. } => "PositionFrozen",
+            AppError::PriceTick { .. } => "PriceTick",
+            AppError::PriceLimit { .. } => "PriceLimit",
+            AppError::InvalidTime { .. } => "InvalidTime",
+            AppError::HolidayYearMismatch { .. } => "HolidayYearMismatch",
+            AppError::EmptyName => "EmptyName",
+            AppError::InvalidFeeRate { .. } => "InvalidFeeRate",
+            AppError::InvalidMinCommission { .. } => "InvalidMinCommission",
+            AppError::RateOwnerRequired => "RateOwnerRequired",
+            AppError::DefaultFeeUndeletable => "DefaultFeeUndeletable",
+            AppError::FeeInUse { .. } => "FeeInUse",
+            AppError::FeeRateTableNotFound { .. } => "FeeRateTableNotFound",
+            AppError::ValidFromTooEarly { .. } => "ValidFromTooEarly",
+            AppError::ActionReplay { .. } => "ActionReplay",
+            AppError::ReplayMismatch { .. } => "ReplayMismatch",
+            AppError::EventReplay { .. } => "EventReplay",
+            AppError::Db { .. } => "Db",
+            AppError::Io { .. } => "Io",
+            AppError::Json { .. } => "Json",
+        }
+    }
+}
+
+// 中文提示
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::NotFound {
+                entity,
+                id: Some(id),
+            } => write!(f, "{entity}{id}不存在"),
+            AppError::NotFound { entity, id: None } => write!(f, "{entity}不存在"),
+            AppError::InvalidPrice { .. } => write!(f, "交易价格必须大于0"),
+            AppError::InvalidQuantity { .. } => write!(f, "交易数量必须大于0"),
+            AppError::InvalidDividend { .. } => write!(f, "每10股派息必须大于0"),
+            AppError::InvalidBonus { shares_per_ten } => {
+                write!(f, "每10股送转{shares_per_ten}股，全部持仓送转不足1股")
+            }
+            AppError::OversellPosition { total_position, .. } => {
+                write!(f, "减仓数量不能达到全部持仓{total_position}，请选择平仓")
+            }
+            AppError::OversellLot {
+                stock_lot_id,
+                remaining_position,
+                ..
+            } => write!(
+                f,
+                "批次{stock_lot_id}剩余数量不足，仅剩{remaining_position}"
+            ),
+            AppError::LotUnavailable { stock_lot_id } => {
+                write!(f, "批次{stock_lot_id}不存在或已卖完")
+            }
+            AppError::LotMismatch { .. } => write!(f, "批次数量与卖出数量不一致"),
+            AppError::CloseQuantityFixed => write!(f, "平仓数量为全部持仓，不能修改"),
+            AppError::DividendQuantityFixed => write!(f, "分红数量为全部持仓，不能修改"),
+            AppError::BonusQuantityFixed => write!(f, "送转股数按全部持仓计算，不能修改"),
+            AppError::SplitRatioFixed => write!(f, "拆合股比例不能修改"),
+            AppError::LotSelectionNotSell => write!(f, "只有卖出可以指定批次"),
+            AppError::SplitFraction {
+                position,
+                split_from,
+                split_to,
+            } => write!(f, "按{split_from}股变为{split_to}股拆合后，持仓{position}股不是整数股"),
+            AppError::StockClosed { .. } => write!(f, "股票已平仓，不能再交易"),
+            AppError::BoardLot {
+                min_position,
+                step,
+                odd_position,
+                suggested_position,
+                ..
+            } => {
+                let action = if odd_position.is_some() {
+                    "卖出"
+                } else {
+                    "买入"
+                };
+                if min_position == step {
+                    write!(f, "{action}数量须为{step}股的整数倍")?;
+                } else if *step == Decimal::ONE {
+                    write!(f, "{action}数量须不少于{min_position}股")?;
+                } else {
+                    write!(
+                        f,
+                        "{action}数量须不少于{min_position}股，超出部分为{step}股的整数倍"
+                    )?;
+                }
+                if let Some(odd_position) = odd_position.filter(|odd| !odd.is_zero()) {
+                    write!(f, "，零股{odd_position}股须一次卖出")?;
+                }
+                if let Some(suggested_position) = suggested_position {
+                    write!(f, "，可改为{suggested_position}股")?;
+                }
+                Ok(())
+            }
+            AppError::PositionFrozen {
+                sellable_position,
+                frozen_position,
+                ..
+            } => write!(
+                f,
+                "可卖数量为{sellable_position}，当日买入的{frozen_position}股下一交易日才能卖出"
+            ),
+            AppError::PriceTick { price, tick } => {
+                write!(f, "价格{price}须为最小变动单位{tick}元的整数倍")
+            }
+            AppError::PriceLimit {
+                price,
+                prev_close,
+                limit_down,
+                limit_up,
+            } => write!(
+                f,
+                "价格{price}超出涨跌幅限制，前收盘价{prev_close}，跌停价{limit_down}，涨停价{limit_up}"
+            ),
+            AppError::InvalidTime { value, .. } => write!(f, "时间格式错误：{value}"),
+            AppError::HolidayYearMismatch { year, date } => {
+                write!(f, "休市日{date}不属于{year}年")
+            }
+            AppError::EmptyName => write!(f, "费率名称不能为空"),
+            AppError::InvalidFeeRate { rate, .. } => {
+                write!(f, "费率须不小于0且小于1，当前为{rate}")
+            }
+            AppError::InvalidMinCommission { .. } => write!(f, "最低佣金不能小于0"),
+            AppError::RateOwnerRequired => write!(f, "请指定费率方案或股票"),
+            AppError::DefaultFeeUndeletable => write!(f, "默认费率不能删除"),
+            AppError::FeeInUse { stock_count, .. } => {
+                write!(f, "仍有{stock_count}只股票使用该费率方案，不能删除")
+            }
+            AppError::FeeRateTableNotFound { trade_date } => {
+                write!(f, "未找到{trade_date}适用的费率表")
+            }
+            AppError::ValidFromTooEarly { current_from, .. } => {
+                write!(f, "生效时间必须晚于当前费率的生效时间{current_from}")
+            }
+            AppError::ActionReplay { index, error } => {
+                write!(f, "第{index}条操作重算失败：{error}")
+            }
+            AppError::ReplayMismatch {
+                entity,
+                recorded,
+                replayed,
+            } => write!(
+                f,
+                "事件重放结果不一致：{entity}ID应为{recorded}，重放得到{replayed}"
+            ),
+            AppError::EventReplay {
+                event_id,
+                event_type,
+                error,
+            } => write!(f, "第{event_id}个事件({event_type})重放失败：{error}"),
+            AppError::Db { detail } => write!(f, "数据库错误：{detail}"),
+            AppError::Io { operation, detail } if detail.is_empty() => write!(f, "无法{operation}"),
+            AppError::Io { operation, detail } => write!(f, "无法{operation}：{detail}"),
+            AppError::Json { detail } => write!(f, "数据格式错误：{detail}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// 序列化为 {"code", "message", ...字段}
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            AppError::NotFound { entity, id } => {
+                map.serialize_entry("entity", entity)?;
+                map.serialize_entry("id", id)?;
+            }
+            AppError::InvalidPrice { price } => map.serialize_entry("price", price)?,
+            AppError::InvalidQuantity { position } => map.serialize_entry("position", position)?,
+            AppError::InvalidDividend { cash_per_ten } => {
+                map.serialize_entry("cash_per_ten", cash_per_ten)?
+            }
+            AppError::InvalidBonus { shares_per_ten } => {
+                map.serialize_entry("shares_per_ten", shares_per_ten)?
+            }
+            AppError::OversellPosition {
+                position,
+                total_position,
+            } => {
+                map.serialize_entry("position", position)?;
+                map.serialize_entry("total_position", total_position)?;
+            }
+            AppError::OversellLot {
+                stock_lot_id,
+                position,
+                remaining_position,
+            } => {
+                map.serialize_entry("stock_lot_id", stock_lot_id)?;
+                map.serialize_entry("position", position)?;
+                map.serialize_entry("remaining_position", remaining_position)?;
+            }
+            AppError::LotUnavailable { stock_lot_id } => {
+                map.serialize_entry("stock_lot_id", stock_lot_id)?
+            }
+            AppError::LotMismatch {
+                selected_position,
+                position,
+            } => {
+                map.serialize_entry("selected_position", selected_position)?;
+                map.serialize_entry("position", position)?;
+            }
+            AppError::StockClosed { stock_id } => map.serialize_entry("stock_id", stock_id)?,
+            AppError::BoardLot {
+                position,
+                min_position,
+                step,
+                odd_position,
+                suggested_position,
+            } => {
+                map.serialize_entry("position", position)?;
+                map.serialize_entry("min_position", min_position)?;
+                map.serialize_entry("step", step)?;
+                map.serialize_entry("odd_position", odd_position)?;
+                map.serialize_entry("suggested_position", suggested_position)?;
+            }
+            AppError::PositionFrozen {
+                position,
+                sellable_position,
+                frozen_position,
+            } => {
+                map.serialize_entry("position", position)?;
+                map.serialize_entry("sellable_position", sellable_position)?;
+                map.serialize_entry("frozen_position", frozen_position)?;
+            }
+            AppError::SplitFraction {
+                position,
+                split_from,
+                split_to,
+            } => {
+                map.serialize_entry("position", position)?;
+                map.serialize_entry("split_from", split_from)?;
+                map.serialize_entry("split_to", split_to)?;
+            }
+            AppError::PriceTick { price, tick } => {
+                map.serialize_entry("price", price)?;
+                map.serialize_entry("tick", tick)?;
+            }
+            AppError::PriceLimit {
+                price,
+                prev_close,
+                limit_down,
+                limit_up,
+            } => {
+                map.serialize_entry("price", price)?;
+                map.serialize_entry("prev_close", prev_close)?;
+                map.serialize_entry("limit_down", limit_down)?;
+                map.serialize_entry("limit_up", limit_up)?;
+            }
+            AppError::InvalidTime { field, value } => {
+                map.serialize_entry("field", field)?;
+                map.serialize_entry("value", value)?;
+            }
+            AppError::HolidayYearMismatch { year, date } => {
+                map.serialize_entry("year", year)?;
+                map.serialize_entry("date", date)?;
+            }
+            AppError::InvalidFeeRate { field, rate } => {
+                map.serialize_entry("field", field)?;
+                map.serialize_entry("rate", rate)?;
+            }
+            AppError::InvalidMinCommission { min_commission } => {
+                map.serialize_entry("min_commission", min_commission)?
+            }
+            AppError::FeeInUse {
+                stock_fee_id,
+                stock_count,
+            } => {
+                map.serialize_entry("stock_fee_id", stock_fee_id)?;
+                map.serialize_entry("stock_count", stock_count)?;
+            }
+            AppError::FeeRateTableNotFound { trade_date } => {
+                map.serialize_entry("trade_date", trade_date)?
+            }
+            AppError::ValidFromTooEarly {
+                valid_from,
+                current_from,
+            } => {
+                map.serialize_entry("valid_from", valid_from)?;
+                map.serialize_entry("current_from", current_from)?;
+            }
+            AppError::ActionReplay { index, error } => {
+                map.serialize_entry("index", index)?;
+                map.serialize_entry("error", error)?;
+            }
+            AppError::ReplayMismatch {
+                entity,
+                recorded,
+                replayed,
+            } => {
+                map.serialize_entry("entity", entity)?;
+                map.serialize_entry("recorded", recorded)?;
+                map.serialize_entry("replayed", replayed)?;
+            }
+            AppError::EventReplay {
+                event_id,
+                event_type,
+                error,
+            } => {
+                map.serialize_entry("event_id", event_id)?;
+                map.serialize_entry("event_type", event_type)?;
+                map.serialize_entry("error", error)?;
+            }
+            AppError::Db { detail } | AppError::Json { detail } => {
+                map.serialize_entry("detail", detail)?
+            }
+            AppError::Io { operation, detail } => {
+                map.serialize_entry("operation", operation)?;
+                map.serialize_entry("detail", detail)?;
+            }
+            AppError::CloseQuantityFixed
+            | AppError::DividendQuantityFixed
+            | AppError::BonusQuantityFixed
+            | AppError::SplitRatioFixed
+            | AppError::LotSelectionNotSell
+            | AppError::EmptyName
+            | AppError::RateOwnerRequired
+            | AppError::DefaultFeeUndeletable => {}
+        }
+        map.end()
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(err: rusqlite::Error) -> Self {
+        AppError::Db {
+            detail: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> Self {
+        AppError::Json {
+            detail: err.to_string(),
+        }
+    }
+}
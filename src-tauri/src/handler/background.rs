@@ -1,21 +1,24 @@
+use crate::error::AppError;
 use base64::{engine::general_purpose, Engine as _};
 use std::fs;
 use std::path::Path;
 use tauri::command;
 
 #[command]
-pub fn check_background_image() -> Result<serde_json::Value, String> {
+pub fn check_background_image() -> Result<serde_json::Value, AppError> {
     // 获取当前可执行文件所在目录
     let current_exe =
-        std::env::current_exe().map_err(|e| format!("无法获取当前可执行文件路径: {}", e))?;
+        std::env::current_exe().map_err(|e| AppError::io("获取当前可执行文件路径", e))?;
 
-    let exe_dir = current_exe.parent().ok_or("无法获取可执行文件目录")?;
+    let exe_dir = current_exe
+        .parent()
+        .ok_or(AppError::io("获取可执行文件目录", ""))?;
 
     // 创建backgrounds文件夹路径（与exe同目录）
     let backgrounds_dir = exe_dir.join("backgrounds");
     println!("{:?}", backgrounds_dir);
     // 确保目录存在
-    std::fs::create_dir_all(&backgrounds_dir).map_err(|e| format!("无法创建目录: {}", e))?;
+    std::fs::create_dir_all(&backgrounds_dir).map_err(|e| AppError::io("创建目录", e))?;
 
     // 支持的图片格式
     let image_formats = ["jpg", "jpeg", "png", "gif", "webp"];
@@ -27,8 +30,7 @@ pub fn check_background_image() -> Result<serde_json::Value, String> {
         println!("{:?}", image_path);
         if Path::new(&image_path).exists() {
             // 读取图片文件
-            let image_data =
-                fs::read(&image_path).map_err(|e| format!("无法读取图片文件: {}", e))?;
+            let image_data = fs::read(&image_path).map_err(|e| AppError::io("读取图片文件", e))?;
 
             // 转换为base64
             let base64_data = general_purpose::STANDARD.encode(&image_data);
@@ -0,0 +1,83 @@
+use crate::calc::calendar::{parse_holidays, TradingCalendar};
+use crate::calc::time::parse_time;
+use crate::database::db_connect::DatabaseState;
+use crate::database::repository::{CalendarRepo, Repository};
+use crate::error::AppError;
+use chrono::NaiveDate;
+use tauri::State;
+
+/// 导入交易日历文件，格式与内置的 data/trading_calendar.json 相同，返回导入的年份
+/// 导入的年份替换内置及之前导入的休市安排，其余年份不变
+#[tauri::command]
+pub fn handle_import_trading_calendar(
+    db: State<'_, DatabaseState>,
+    path: String,
+) -> Result<Vec<i32>, AppError> {
+    let content =
+        std::fs::read_to_string(&path).map_err(|e| AppError::io("读取交易日历文件", e))?;
+    let years = parse_holidays(&content)?;
+    db.transaction(|conn| {
+        conn.save_holidays(&years)?;
+        Ok(years.keys().copied().collect())
+    })
+}
+
+/// 获取某一年的休市日(不含周末)
+#[tauri::command]
+pub fn handle_get_trading_holidays(
+    db: State<'_, DatabaseState>,
+    year: i32,
+) -> Result<Vec<NaiveDate>, AppError> {
+    db.transaction(|conn| Ok(load_calendar(conn)?.holidays(year)))
+}
+
+/// 获取from(不含)至to(含)之间的交易日数，如持仓天数
+#[tauri::command]
+pub fn handle_get_trading_days_between(
+    db: State<'_, DatabaseState>,
+    from: String,
+    to: String,
+) -> Result<i64, AppError> {
+    let from = parse_date("from", &from)?;
+    let to = parse_date("to", &to)?;
+    db.transaction(|conn| Ok(load_calendar(conn)?.trading_days_between(from, to)))
+}
+
+/// 某一天是否交易日
+#[tauri::command]
+pub fn handle_is_trading_day(db: State<'_, DatabaseState>, date: String) -> Result<bool, AppError> {
+    let date = parse_date("date", &date)?;
+    db.transaction(|conn| Ok(load_calendar(conn)?.is_trading_day(date)))
+}
+
+/// 获取date之后的第一个交易日
+#[tauri::command]
+pub fn handle_get_next_trading_day(
+    db: State<'_, DatabaseState>,
+    date: String,
+) -> Result<NaiveDate, AppError> {
+    let date = parse_date("date", &date)?;
+    db.transaction(|conn| Ok(load_calendar(conn)?.next_trading_day(date)))
+}
+
+/// 获取date之前的最后一个交易日
+#[tauri::command]
+pub fn handle_get_previous_trading_day(
+    db: State<'_, DatabaseState>,
+    date: String,
+) -> Result<NaiveDate, AppError> {
+    let date = parse_date("date", &date)?;
+    db.transaction(|conn| Ok(load_calendar(conn)?.previous_trading_day(date)))
+}
+
+// 解析日期参数，带时间时取日期部分
+fn parse_date(field: &str, text: &str) -> Result<NaiveDate, AppError> {
+    parse_time(text)
+        .map(|time| time.date())
+        .ok_or_else(|| AppError::invalid_time(field, text))
+}
+
+// 当前的交易日历：内置的休市安排叠加导入的年份
+pub fn load_calendar(repo: &impl Repository) -> Result<TradingCalendar, AppError> {
+    Ok(TradingCalendar::with_imported(repo.get_holidays()?))
+}
@@ -0,0 +1,287 @@
+use crate::calc::lot::LotSelection;
+use crate::calc::time::{format_time, now, parse_time};
+use crate::calc::trade_engine::{Trade, TradeFeeRate};
+use crate::database::db_connect::DatabaseState;
+use crate::database::event::{EventRecord, SNAPSHOT_EVENT};
+use crate::database::repository::{EventRepo, Repository};
+use crate::error::{AppError, Entity};
+use crate::handler::stock::{update_stock_fee_rate, update_stock_sort};
+use crate::handler::stock_action::{
+    back_position, edit_action, open_position, recalculate_fees, trade_position,
+};
+use crate::handler::stock_action_info::save_action_info;
+use crate::handler::stock_fee::update_stock_fee;
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+// 操作事件，只保存用户输入及当时产生的ID，操作记录中的成本、持仓、费用等都由重放计算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum StockEvent {
+    // 事件表建立之前的数据
+    Snapshot,
+    // 开仓，fee_rate为开仓时按费率方案得到的股票费率
+    OpenPosition {
+        stock_id: i32,
+        stock_name: String,
+        stock_type: i32,
+        stock_fee_id: Option<i32>,
+        is_st: bool,
+        fee_rate: TradeFeeRate,
+        trade: Trade,
+    },
+    // 加仓、减仓、平仓
+    Trade {
+        stock_id: i32,
+        stock_action_id: i32,
+        trade: Trade,
+    },
+    // 回退最后一条操作
+    BackPosition {
+        stock_id: i32,
+    },
+    // 修改操作
+    EditAction {
+        stock_action_id: i32,
+        transaction_price: Option<Decimal>,
+        transaction_position: Option<i32>,
+        action_time: Option<String>,
+        lot_selections: Option<Vec<LotSelection>>,
+    },
+    // 保存操作时间及备注
+    SaveActionInfo {
+        stock_action_id: i32,
+        action_time: String,
+        action_info: String,
+    },
+    DeleteStock {
+        stock_id: i32,
+    },
+    UpdateStockSort {
+        list: Vec<i32>,
+    },
+    // 修改股票费率，valid_from为费率生效时间
+    UpdateStockFeeRate {
+        stock_id: i32,
+        fee_rate: TradeFeeRate,
+        valid_from: String,
+    },
+    UpdateStockSt {
+        stock_id: i32,
+        is_st: bool,
+    },
+    // 修改费率方案，market_rates依次为印花税、证管费、经手费、过户费，valid_from为费率生效时间
+    UpdateStockFee {
+        stock_fee_id: i32,
+        commission_fee_rate: Decimal,
+        market_rates: [Option<Decimal>; 4],
+        min_commission: Decimal,
+        valid_from: String,
+    },
+    RecalculateFees {
+        stock_id: Option<i32>,
+        repair_only: bool,
+    },
+}
+
+impl StockEvent {
+    // 事件类型，与payload中的type一致
+    fn event_type(&self) -> &'static str {
+        match self {
+            StockEvent::Snapshot => SNAPSHOT_EVENT,
+            StockEvent::OpenPosition { .. } => "OpenPosition",
+            StockEvent::Trade { .. } => "Trade",
+            StockEvent::BackPosition { .. } => "BackPosition",
+            StockEvent::EditAction { .. } => "EditAction",
+            StockEvent::SaveActionInfo { .. } => "SaveActionInfo",
+            StockEvent::DeleteStock { .. } => "DeleteStock",
+            StockEvent::UpdateStockSort { .. } => "UpdateStockSort",
+            StockEvent::UpdateStockFeeRate { .. } => "UpdateStockFeeRate",
+            StockEvent::UpdateStockSt { .. } => "UpdateStockSt",
+            StockEvent::UpdateStockFee { .. } => "UpdateStockFee",
+            StockEvent::RecalculateFees { .. } => "RecalculateFees",
+        }
+    }
+
+    // 重放事件，at为事件发生的时间(开仓、交易按当时生效的费率计算)
+    fn replay(&self, repo: &impl Repository, at: NaiveDateTime) -> Result<(), AppError> {
+        match self {
+            StockEvent::Snapshot => repo.restore_snapshot().map_err(AppError::from),
+            StockEvent::OpenPosition {
+                stock_id,
+                stock_name,
+                stock_type,
+                stock_fee_id,
+                is_st,
+                fee_rate,
+                trade,
+            } => {
+                let replayed_id = open_position(
+                    repo,
+                    stock_name,
+                    *stock_type,
+                    *stock_fee_id,
+                    *is_st,
+                    fee_rate,
+                    trade,
+                    at,
+                )?;
+                check_id(Entity::Stock, *stock_id, replayed_id)
+            }
+            StockEvent::Trade {
+                stock_id,
+                stock_action_id,
+                trade,
+            } => {
+                let (replayed_id, _) = trade_position(repo, *stock_id, trade, at)?;
+                check_id(Entity::Action, *stock_action_id, replayed_id)
+            }
+            StockEvent::BackPosition { stock_id } => back_position(repo, *stock_id),
+            StockEvent::EditAction {
+                stock_action_id,
+                transaction_price,
+                transaction_position,
+                action_time,
+                lot_selections,
+            } => edit_action(
+                repo,
+                *stock_action_id,
+                *transaction_price,
+                *transaction_position,
+                action_time.clone(),
+                lot_selections.clone(),
+            ),
+            StockEvent::SaveActionInfo {
+                stock_action_id,
+                action_time,
+                action_info,
+            } => save_action_info(repo, *stock_action_id, action_time, action_info),
+            StockEvent::DeleteStock { stock_id } => {
+                repo.delete_stock(*stock_id).map_err(AppError::from)
+            }
+            StockEvent::UpdateStockSort { list } => update_stock_sort(repo, list),
+            StockEvent::UpdateStockFeeRate {
+                stock_id,
+                fee_rate,
+                valid_from,
+            } => {
+                let valid_from = parse_time(valid_from)
+                    .ok_or_else(|| AppError::invalid_time("valid_from", valid_from))?;
+                update_stock_fee_rate(repo, *stock_id, fee_rate, valid_from)
+            }
+            StockEvent::UpdateStockSt { stock_id, is_st } => repo
+                .update_stock_st(*stock_id, *is_st)
+                .map_err(AppError::from),
+            StockEvent::UpdateStockFee {
+                stock_fee_id,
+                commission_fee_rate,
+                market_rates,
+                min_commission,
+                valid_from,
+            } => {
+                let valid_from = parse_time(valid_from)
+                    .ok_or_else(|| AppError::invalid_time("valid_from", valid_from))?;
+                update_stock_fee(
+                    repo,
+                    *stock_fee_id,
+                    *commission_fee_rate,
+                    *market_rates,
+                    *min_commission,
+                    valid_from,
+                )
+            }
+            StockEvent::RecalculateFees {
+                stock_id,
+                repair_only,
+            } => recalculate_fees(repo, *stock_id, *repair_only).map(|_| ()),
+        }
+    }
+}
+
+// 重放产生的ID必须与原来一致，否则之后引用该ID的事件会作用到别的数据上
+fn check_id(entity: Entity, recorded: i32, replayed: i32) -> Result<(), AppError> {
+    if recorded != replayed {
+        return Err(AppError::ReplayMismatch {
+            entity,
+            recorded,
+            replayed,
+        });
+    }
+    Ok(())
+}
+
+// 记录已完成的操作
+pub fn record_event(
+    repo: &impl Repository,
+    event: &StockEvent,
+    at: NaiveDateTime,
+) -> Result<(), AppError> {
+    let payload = serde_json::to_string(event)?;
+    repo.append_event(event.event_type(), &payload, &format_time(at))?;
+    Ok(())
+}
+
+// 重放一条事件记录
+fn replay_record(repo: &impl Repository, record: &EventRecord) -> Result<(), AppError> {
+    let event: StockEvent = serde_json::from_str(&record.payload)?;
+    let at = parse_time(&record.created_at).unwrap_or_else(now);
+    event
+        .replay(repo, at)
+        .map_err(|error| AppError::EventReplay {
+            event_id: record.event_id,
+            event_type: record.event_type.clone(),
+            error: Box::new(error),
+        })
+}
+
+// 清空股票及操作记录，按顺序重放全部未撤销的事件
+fn rebuild_projection(repo: &impl Repository) -> Result<(), AppError> {
+    repo.clear_projection()?;
+    for record in repo.get_active_events()? {
+        replay_record(repo, &record)?;
+    }
+    Ok(())
+}
+
+/// 获取操作事件列表
+#[tauri::command]
+pub fn handle_get_event_list(db: State<'_, DatabaseState>) -> Result<Vec<EventRecord>, AppError> {
+    db.transaction(|conn| conn.get_events().map_err(AppError::from))
+}
+
+/// 撤销最近的steps(默认1)个操作，返回实际撤销的个数
+/// 撤销后按顺序重放其余的事件，重放失败时整体回滚
+#[tauri::command]
+pub fn handle_undo(db: State<'_, DatabaseState>, steps: Option<u32>) -> Result<usize, AppError> {
+    db.transaction(|conn| undo(conn, steps.unwrap_or(1)))
+}
+
+// 撤销最近的steps个事件，返回实际撤销的个数
+pub fn undo(repo: &impl Repository, steps: u32) -> Result<usize, AppError> {
+    let events = repo.get_undo_events(steps.into())?;
+    if events.is_empty() {
+        return Ok(0);
+    }
+    let event_ids: Vec<i32> = events.iter().map(|event| event.event_id).collect();
+    repo.set_undone(&event_ids, true)?;
+    rebuild_projection(repo)?;
+    Ok(event_ids.len())
+}
+
+/// 重做最近撤销的steps(默认1)个操作，返回实际重做的个数，重放失败时整体回滚
+#[tauri::command]
+pub fn handle_redo(db: State<'_, DatabaseState>, steps: Option<u32>) -> Result<usize, AppError> {
+    db.transaction(|conn| redo(conn, steps.unwrap_or(1)))
+}
+
+// 按撤销的先后重做steps个事件，返回实际重做的个数
+pub fn redo(repo: &impl Repository, steps: u32) -> Result<usize, AppError> {
+    let events = repo.get_redo_events(steps.into())?;
+    for record in &events {
+        replay_record(repo, record)?;
+        repo.set_undone(&[record.event_id], false)?;
+    }
+    Ok(events.len())
+}
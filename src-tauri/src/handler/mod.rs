@@ -1,5 +1,9 @@
 pub mod background;
+pub mod calendar;
+pub mod event;
+pub mod setting;
 pub mod stock;
 pub mod stock_action;
 pub mod stock_action_info;
 pub mod stock_fee;
+pub mod stock_lot;
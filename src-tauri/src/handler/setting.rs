@@ -0,0 +1,67 @@
+use crate::database::db_connect::DatabaseState;
+use crate::database::db_path::{resolve_db_path, AppSettings, DbPathSource};
+use crate::error::AppError;
+use serde::Serialize;
+use std::path::Path;
+use tauri::{AppHandle, Manager, State};
+
+#[derive(Debug, Serialize)]
+pub struct DbPathInfo {
+    pub current_path: String, // 当前使用的数据库
+    pub next_path: String,    // 下次启动使用的数据库
+    pub source: DbPathSource, // 下次启动路径的来源
+}
+
+/// 获取数据库路径
+#[tauri::command]
+pub fn handle_get_db_path(
+    app: AppHandle,
+    db: State<'_, DatabaseState>,
+) -> Result<DbPathInfo, AppError> {
+    let data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| AppError::io("获取应用数据目录", e))?;
+    let config_dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| AppError::io("获取应用配置目录", e))?;
+    let (next_path, source) = resolve_db_path(&data_dir, &config_dir);
+    Ok(DbPathInfo {
+        current_path: db
+            .db_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_default(),
+        next_path: next_path.display().to_string(),
+        source,
+    })
+}
+
+/// 设置数据库路径，重启后生效；为空时恢复默认位置
+/// 新位置没有数据库时，把当前数据库复制过去
+#[tauri::command]
+pub fn handle_set_db_path(
+    app: AppHandle,
+    db: State<'_, DatabaseState>,
+    db_path: String,
+) -> Result<(), AppError> {
+    let config_dir = app
+        .path()
+        .app_config_dir()
+        .map_err(|e| AppError::io("获取应用配置目录", e))?;
+    let db_path = db_path.trim().to_string();
+    if !db_path.is_empty() && !Path::new(&db_path).exists() {
+        if let Some(parent) = Path::new(&db_path).parent() {
+            std::fs::create_dir_all(parent).map_err(|e| AppError::io("创建数据库目录", e))?;
+        }
+        let db_conn = db.get_connection();
+        let conn = db_conn.lock().unwrap();
+        conn.execute("VACUUM INTO ?1", [&db_path])
+            .map_err(|e| AppError::io("复制数据库", e))?;
+    }
+    let settings = AppSettings {
+        db_path: Some(db_path).filter(|p| !p.is_empty()),
+    };
+    settings.save(&config_dir)
+}
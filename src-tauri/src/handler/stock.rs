@@ -1,35 +1,201 @@
+use crate::calc::settlement::Sellable;
+use crate::calc::time::{format_time, now, parse_time};
+use crate::calc::trade_engine::{BreakEven, TradeEngine, TradeFeeRate};
+use crate::calc::validation::validate_fee_rates;
+use crate::database::db_connect::DatabaseState;
+use crate::database::fee_rate_history::{RateOwner, BEGINNING};
+use crate::database::repository::{FeeRepo, Repository, StockRepo};
 use crate::database::stock::StockRecord;
+use crate::error::{AppError, Entity};
+use crate::handler::event::{record_event, StockEvent};
+use crate::handler::stock_action::{load_position_state, load_sellable, recalculate_fees};
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tauri::State;
+
+// 股票信息，附带含费成本与保本价
+#[derive(Debug, Serialize)]
+pub struct StockInfo {
+    #[serde(flatten)]
+    pub stock: StockRecord,
+    #[serde(flatten)]
+    pub break_even: Option<BreakEven>, // 空仓时没有
+    #[serde(flatten)]
+    pub sellable: Sellable, // 当前可卖、冻结数量
+}
 
 /// 获取所有股票 - 适配Tauri
 #[tauri::command]
-pub fn handle_get_all_stocks() -> Result<Vec<StockRecord>, String> {
+pub fn handle_get_all_stocks(db: State<'_, DatabaseState>) -> Result<Vec<StockRecord>, AppError> {
     println!("get_all_stocks");
-    let list = StockRecord::get_all_stocks().map_err(|e| e.to_string())?;
+    let list = db.transaction(|conn| conn.get_all_stocks().map_err(AppError::from))?;
     println!("list: {:?}", list);
     Ok(list)
 }
 
-/// 获取股票信息
+/// 获取股票信息(含保本价)
 #[tauri::command]
-pub fn handle_get_stock_info(stock_id: i32) -> Result<Option<StockRecord>, String> {
+pub fn handle_get_stock_info(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+) -> Result<Option<StockInfo>, AppError> {
     println!("get_stock_info:{stock_id}");
-    let stock = StockRecord::get_stock_by_id(stock_id).map_err(|e| e.to_string())?;
-    Ok(stock)
+    db.transaction(|conn| {
+        let Some(stock) = conn.get_stock_by_id(stock_id)? else {
+            return Ok(None);
+        };
+        let fee_rate = conn.resolve_fee_rate(&stock, now())?;
+        // 没有操作记录时为空仓，其余错误直接返回
+        let state = match load_position_state(conn, stock_id) {
+            Ok(state) => Some(state),
+            Err(AppError::NotFound { .. }) => None,
+            Err(error) => return Err(error),
+        };
+        let break_even = state
+            .as_ref()
+            .and_then(|state| TradeEngine::new(fee_rate).break_even(state));
+        let total_position = state.map_or(Decimal::ZERO, |state| state.total_position);
+        let sellable = load_sellable(conn, &stock, total_position, now())?;
+        Ok(Some(StockInfo {
+            stock,
+            break_even,
+            sellable,
+        }))
+    })
 }
 /// 排序股票
 #[tauri::command]
-pub fn handle_update_stock_sort(list: Vec<i32>) -> Result<(), String> {
+pub fn handle_update_stock_sort(
+    db: State<'_, DatabaseState>,
+    list: Vec<i32>,
+) -> Result<(), AppError> {
     println!("handle_update_stock_sort:{:?}", list);
+    let at = now();
+    db.transaction(|conn| {
+        update_stock_sort(conn, &list)?;
+        record_event(conn, &StockEvent::UpdateStockSort { list }, at)
+    })
+}
+
+// 按列表顺序排序股票
+pub fn update_stock_sort(repo: &impl Repository, list: &[i32]) -> Result<(), AppError> {
     for (index, stock_id) in list.iter().enumerate() {
-        StockRecord::update_stock_sort(*stock_id, index as i32).map_err(|e| e.to_string())?;
+        repo.update_stock_sort(*stock_id, index as i32)?;
     }
     Ok(())
 }
 
-/// 股票删除
+/// 股票删除(可通过撤销恢复)
 #[tauri::command]
-pub fn handle_delete_stock(stock_id: i32) -> Result<(), String> {
+pub fn handle_delete_stock(db: State<'_, DatabaseState>, stock_id: i32) -> Result<(), AppError> {
     println!("handle_delete_stock:{stock_id}");
-    StockRecord::delete_stock(stock_id).map_err(|e| e.to_string())?;
+    let at = now();
+    db.transaction(|conn| {
+        conn.delete_stock(stock_id)?;
+        record_event(conn, &StockEvent::DeleteStock { stock_id }, at)
+    })
+}
+
+/// 修改股票费率，自valid_from(不传时为当前时间)起生效，之前的操作仍按原费率，
+/// 生效时间早于已有操作时这些操作按新费率重新计算费用，撤销时费率历史及费用随之撤销
+#[tauri::command]
+pub fn handle_update_stock_fee_rate(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    fee_rate: TradeFeeRate,
+    valid_from: Option<String>,
+) -> Result<(), AppError> {
+    validate_fee_rates(
+        fee_rate.commission_fee_rate,
+        [
+            Some(fee_rate.tax_fee_rate),
+            Some(fee_rate.regulatory_fee_rate),
+            Some(fee_rate.brokerage_fee_rate),
+            Some(fee_rate.transfer_fee_rate),
+        ],
+        fee_rate.min_commission,
+    )?;
+    let at = now();
+    let valid_from = match valid_from {
+        Some(valid_from) => parse_time(&valid_from)
+            .ok_or_else(|| AppError::invalid_time("valid_from", &valid_from))?,
+        None => now(),
+    };
+    db.transaction(|conn| {
+        update_stock_fee_rate(conn, stock_id, &fee_rate, valid_from)?;
+        record_event(
+            conn,
+            &StockEvent::UpdateStockFeeRate {
+                stock_id,
+                fee_rate,
+                valid_from: format_time(valid_from),
+            },
+            at,
+        )
+    })
+}
+
+// 修改股票费率并记录费率历史，按每次操作时生效的费率重新计算费用
+pub fn update_stock_fee_rate(
+    repo: &impl Repository,
+    stock_id: i32,
+    fee_rate: &TradeFeeRate,
+    valid_from: NaiveDateTime,
+) -> Result<(), AppError> {
+    let stock = repo
+        .get_stock_by_id(stock_id)?
+        .ok_or(AppError::not_found(Entity::Stock, stock_id))?;
+    // 没有费率方案的股票之前按股票记录的费率计算，该费率即将被覆盖，先记为最初的费率，之前的操作重算后不变
+    if stock.stock_fee_id.is_none()
+        && repo
+            .get_rate_history(RateOwner::Stock(stock_id))?
+            .is_empty()
+    {
+        let beginning =
+            parse_time(BEGINNING).ok_or_else(|| AppError::invalid_time("valid_from", BEGINNING))?;
+        record_stock_rate(repo, stock_id, &stock.fee_rate(), beginning)?;
+    }
+    record_stock_rate(repo, stock_id, fee_rate, valid_from)?;
+    repo.update_fee_rate(stock_id, fee_rate)?;
+    recalculate_fees(repo, Some(stock_id), false)?;
+    Ok(())
+}
+
+// 记录股票费率历史
+fn record_stock_rate(
+    repo: &impl Repository,
+    stock_id: i32,
+    fee_rate: &TradeFeeRate,
+    valid_from: NaiveDateTime,
+) -> Result<(), AppError> {
+    repo.record_rate_change(
+        RateOwner::Stock(stock_id),
+        fee_rate.commission_fee_rate,
+        [
+            Some(fee_rate.tax_fee_rate),
+            Some(fee_rate.regulatory_fee_rate),
+            Some(fee_rate.brokerage_fee_rate),
+            Some(fee_rate.transfer_fee_rate),
+        ],
+        fee_rate.min_commission,
+        valid_from,
+    )?;
     Ok(())
 }
+
+/// 修改股票的ST标记，影响之后交易的涨跌幅限制
+#[tauri::command]
+pub fn handle_update_stock_st(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    is_st: bool,
+) -> Result<(), AppError> {
+    let at = now();
+    db.transaction(|conn| {
+        conn.get_stock_by_id(stock_id)?
+            .ok_or(AppError::not_found(Entity::Stock, stock_id))?;
+        conn.update_stock_st(stock_id, is_st)?;
+        record_event(conn, &StockEvent::UpdateStockSt { stock_id, is_st }, at)
+    })
+}
@@ -1,318 +1,972 @@
-use crate::constant::{action_type::ActionType, stock_status::StockStatus};
+use crate::calc::calendar::TradingCalendar;
+use crate::calc::lot::{
+    apply_sales, bonus_lots, split_lots, split_position, split_price, Lot, LotBonus, LotSale,
+    LotSelection,
+};
+use crate::calc::money::round_price;
+use crate::calc::settlement::{sellable_position, Sellable};
+use crate::calc::time::{action_time, format_time, now, parse_time};
+use crate::calc::trade_engine::{
+    PositionState, Trade, TradeEngine, TradeFee, TradeFeeRate, TradeResult,
+};
+use crate::calc::validation::{
+    validate_quantity, validate_sellable, validate_status, validate_trade,
+};
+use crate::constant::lot_method::LotMethod;
+use crate::constant::{action_type::ActionType, stock_status::StockStatus, stock_type::StockType};
+use crate::database::db_connect::DatabaseState;
+use crate::database::repository::{ActionRepo, Repository};
 use crate::database::stock::StockRecord;
-use crate::database::stock_action::StockActionRecord;
+use crate::database::stock_action::{KeptLotChange, ReplayedAction, StockActionRecord};
+use crate::error::{AppError, Entity};
+use crate::handler::calendar::load_calendar;
+use crate::handler::event::{record_event, StockEvent};
+use crate::handler::stock_fee::{get_fee_or_default, open_fee_rate};
+use chrono::{NaiveDate, NaiveDateTime};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use tauri::State;
 
-fn calculate_safe_profit_rate(profit: f64, cost: f64, position: f64, current_price: f64) -> f64 {
-    println!("profit:{profit},cost:{cost},position:{position},current_price:{current_price}");
-    let profit_rate = if cost <= 0.0 {
-        // 如果成本为 0 或负，说明是"完全靠利润买出的免费股"，用市值当分母
-        profit / (current_price * position)
-    } else {
-        profit / (cost * position)
-    };
-    profit_rate
-}
-
-/// 获取股票操作记录
+/// 获取股票操作记录，adjusted为true时按之后的拆合股折算之前的价格和数量(复权显示)
 #[tauri::command]
-pub fn handle_get_action_list(stock_id: i32) -> Vec<StockActionRecord> {
+pub fn handle_get_action_list(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    adjusted: Option<bool>,
+) -> Result<Vec<StockActionRecord>, AppError> {
     println!("get_action_list: stock_id={}", stock_id);
-    let list = StockActionRecord::get_actions_by_stock_id(stock_id).unwrap_or_else(|e| {
-        println!("Error getting actions: {}", e);
-        Vec::new()
-    });
-    list
+    db.transaction(|conn| {
+        let actions = conn.get_actions_by_stock_id(stock_id)?;
+        Ok(if adjusted.unwrap_or(false) {
+            split_adjusted(actions)
+        } else {
+            actions
+        })
+    })
 }
 
-/// 开仓
+// 复权显示：从后往前累计拆合股比例，之前操作的价格、数量按拆合后的股数折算，只用于显示
+// 拆合股操作没有交易价格和数量，比例单独记录
+fn split_adjusted(mut actions: Vec<StockActionRecord>) -> Vec<StockActionRecord> {
+    let (mut split_from, mut split_to) = (Decimal::ONE, Decimal::ONE);
+    for action in actions.iter_mut().rev() {
+        action.current_price = round_price(split_price(action.current_price, split_from, split_to));
+        action.current_cost = round_price(split_price(action.current_cost, split_from, split_to));
+        action.total_position = split_position(action.total_position, split_from, split_to);
+        if ActionType::from(action.action) == ActionType::Split {
+            split_from *= action.split_from;
+            split_to *= action.split_to;
+            continue;
+        }
+        action.transaction_price =
+            round_price(split_price(action.transaction_price, split_from, split_to));
+        action.transaction_position =
+            split_position(action.transaction_position, split_from, split_to);
+    }
+    actions
+}
+
+/// 开仓，按当前生效的费率表叠加选定的费率方案记录股票费率，不传stock_fee_id时使用默认费率
+/// is_st: 是否ST股票；prev_close: 前收盘价，传入时校验涨跌幅限制
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn handle_open_position(
+    db: State<'_, DatabaseState>,
     stock_name: String,
     stock_type: i32,
-    current_price: f64,
-    transaction_price: f64,
+    current_price: Decimal,
+    transaction_price: Decimal,
     transaction_position: i32,
-    commission_fee_rate: f64,
-    tax_fee_rate: f64,
-    regulatory_fee_rate: f64,
-    brokerage_fee_rate: f64,
-    transfer_fee_rate: f64,
-) -> Result<(), String> {
+    stock_fee_id: Option<i32>,
+    is_st: Option<bool>,
+    prev_close: Option<Decimal>,
+) -> Result<(), AppError> {
+    let at = now();
+    let is_st = is_st.unwrap_or(false);
+    let trade = Trade::new(
+        ActionType::Open,
+        current_price,
+        transaction_price,
+        Decimal::from(transaction_position),
+    );
+    validate_trade(
+        StockType::from(stock_type),
+        is_st,
+        prev_close,
+        &PositionState::default(),
+        &trade,
+    )?;
+    db.transaction(|conn| {
+        let stock_fee = get_fee_or_default(conn, stock_fee_id)?;
+        let fee_rate = open_fee_rate(conn, &stock_fee, stock_type, at)?;
+        let stock_fee_id = Some(stock_fee.stock_fee_id);
+        let stock_id = open_position(
+            conn,
+            &stock_name,
+            stock_type,
+            stock_fee_id,
+            is_st,
+            &fee_rate,
+            &trade,
+            at,
+        )?;
+        record_event(
+            conn,
+            &StockEvent::OpenPosition {
+                stock_id,
+                stock_name,
+                stock_type,
+                stock_fee_id,
+                is_st,
+                fee_rate,
+                trade,
+            },
+            at,
+        )
+    })
+}
+
+// 开仓：插入股票及开仓操作，返回股票ID
+#[allow(clippy::too_many_arguments)]
+pub fn open_position(
+    repo: &impl Repository,
+    stock_name: &str,
+    stock_type: i32,
+    stock_fee_id: Option<i32>,
+    is_st: bool,
+    fee_rate: &TradeFeeRate,
+    trade: &Trade,
+    at: NaiveDateTime,
+) -> Result<i32, AppError> {
+    let trade = Trade {
+        trade_date: Some(at.date()),
+        ..trade.clone()
+    };
+    let result = TradeEngine::new(*fee_rate).calculate(&PositionState::default(), &trade)?;
+    let created_at = format_time(at);
     // 插入股票及其费率
-    let stock_id = StockRecord::insert_stock(
-        &stock_name,
+    let stock_id = repo.insert_stock(
+        stock_name,
         stock_type,
-        commission_fee_rate,
-        tax_fee_rate,
-        regulatory_fee_rate,
-        brokerage_fee_rate,
-        transfer_fee_rate,
-    )
-    .map_err(|e| e.to_string())?;
-
+        stock_fee_id,
+        is_st,
+        fee_rate,
+        &created_at,
+    )? as i32;
     // 记录开仓价格数据
-    let current_cost = transaction_price; // 开仓:成本 = 交易价格
-    let total_position = transaction_position as f64; // 开仓:总数 = 交易数量
-    let transaction_position_f64 = transaction_position as f64;
-    let transaction_value = transaction_price * transaction_position_f64;
-
-    // 计算各种费用
-    let mut transaction_commission_fee = transaction_value * commission_fee_rate;
-    if transaction_commission_fee < 5.0 {
-        // 佣金最少5元
-        transaction_commission_fee = 5.0;
-    }
-    let transaction_tax_fee = transaction_value * 0.0; // 买入时不收印花税
-    let transaction_regulatory_fee = transaction_value * regulatory_fee_rate;
-    let transaction_brokerage_fee = transaction_value * brokerage_fee_rate;
-    let transaction_transfer_fee = transaction_value * transfer_fee_rate;
-    let total_fee = transaction_commission_fee
-        + transaction_tax_fee
-        + transaction_regulatory_fee
-        + transaction_brokerage_fee
-        + transaction_transfer_fee;
-    //
-    let action_type = ActionType::Open as i32; // 操作类型
-                                               //利润
-    let profit = (current_price - current_cost) * total_position;
-    // 利润率
-    let profit_rate =
-        calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
-
-    StockActionRecord::insert_action(
-        stock_id as i32,
-        current_price,
-        current_cost,
-        total_position,
-        total_fee,
-        transaction_price,
-        transaction_position_f64,
-        transaction_commission_fee,
-        transaction_tax_fee,
-        transaction_regulatory_fee,
-        transaction_brokerage_fee,
-        transaction_transfer_fee,
-        action_type,
-        profit,
-        profit_rate,
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+    insert_action_with_lots(repo, stock_id, &result, &created_at)?;
+    Ok(stock_id)
 }
 
-// 加仓
+// 加仓，prev_close: 前收盘价，传入时校验涨跌幅限制
 #[tauri::command]
 pub fn handle_add_position(
+    db: State<'_, DatabaseState>,
     stock_id: i32,
-    current_price: f64,
-    transaction_price: f64,
+    current_price: Decimal,
+    transaction_price: Decimal,
     transaction_position: i32,
-) -> Result<(), String> {
+    prev_close: Option<Decimal>,
+) -> Result<(), AppError> {
     println!("add_stock:{stock_id},{current_price},{transaction_price},{transaction_position}");
-    let stock = StockRecord::get_stock_by_id(stock_id)
-        .map_err(|e| e.to_string())?
-        .ok_or("Stock not found")?;
-    let last_action = StockActionRecord::get_last_action(stock_id).map_err(|e| e.to_string())?;
-    //
-    let action_type = ActionType::AddPosition as i32;
-    // 本次各项费用
-    let mut transaction_commission_fee =
-        transaction_price * transaction_position as f64 * stock.commission_fee_rate;
-    if transaction_commission_fee < 5.0 {
-        // 佣金最少5元
-        transaction_commission_fee = 5.0;
-    }
-    let transaction_tax_fee = transaction_price * transaction_position as f64 * stock.tax_fee_rate;
-    let transaction_regulatory_fee =
-        transaction_price * transaction_position as f64 * stock.regulatory_fee_rate;
-    let transaction_brokerage_fee =
-        transaction_price * transaction_position as f64 * stock.brokerage_fee_rate;
-    let transaction_transfer_fee =
-        transaction_price * transaction_position as f64 * stock.transfer_fee_rate;
-    let total_fee = last_action.total_fee
-        + transaction_commission_fee
-        + transaction_tax_fee
-        + transaction_regulatory_fee
-        + transaction_brokerage_fee
-        + transaction_transfer_fee;
-    // 总仓位
-    let total_position = last_action.total_position + transaction_position as f64;
-    // 新成本价（加权平均）
-    let current_cost = (last_action.current_cost * last_action.total_position
-        + transaction_price * transaction_position as f64)
-        / total_position;
-    // 当前整体浮动利润
-    let profit = (current_price - current_cost) * total_position;
-    // 利润率：以当前总成本为基准
-    let profit_rate =
-        calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
-    // 插入操作记录
-    StockActionRecord::insert_action(
-        stock_id,
+    let trade = Trade::new(
+        ActionType::AddPosition,
         current_price,
-        current_cost,
-        total_position,
-        total_fee,
         transaction_price,
-        transaction_position as f64,
-        transaction_commission_fee,
-        transaction_tax_fee,
-        transaction_regulatory_fee,
-        transaction_brokerage_fee,
-        transaction_transfer_fee,
-        action_type,
-        profit,
-        profit_rate,
-    )
-    .map_err(|e| e.to_string())?;
+        Decimal::from(transaction_position),
+    );
+    trade_and_record(&db, stock_id, trade, prev_close)?;
     Ok(())
 }
 
-// 减仓
+// 减仓，返回本次卖出消耗的批次及其已实现盈亏
+// lot_method: 1-先进先出(默认) 2-后进先出 3-指定批次(lot_selections)
+// prev_close: 前收盘价，传入时校验涨跌幅限制
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub fn handle_reduce_position(
+    db: State<'_, DatabaseState>,
     stock_id: i32,
-    current_price: f64,
-    transaction_price: f64,
+    current_price: Decimal,
+    transaction_price: Decimal,
     transaction_position: i32,
-) -> Result<(), String> {
+    lot_method: Option<i32>,
+    lot_selections: Option<Vec<LotSelection>>,
+    prev_close: Option<Decimal>,
+) -> Result<Vec<LotSale>, AppError> {
     println!("reduce_stock:{stock_id},{current_price},{transaction_price},{transaction_position}");
-    let stock = StockRecord::get_stock_by_id(stock_id)
-        .map_err(|e| e.to_string())?
-        .ok_or("Stock not found")?;
-    let last_action = StockActionRecord::get_last_action(stock_id).map_err(|e| e.to_string())?;
-    if transaction_position >= last_action.total_position as i32 {
-        return Err("请选择平仓".to_string());
-    }
-    //
-    let action_type = ActionType::ReducePosition as i32;
-    // 本次各项费用
-    let mut transaction_commission_fee =
-        transaction_price * transaction_position as f64 * stock.commission_fee_rate;
-    if transaction_commission_fee < 5.0 {
-        // 佣金最少5元
-        transaction_commission_fee = 5.0;
-    }
-    let transaction_tax_fee = transaction_price * transaction_position as f64 * stock.tax_fee_rate;
-    let transaction_regulatory_fee =
-        transaction_price * transaction_position as f64 * stock.regulatory_fee_rate;
-    let transaction_brokerage_fee =
-        transaction_price * transaction_position as f64 * stock.brokerage_fee_rate;
-    let transaction_transfer_fee =
-        transaction_price * transaction_position as f64 * stock.transfer_fee_rate;
-    let total_fee = last_action.total_fee
-        + transaction_commission_fee
-        + transaction_tax_fee
-        + transaction_regulatory_fee
-        + transaction_brokerage_fee
-        + transaction_transfer_fee;
-    // 采用利润反向摊薄计算剩余成本的方式（券商常见写法之一）
-    // 新总手数
-    let total_position = last_action.total_position - transaction_position as f64;
-    // 摊薄成本价 = (原成本*原总手数-卖出价格*卖出手数) / 新总手数
-    let current_cost = (last_action.current_cost * last_action.total_position
-        - transaction_price * transaction_position as f64)
-        / total_position;
-    // 利润 = (当前价格 - 持仓成本) * 当前持仓数量
-    let profit = (current_price - current_cost) * total_position;
-    // 利润率 = 利润 / 当前成本
-    let profit_rate =
-        calculate_safe_profit_rate(profit, current_cost, total_position, current_price);
-    // 插入操作记录
-    StockActionRecord::insert_action(
-        stock_id,
+    let trade = Trade {
+        lot_method: lot_method.map(LotMethod::from).unwrap_or(LotMethod::Fifo),
+        lot_selections: lot_selections.unwrap_or_default(),
+        ..Trade::new(
+            ActionType::ReducePosition,
+            current_price,
+            transaction_price,
+            Decimal::from(transaction_position),
+        )
+    };
+    trade_and_record(&db, stock_id, trade, prev_close)
+}
+
+// 平仓，返回本次卖出消耗的批次及其已实现盈亏
+#[tauri::command]
+pub fn handle_close_position(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    current_price: Decimal,
+    lot_method: Option<i32>,
+    lot_selections: Option<Vec<LotSelection>>,
+) -> Result<Vec<LotSale>, AppError> {
+    println!("close_stock:{stock_id},{current_price}");
+    let trade = Trade {
+        lot_method: lot_method.map(LotMethod::from).unwrap_or(LotMethod::Fifo),
+        lot_selections: lot_selections.unwrap_or_default(),
+        ..Trade::new(
+            ActionType::Close,
+            current_price,
+            current_price,
+            Decimal::ZERO,
+        )
+    };
+    trade_and_record(&db, stock_id, trade, None)
+}
+
+/// 现金分红，cash_per_ten: 每10股派息(税前)，按全部持仓派息并摊薄成本，派息计入已实现收益
+#[tauri::command]
+pub fn handle_cash_dividend(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    current_price: Decimal,
+    cash_per_ten: Decimal,
+) -> Result<(), AppError> {
+    let trade = Trade::new(
+        ActionType::CashDividend,
         current_price,
-        current_cost,
-        total_position,
-        total_fee,
-        transaction_price,
-        transaction_position as f64,
-        transaction_commission_fee,
-        transaction_tax_fee,
-        transaction_regulatory_fee,
-        transaction_brokerage_fee,
-        transaction_transfer_fee,
-        action_type,
-        profit,
-        profit_rate,
-    )
-    .map_err(|e| e.to_string())?;
+        cash_per_ten / Decimal::TEN,
+        Decimal::ZERO,
+    );
+    trade_and_record(&db, stock_id, trade, None)?;
     Ok(())
 }
 
-// 平仓
+/// 送转股，shares_per_ten: 每10股送转股数，按全部持仓计算，不足1股的部分舍去
+/// 送转的股份按原批次分别作为0成本的批次，持股期限沿用原批次，持仓成本按新的总数量摊薄
 #[tauri::command]
-pub fn handle_close_position(stock_id: i32, current_price: f64) -> Result<(), String> {
-    println!("close_stock:{stock_id},{current_price}");
-    let stock = StockRecord::get_stock_by_id(stock_id)
-        .map_err(|e| e.to_string())?
-        .ok_or("Stock not found")?;
-    let last_action = StockActionRecord::get_last_action(stock_id).map_err(|e| e.to_string())?;
-    //
-    let action_type = ActionType::Close as i32;
-    // 本次各项费用
-    let mut transaction_commission_fee =
-        current_price * last_action.total_position as f64 * stock.commission_fee_rate;
-    if transaction_commission_fee < 5.0 {
-        // 佣金最少5元
-        transaction_commission_fee = 5.0;
-    }
-    let transaction_tax_fee =
-        current_price * last_action.total_position as f64 * stock.tax_fee_rate;
-    let transaction_regulatory_fee =
-        current_price * last_action.total_position as f64 * stock.regulatory_fee_rate;
-    let transaction_brokerage_fee =
-        current_price * last_action.total_position as f64 * stock.brokerage_fee_rate;
-    let transaction_transfer_fee =
-        current_price * last_action.total_position as f64 * stock.transfer_fee_rate;
-    let total_fee = last_action.total_fee
-        + transaction_commission_fee
-        + transaction_tax_fee
-        + transaction_regulatory_fee
-        + transaction_brokerage_fee
-        + transaction_transfer_fee;
-    // 成本价
-    let current_cost = 0.0;
-    // 总持仓变成0
-    let total_position = 0.0;
-    // 利润
-    let profit = (current_price - last_action.current_cost) * last_action.total_position;
-    let profit_rate = calculate_safe_profit_rate(
-        profit,
-        last_action.current_cost,
-        last_action.total_position,
+pub fn handle_bonus_shares(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    current_price: Decimal,
+    shares_per_ten: Decimal,
+) -> Result<(), AppError> {
+    let trade = Trade::new(
+        ActionType::BonusShares,
         current_price,
+        shares_per_ten / Decimal::TEN,
+        Decimal::ZERO,
     );
+    trade_and_record(&db, stock_id, trade, None)?;
+    Ok(())
+}
 
-    // 插入操作记录
-    StockActionRecord::insert_action(
-        stock_id,
+/// 配股，按配股价格和实际配售数量计入，不收取交易费用，不校验整手
+#[tauri::command]
+pub fn handle_rights_issue(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    current_price: Decimal,
+    transaction_price: Decimal,
+    transaction_position: i32,
+) -> Result<(), AppError> {
+    let trade = Trade::new(
+        ActionType::RightsIssue,
         current_price,
-        current_cost,
-        total_position,
-        total_fee,
+        transaction_price,
+        Decimal::from(transaction_position),
+    );
+    trade_and_record(&db, stock_id, trade, None)?;
+    Ok(())
+}
+
+/// 拆股、合股，每split_from股变为split_to股，如1拆2为1、2，10合1为10、1
+/// 持仓数量按比例折算，成本价反向折算，不产生盈亏和费用，拆合后各批次须为整数股
+#[tauri::command]
+pub fn handle_split_shares(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+    current_price: Decimal,
+    split_from: i32,
+    split_to: i32,
+) -> Result<(), AppError> {
+    let trade = Trade::split(
         current_price,
-        last_action.total_position,
-        transaction_commission_fee,
-        transaction_tax_fee,
-        transaction_regulatory_fee,
-        transaction_brokerage_fee,
-        transaction_transfer_fee,
-        action_type,
-        profit,
-        profit_rate,
+        Decimal::from(split_from),
+        Decimal::from(split_to),
+    );
+    trade_and_record(&db, stock_id, trade, None)?;
+    Ok(())
+}
+
+// 加仓、减仓、平仓、分红、配股、拆合股并记录事件
+fn trade_and_record(
+    db: &DatabaseState,
+    stock_id: i32,
+    trade: Trade,
+    prev_close: Option<Decimal>,
+) -> Result<Vec<LotSale>, AppError> {
+    let at = now();
+    db.transaction(|conn| record_trade(conn, stock_id, trade, prev_close, at))
+}
+
+// 校验并计算交易，记录事件，返回本次卖出消耗的批次
+fn record_trade(
+    repo: &impl Repository,
+    stock_id: i32,
+    trade: Trade,
+    prev_close: Option<Decimal>,
+    at: NaiveDateTime,
+) -> Result<Vec<LotSale>, AppError> {
+    check_trade(repo, stock_id, &trade, prev_close, at)?;
+    let (stock_action_id, sales) = trade_position(repo, stock_id, &trade, at)?;
+    record_event(
+        repo,
+        &StockEvent::Trade {
+            stock_id,
+            stock_action_id,
+            trade,
+        },
+        at,
+    )?;
+    Ok(sales)
+}
+
+// 校验用户发起的交易：股票存在且未平仓，交易符合当前持仓，卖出不超过at时的可卖数量
+// 重放事件时不再校验，记录事件前已校验过
+fn check_trade(
+    repo: &impl Repository,
+    stock_id: i32,
+    trade: &Trade,
+    prev_close: Option<Decimal>,
+    at: NaiveDateTime,
+) -> Result<(), AppError> {
+    let stock = repo
+        .get_stock_by_id(stock_id)?
+        .ok_or(AppError::not_found(Entity::Stock, stock_id))?;
+    validate_status(stock_id, StockStatus::from(stock.status))?;
+    let state = load_position_state(repo, stock_id)?;
+    let actions = repo.get_actions_by_stock_id(stock_id)?;
+    validate_position_trade(
+        &load_calendar(repo)?,
+        &stock,
+        &actions,
+        &state,
+        trade,
+        prev_close,
+        at,
+    )
+}
+
+// 校验交易符合持仓状态state(价格、数量、整手规则等)，卖出不超过at时的可卖数量
+// actions为该交易之前的操作
+fn validate_position_trade(
+    calendar: &TradingCalendar,
+    stock: &StockRecord,
+    actions: &[StockActionRecord],
+    state: &PositionState,
+    trade: &Trade,
+    prev_close: Option<Decimal>,
+    at: NaiveDateTime,
+) -> Result<(), AppError> {
+    validate_trade(
+        StockType::from(stock.stock_type),
+        stock.is_st,
+        prev_close,
+        state,
+        trade,
+    )?;
+    if !trade.action.is_sell() {
+        return Ok(());
+    }
+    let position = match trade.action {
+        ActionType::Close => state.total_position,
+        _ => trade.transaction_position,
+    };
+    validate_sellable(
+        position,
+        &sellable_after(calendar, stock, actions, state.total_position, at),
+    )
+}
+
+// 股票在at时的可卖数量，按各次买入的操作时间及交易日历判断是否已交收
+pub fn load_sellable(
+    repo: &impl Repository,
+    stock: &StockRecord,
+    total_position: Decimal,
+    at: NaiveDateTime,
+) -> Result<Sellable, AppError> {
+    Ok(sellable_after(
+        &load_calendar(repo)?,
+        stock,
+        &repo.get_actions_by_stock_id(stock.stock_id)?,
+        total_position,
+        at,
+    ))
+}
+
+// actions之后at时的可卖数量
+fn sellable_after(
+    calendar: &TradingCalendar,
+    stock: &StockRecord,
+    actions: &[StockActionRecord],
+    total_position: Decimal,
+    at: NaiveDateTime,
+) -> Sellable {
+    let mut buys: Vec<(NaiveDate, Decimal)> = Vec::new();
+    for action in actions {
+        match ActionType::from(action.action) {
+            // 送转股、配股到账即可卖出，只有买入需等待交收
+            ActionType::Open | ActionType::AddPosition => {
+                if let Some(time) = action_time(&action.action_time, &action.created_at) {
+                    buys.push((time.date(), action.transaction_position));
+                }
+            }
+            // 拆合股之前的买入按拆合后的股数冻结
+            ActionType::Split => {
+                for (_, position) in &mut buys {
+                    *position = split_position(*position, action.split_from, action.split_to);
+                }
+            }
+            _ => {}
+        }
+    }
+    sellable_position(
+        calendar,
+        StockType::from(stock.stock_type),
+        &buys,
+        total_position,
+        at.date(),
     )
-    .map_err(|e| e.to_string())?;
-    StockRecord::update_stock_status(stock_id, StockStatus::CLOSE as i32)
-        .map_err(|e| e.to_string())?;
+}
+
+// 按at时生效的股票费率和当前持仓状态计算，插入操作记录，平仓时修改股票状态
+// 返回操作ID和本次卖出消耗的批次
+pub fn trade_position(
+    repo: &impl Repository,
+    stock_id: i32,
+    trade: &Trade,
+    at: NaiveDateTime,
+) -> Result<(i32, Vec<LotSale>), AppError> {
+    let stock = repo
+        .get_stock_by_id(stock_id)?
+        .ok_or(AppError::not_found(Entity::Stock, stock_id))?;
+    let state = load_position_state(repo, stock_id)?;
+    let fee_rate = repo.resolve_fee_rate(&stock, at)?;
+    let trade = Trade {
+        trade_date: Some(at.date()),
+        ..trade.clone()
+    };
+    let result = TradeEngine::new(fee_rate).calculate(&state, &trade)?;
+    let stock_action_id = insert_action_with_lots(repo, stock_id, &result, &format_time(at))?;
+    if trade.action == ActionType::Close {
+        repo.update_stock_status(stock_id, StockStatus::CLOSE as i32)?;
+    }
+    Ok((stock_action_id, result.lot_sales))
+}
+
+// 当前持仓状态：最后一次操作 + 累计已实现盈亏 + 持有中的批次
+pub fn load_position_state(
+    repo: &impl Repository,
+    stock_id: i32,
+) -> Result<PositionState, AppError> {
+    let actions = repo.get_actions_by_stock_id(stock_id)?;
+    let last_action = actions.last().ok_or(AppError::NotFound {
+        entity: Entity::Action,
+        id: None,
+    })?;
+    Ok(PositionState {
+        current_cost: last_action.current_cost,
+        total_position: last_action.total_position,
+        total_fee: last_action.total_fee,
+        total_realized_profit: actions.iter().map(|a| a.realized_profit).sum(),
+        lots: load_open_lots(repo, stock_id, &actions)?,
+    })
+}
+
+// 持有中的批次，按买入操作补充买入日期和之后每股所得的现金分红(按之后的拆合股折算)
+// 送转股批次的买入日期为原批次的买入日期
+fn load_open_lots(
+    repo: &impl Repository,
+    stock_id: i32,
+    actions: &[StockActionRecord],
+) -> Result<Vec<Lot>, AppError> {
+    let records = repo.get_lots_by_stock_id(stock_id)?;
+    let action_date = |stock_action_id: i32| {
+        actions
+            .iter()
+            .find(|action| action.stock_action_id == stock_action_id)
+            .and_then(|action| action_time(&action.action_time, &action.created_at))
+            .map(|time| time.date())
+    };
+    Ok(records
+        .iter()
+        .filter(|lot| lot.remaining_position > Decimal::ZERO)
+        .map(|record| {
+            let Some(index) = actions
+                .iter()
+                .position(|action| action.stock_action_id == record.stock_action_id)
+            else {
+                return record.lot();
+            };
+            let mut bought = record;
+            while let Some(source) = bought
+                .source_lot_id
+                .and_then(|id| records.iter().find(|lot| lot.stock_lot_id == id))
+            {
+                bought = source;
+            }
+            Lot {
+                buy_date: action_date(bought.stock_action_id),
+                dividend_per_share: actions[index + 1..].iter().fold(
+                    Decimal::ZERO,
+                    |dividend, action| match ActionType::from(action.action) {
+                        ActionType::CashDividend => dividend + action.transaction_price,
+                        ActionType::Split => {
+                            split_price(dividend, action.split_from, action.split_to)
+                        }
+                        _ => dividend,
+                    },
+                ),
+                ..record.lot()
+            }
+        })
+        .collect())
+}
+
+// 插入操作记录：买入、配股新增批次，送转按原批次新增批次，卖出扣减批次，返回操作ID
+fn insert_action_with_lots(
+    repo: &impl Repository,
+    stock_id: i32,
+    result: &TradeResult,
+    created_at: &str,
+) -> Result<i32, AppError> {
+    let stock_action_id = repo.insert_action(stock_id, result, created_at)? as i32;
+    match ActionType::from(result.action) {
+        ActionType::Open | ActionType::AddPosition | ActionType::RightsIssue => {
+            repo.insert_lot(
+                stock_id,
+                stock_action_id,
+                result.transaction_price,
+                result.transaction_position,
+                result.transaction_fee(),
+            )?;
+        }
+        ActionType::BonusShares => {
+            for bonus in &result.lot_bonuses {
+                repo.insert_bonus_lot(stock_id, stock_action_id, bonus)?;
+            }
+        }
+        ActionType::ReducePosition | ActionType::Close => {
+            repo.insert_sales(stock_id, stock_action_id, &result.lot_sales)?;
+        }
+        ActionType::Split => {
+            repo.split_lots(
+                stock_id,
+                stock_action_id,
+                result.split_from,
+                result.split_to,
+            )?;
+        }
+        ActionType::CashDividend => {}
+    }
+    Ok(stock_action_id)
+}
+
+// 回退(可通过撤销恢复)
+#[tauri::command]
+pub fn handle_back_position(db: State<'_, DatabaseState>, stock_id: i32) -> Result<(), AppError> {
+    let at = now();
+    db.transaction(|conn| {
+        back_position(conn, stock_id)?;
+        record_event(conn, &StockEvent::BackPosition { stock_id }, at)
+    })
+}
+
+// 删除最后一条操作并恢复其批次
+pub fn back_position(repo: &impl Repository, stock_id: i32) -> Result<(), AppError> {
+    let last_action = repo.get_last_action(stock_id)?.ok_or(AppError::NotFound {
+        entity: Entity::Action,
+        id: None,
+    })?;
+    repo.revert_action(last_action.stock_action_id)?;
+    repo.delete_last_action(stock_id)?;
+    repo.update_stock_status(stock_id, StockStatus::OPEN as i32)?;
     Ok(())
 }
 
-// 回退
+/// 修改任意一条操作的交易价格、数量或操作时间，并按计算引擎重放该操作及之后的全部操作
+/// 平仓数量为当时的全部持仓，不能修改；修改平仓价格时当时价格一并修改
+/// 修改后的操作按开仓、加仓、减仓时的规则校验(最小价格变动、整手、当时的可卖数量)
+/// 分红、送转的transaction_price为每10股派息、送转股数，与现金分红、送转股命令一致
+/// lot_selections: 重新指定该次卖出的批次，不传时沿用原来卖出的批次(修改卖出数量时须重新指定)
 #[tauri::command]
-pub fn handle_back_position(stock_id: i32) -> Result<(), String> {
-    StockActionRecord::delete_last_action(stock_id).map_err(|e| e.to_string())?;
-    StockRecord::update_stock_status(stock_id, StockStatus::OPEN as i32)
-        .map_err(|e| e.to_string())?;
+pub fn handle_edit_action(
+    db: State<'_, DatabaseState>,
+    stock_action_id: i32,
+    transaction_price: Option<Decimal>,
+    transaction_position: Option<i32>,
+    action_time: Option<String>,
+    lot_selections: Option<Vec<LotSelection>>,
+) -> Result<(), AppError> {
+    let at = now();
+    db.transaction(|conn| {
+        edit_action(
+            conn,
+            stock_action_id,
+            transaction_price,
+            transaction_position,
+            action_time.clone(),
+            lot_selections.clone(),
+        )?;
+        record_event(
+            conn,
+            &StockEvent::EditAction {
+                stock_action_id,
+                transaction_price,
+                transaction_position,
+                action_time,
+                lot_selections,
+            },
+            at,
+        )
+    })
+}
+
+// 修改操作并重放该操作及之后的全部操作
+pub fn edit_action(
+    repo: &impl Repository,
+    stock_action_id: i32,
+    transaction_price: Option<Decimal>,
+    transaction_position: Option<i32>,
+    action_time: Option<String>,
+    lot_selections: Option<Vec<LotSelection>>,
+) -> Result<(), AppError> {
+    let target = repo
+        .get_action_by_id(stock_action_id)?
+        .ok_or(AppError::not_found(Entity::Action, stock_action_id))?;
+    let action_type = ActionType::from(target.action);
+    // 分红、送转与现金分红、送转股命令一样按每10股输入，记录为每股
+    let transaction_price = match action_type {
+        ActionType::CashDividend | ActionType::BonusShares => {
+            transaction_price.map(|per_ten| per_ten / Decimal::TEN)
+        }
+        _ => transaction_price,
+    };
+    if let Some(position) = transaction_position {
+        validate_quantity(Decimal::from(position))?;
+    }
+    if let Some(time) = action_time
+        .as_deref()
+        .filter(|time| parse_time(time).is_none())
+    {
+        return Err(AppError::invalid_time("action_time", time));
+    }
+    let is_close = action_type == ActionType::Close;
+    if action_type == ActionType::Split
+        && (transaction_price.is_some() || transaction_position.is_some())
+    {
+        return Err(AppError::SplitRatioFixed);
+    }
+    if lot_selections.is_some() && !action_type.is_sell() {
+        return Err(AppError::LotSelectionNotSell);
+    }
+    if transaction_position.is_some() {
+        match action_type {
+            ActionType::Close => return Err(AppError::CloseQuantityFixed),
+            ActionType::CashDividend => return Err(AppError::DividendQuantityFixed),
+            ActionType::BonusShares => return Err(AppError::BonusQuantityFixed),
+            _ => {}
+        }
+    }
+
+    let stock = repo
+        .get_stock_by_id(target.stock_id)?
+        .ok_or(AppError::not_found(Entity::Stock, target.stock_id))?;
+    let mut actions = repo.get_actions_by_stock_id(stock.stock_id)?;
+    let from = actions
+        .iter()
+        .position(|action| action.stock_action_id == stock_action_id)
+        .ok_or(AppError::not_found(Entity::Action, stock_action_id))?;
+    let action = &mut actions[from];
+    if let Some(price) = transaction_price {
+        action.transaction_price = price;
+        if is_close {
+            action.current_price = price;
+        }
+    }
+    if let Some(position) = transaction_position {
+        action.transaction_position = Decimal::from(position);
+    }
+    if let Some(time) = action_time {
+        action.action_time = time;
+    }
+    let (kept, replayed) = replay_actions(repo, &stock, &actions, from, lot_selections)?;
+    repo.replace_history(stock.stock_id, &kept, &replayed)?;
     Ok(())
 }
+
+// 从第from条操作开始按计算引擎重放，之前的操作保持原记录，只用于恢复持仓状态
+// 卖出沿用原来卖出的批次(第from条传入selections时按selections)，批次对不上时返回错误，
+// 不自动改选批次，以免改变用户选定的批次及已实现盈亏
+// 返回之前操作的卖出批次和重放后的操作
+#[allow(clippy::type_complexity)]
+fn replay_actions(
+    repo: &impl Repository,
+    stock: &StockRecord,
+    actions: &[StockActionRecord],
+    from: usize,
+    selections: Option<Vec<LotSelection>>,
+) -> Result<(Vec<KeptLotChange>, Vec<ReplayedAction>), AppError> {
+    let lots = repo.get_lots_by_stock_id(stock.stock_id)?;
+    // 买入、配股的批次，送转股的批次按原批次查找
+    let lot_ids: HashMap<i32, i32> = lots
+        .iter()
+        .filter(|lot| lot.source_lot_id.is_none())
+        .map(|lot| (lot.stock_action_id, lot.stock_lot_id))
+        .collect();
+    let sales = repo.get_sales_by_stock_id(stock.stock_id)?;
+    let calendar = load_calendar(repo)?;
+
+    let mut state = PositionState::default();
+    let mut kept = Vec::new();
+    let mut replayed = Vec::new();
+    for (index, action) in actions.iter().enumerate() {
+        let action_type = ActionType::from(action.action);
+        let recorded_sales: Vec<LotSale> = sales
+            .iter()
+            .filter(|sale| sale.stock_action_id == action.stock_action_id)
+            .map(|sale| sale.sale())
+            .collect();
+        let at = action_time(&action.action_time, &action.created_at).unwrap_or_else(now);
+        let (lot_sales, lot_bonuses, price, position) = if index < from {
+            state.current_cost = action.current_cost;
+            state.total_position = action.total_position;
+            state.total_fee = action.total_fee;
+            state.total_realized_profit += action.realized_profit;
+            if action_type.is_sell() {
+                kept.push(KeptLotChange::Sales {
+                    stock_action_id: action.stock_action_id,
+                    sales: recorded_sales.clone(),
+                });
+            } else if action_type == ActionType::Split {
+                kept.push(KeptLotChange::Split {
+                    stock_action_id: action.stock_action_id,
+                    split_from: action.split_from,
+                    split_to: action.split_to,
+                });
+            }
+            let recorded_bonuses = lots
+                .iter()
+                .filter(|lot| lot.stock_action_id == action.stock_action_id)
+                .filter_map(|lot| {
+                    Some(LotBonus {
+                        source_lot_id: lot.source_lot_id?,
+                        bonus_position: lot.buy_position,
+                    })
+                })
+                .filter(|bonus| bonus.bonus_position > Decimal::ZERO)
+                .collect();
+            (
+                recorded_sales,
+                recorded_bonuses,
+                action.transaction_price,
+                action.transaction_position,
+            )
+        } else {
+            let lot_selections = match &selections {
+                Some(selections) if index == from => selections.clone(),
+                _ => recorded_sales
+                    .iter()
+                    .map(|sale| LotSelection {
+                        stock_lot_id: sale.stock_lot_id,
+                        position: sale.sell_position,
+                    })
+                    .collect(),
+            };
+            let trade = Trade {
+                split_from: action.split_from,
+                split_to: action.split_to,
+                lot_method: LotMethod::Specific,
+                lot_selections,
+                trade_date: Some(at.date()),
+                ..Trade::new(
+                    action_type,
+                    action.current_price,
+                    action.transaction_price,
+                    action.transaction_position,
+                )
+            };
+            // 重放的操作与用户发起时一样校验整手、零股、最小价格变动及当时的可卖数量，
+            // 没有前收盘价，不校验涨跌幅；修改的操作直接返回校验错误，之后的操作标明第几条
+            validate_position_trade(
+                &calendar,
+                stock,
+                &actions[..index],
+                &state,
+                &trade,
+                None,
+                at,
+            )
+            .map_err(|error| {
+                if index == from {
+                    error
+                } else {
+                    AppError::ActionReplay {
+                        index: index + 1,
+                        error: Box::new(error),
+                    }
+                }
+            })?;
+            // 按操作时生效的费率计算
+            let fee_rate = repo.resolve_fee_rate(stock, at)?;
+            let result = TradeEngine::new(fee_rate)
+                .calculate(&state, &trade)
+                .map_err(|error| AppError::ActionReplay {
+                    index: index + 1,
+                    error: Box::new(error),
+                })?;
+            state.current_cost = result.current_cost;
+            state.total_position = result.total_position;
+            state.total_fee = result.total_fee;
+            state.total_realized_profit += result.realized_profit;
+            let lot_change = (
+                result.lot_sales.clone(),
+                result.lot_bonuses.clone(),
+                result.transaction_price,
+                result.transaction_position,
+            );
+            replayed.push(ReplayedAction {
+                stock_action_id: action.stock_action_id,
+                action_time: action.action_time.clone(),
+                result,
+            });
+            lot_change
+        };
+        // 批次：买入、送转、配股新增，卖出扣减，分红计入持有中的批次，拆合股按比例折算
+        if action_type.is_sell() {
+            state.lots = apply_sales(&state.lots, &lot_sales);
+        } else if action_type == ActionType::Split {
+            state.lots = split_lots(&state.lots, action.split_from, action.split_to);
+        } else if action_type == ActionType::CashDividend {
+            for lot in &mut state.lots {
+                lot.dividend_per_share += price;
+            }
+        } else if action_type == ActionType::BonusShares {
+            // 送转股按原批次新增批次，重放后新分到股份的原批次补建批次记录，数量由replace_history更新
+            let mut added = bonus_lots(&state.lots, &lot_bonuses);
+            for (lot, bonus) in added.iter_mut().zip(&lot_bonuses) {
+                lot.stock_lot_id = match lots.iter().find(|record| {
+                    record.stock_action_id == action.stock_action_id
+                        && record.source_lot_id == Some(bonus.source_lot_id)
+                }) {
+                    Some(record) => record.stock_lot_id,
+                    None => {
+                        repo.insert_bonus_lot(stock.stock_id, action.stock_action_id, bonus)? as i32
+                    }
+                };
+            }
+            state.lots.extend(added);
+        } else {
+            let stock_lot_id =
+                *lot_ids
+                    .get(&action.stock_action_id)
+                    .ok_or(AppError::ActionReplay {
+                        index: index + 1,
+                        error: Box::new(AppError::NotFound {
+                            entity: Entity::Lot,
+                            id: None,
+                        }),
+                    })?;
+            state.lots.push(Lot {
+                stock_lot_id,
+                buy_price: price,
+                remaining_position: position,
+                buy_date: Some(at.date()),
+                dividend_per_share: Decimal::ZERO,
+            });
+        }
+    }
+    Ok((kept, replayed))
+}
+
+/// 重新计算历史费用并重新累计总费用，不传stock_id时重算所有股票，返回修正的记录数
+/// repair_only为true时只修复买入误收的印花税，其余费用保留原记录，
+/// 否则按每次操作时间生效的费率重新计算(操作时间为空时按记录创建时间)
+#[tauri::command]
+pub fn handle_recalculate_fees(
+    db: State<'_, DatabaseState>,
+    stock_id: Option<i32>,
+    repair_only: Option<bool>,
+) -> Result<usize, AppError> {
+    let at = now();
+    let repair_only = repair_only.unwrap_or(false);
+    db.transaction(|conn| {
+        let recalculated = recalculate_fees(conn, stock_id, repair_only)?;
+        if recalculated > 0 {
+            record_event(
+                conn,
+                &StockEvent::RecalculateFees {
+                    stock_id,
+                    repair_only,
+                },
+                at,
+            )?;
+        }
+        Ok(recalculated)
+    })
+}
+
+// 重新计算历史费用，返回修正的记录数
+pub fn recalculate_fees(
+    repo: &impl Repository,
+    stock_id: Option<i32>,
+    repair_only: bool,
+) -> Result<usize, AppError> {
+    let stocks = match stock_id {
+        Some(stock_id) => vec![repo
+            .get_stock_by_id(stock_id)?
+            .ok_or(AppError::not_found(Entity::Stock, stock_id))?],
+        None => repo.get_all_stocks()?,
+    };
+    let mut recalculated = 0;
+    for stock in stocks {
+        let actions = repo.get_actions_by_stock_id(stock.stock_id)?;
+        let mut fees = Vec::with_capacity(actions.len());
+        for action in &actions {
+            let action_type = ActionType::from(action.action);
+            if repair_only {
+                // 买入不收印花税
+                fees.push(TradeFee {
+                    tax_fee: if action_type.is_sell() {
+                        action.transaction_tax_fee
+                    } else {
+                        Decimal::ZERO
+                    },
+                    ..action.fee()
+                });
+                continue;
+            }
+            let at = action_time(&action.action_time, &action.created_at).unwrap_or_else(now);
+            let fee_rate = repo.resolve_fee_rate(&stock, at)?;
+            // 红利税与费率无关，保留原记录
+            fees.push(TradeFee {
+                dividend_tax_fee: action.transaction_dividend_tax_fee,
+                ..TradeEngine::new(fee_rate).calculate_fee(
+                    action_type,
+                    action.transaction_price,
+                    action.transaction_position,
+                )
+            });
+        }
+        recalculated += repo.rewrite_fees(&actions, &fees)?;
+    }
+    Ok(recalculated)
+}
@@ -1,11 +1,60 @@
-use crate::database::stock_action::StockActionRecord;
+use crate::calc::time::now;
+use crate::database::db_connect::DatabaseState;
+use crate::database::repository::Repository;
+use crate::error::{AppError, Entity};
+use crate::handler::event::{record_event, StockEvent};
+use crate::handler::stock_action::edit_action;
+use tauri::State;
+
+/// 保存操作时间及备注，操作时间有修改时按新时间生效的费率重新计算该操作及之后的操作
+/// action_time为空时只保存备注
 #[tauri::command]
 pub fn handle_save_action_info(
+    db: State<'_, DatabaseState>,
     stock_action_id: i32,
     action_time: String,
     action_info: String,
-) -> Result<(), String> {
-    StockActionRecord::save_stock_action_info(stock_action_id, action_time, action_info)
-        .map_err(|e| e.to_string())?;
+) -> Result<(), AppError> {
+    let at = now();
+    db.transaction(|conn| {
+        save_action_info(conn, stock_action_id, &action_time, &action_info)?;
+        record_event(
+            conn,
+            &StockEvent::SaveActionInfo {
+                stock_action_id,
+                action_time,
+                action_info,
+            },
+            at,
+        )
+    })
+}
+
+// 时间有修改时走修改操作的重放，备注直接保存，时间为空时保留原时间
+pub fn save_action_info(
+    repo: &impl Repository,
+    stock_action_id: i32,
+    action_time: &str,
+    action_info: &str,
+) -> Result<(), AppError> {
+    let action = repo
+        .get_action_by_id(stock_action_id)?
+        .ok_or(AppError::not_found(Entity::Action, stock_action_id))?;
+    let action_time = if action_time.is_empty() {
+        action.action_time.clone()
+    } else {
+        action_time.to_string()
+    };
+    if action.action_time != action_time {
+        edit_action(
+            repo,
+            stock_action_id,
+            None,
+            None,
+            Some(action_time.clone()),
+            None,
+        )?;
+    }
+    repo.save_stock_action_info(stock_action_id, action_time, action_info.to_string())?;
     Ok(())
 }
@@ -1,31 +1,284 @@
+use crate::calc::time::{format_time, now, parse_time};
+use crate::calc::trade_engine::TradeFeeRate;
+use crate::calc::validation::validate_fee_rates;
+use crate::constant::fee_rate::FeeRates;
+use crate::constant::stock_type::StockType;
+use crate::database::db_connect::DatabaseState;
+use crate::database::fee_rate_history::{FeeRateHistoryRecord, RateOwner};
+use crate::database::repository::{FeeRepo, Repository, StockRepo};
 use crate::database::stock_fee::StockFeeRate;
+use crate::error::{AppError, Entity};
+use crate::handler::event::{record_event, StockEvent};
+use crate::handler::stock_action::recalculate_fees;
+use chrono::NaiveDateTime;
+use rust_decimal::Decimal;
+use tauri::State;
 
-/// 获取股票操作记录
+/// 获取默认费率
 #[tauri::command]
-pub fn handle_stock_fee() -> Result<StockFeeRate, String> {
-    let stock_fee = StockFeeRate::get_fee().map_err(|e| e.to_string())?;
-    Ok(stock_fee)
+pub fn handle_stock_fee(db: State<'_, DatabaseState>) -> Result<StockFeeRate, AppError> {
+    db.transaction(|conn| conn.get_fee().map_err(AppError::from))
 }
 
+/// 获取所有费率方案
 #[tauri::command]
-pub fn handle_stock_fee_update(
-    commission_fee_rate: f64,
-    tax_fee_rate: f64,
-    regulatory_fee_rate: f64,
-    brokerage_fee_rate: f64,
-    transfer_fee_rate: f64,
-) -> Result<(), String> {
+pub fn handle_get_fee_list(db: State<'_, DatabaseState>) -> Result<Vec<StockFeeRate>, AppError> {
+    db.transaction(|conn| conn.get_fee_list().map_err(AppError::from))
+}
+
+/// 新建费率方案，返回新方案ID
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn handle_stock_fee_create(
+    db: State<'_, DatabaseState>,
+    stock_fee_name: String,
+    commission_fee_rate: Decimal,
+    tax_fee_rate: Option<Decimal>,
+    regulatory_fee_rate: Option<Decimal>,
+    brokerage_fee_rate: Option<Decimal>,
+    transfer_fee_rate: Option<Decimal>, // 以上四项不传时按费率表收取
+    min_commission: Decimal,
+) -> Result<i32, AppError> {
     println!("handle_stock_fee_create");
-    StockFeeRate::update(
-        commission_fee_rate,
+    let stock_fee_name = stock_fee_name.trim();
+    if stock_fee_name.is_empty() {
+        return Err(AppError::EmptyName);
+    }
+    let market_rates = [
         tax_fee_rate,
         regulatory_fee_rate,
         brokerage_fee_rate,
         transfer_fee_rate,
+    ];
+    validate_fee_rates(commission_fee_rate, market_rates, min_commission)?;
+    let stock_fee_id = db.transaction(|conn| {
+        conn.insert_fee(
+            stock_fee_name,
+            commission_fee_rate,
+            market_rates,
+            min_commission,
+        )
+        .map_err(AppError::from)
+    })?;
+    Ok(stock_fee_id as i32)
+}
+
+/// 重命名费率方案
+#[tauri::command]
+pub fn handle_stock_fee_rename(
+    db: State<'_, DatabaseState>,
+    stock_fee_id: i32,
+    stock_fee_name: String,
+) -> Result<(), AppError> {
+    let stock_fee_name = stock_fee_name.trim();
+    if stock_fee_name.is_empty() {
+        return Err(AppError::EmptyName);
+    }
+    let renamed = db.transaction(|conn| {
+        conn.rename_fee(stock_fee_id, stock_fee_name)
+            .map_err(AppError::from)
+    })?;
+    match renamed {
+        0 => Err(AppError::not_found(Entity::Fee, stock_fee_id)),
+        _ => Ok(()),
+    }
+}
+
+/// 修改费率方案，不传stock_fee_id时修改默认费率
+/// 使用该方案的股票按每次操作时生效的费率重新计算费用，生效时间早于已有操作时这些操作按新费率
+/// 撤销时费率历史及重新计算的费用随之撤销
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn handle_stock_fee_update(
+    db: State<'_, DatabaseState>,
+    stock_fee_id: Option<i32>,
+    commission_fee_rate: Decimal,
+    tax_fee_rate: Option<Decimal>,
+    regulatory_fee_rate: Option<Decimal>,
+    brokerage_fee_rate: Option<Decimal>,
+    transfer_fee_rate: Option<Decimal>, // 以上四项不传时按费率表收取
+    min_commission: Option<Decimal>,    // 最低佣金，不传时保持不变
+    valid_from: Option<String>,         // 生效时间，不传时为当前时间，之前的操作仍按原费率
+) -> Result<(), AppError> {
+    let at = now();
+    let valid_from = match valid_from {
+        Some(valid_from) => parse_time(&valid_from)
+            .ok_or_else(|| AppError::invalid_time("valid_from", &valid_from))?,
+        None => at,
+    };
+    db.transaction(|conn| {
+        let stock_fee = get_fee_or_default(conn, stock_fee_id)?;
+        let min_commission = min_commission.unwrap_or(stock_fee.min_commission);
+        let market_rates = [
+            tax_fee_rate,
+            regulatory_fee_rate,
+            brokerage_fee_rate,
+            transfer_fee_rate,
+        ];
+        validate_fee_rates(commission_fee_rate, market_rates, min_commission)?;
+        update_stock_fee(
+            conn,
+            stock_fee.stock_fee_id,
+            commission_fee_rate,
+            market_rates,
+            min_commission,
+            valid_from,
+        )?;
+        record_event(
+            conn,
+            &StockEvent::UpdateStockFee {
+                stock_fee_id: stock_fee.stock_fee_id,
+                commission_fee_rate,
+                market_rates,
+                min_commission,
+                valid_from: format_time(valid_from),
+            },
+            at,
+        )
+    })
+}
+
+// 修改费率方案并记录费率历史，使用该方案的股票重新计算费用
+pub fn update_stock_fee(
+    repo: &impl Repository,
+    stock_fee_id: i32,
+    commission_fee_rate: Decimal,
+    market_rates: [Option<Decimal>; 4],
+    min_commission: Decimal,
+    valid_from: NaiveDateTime,
+) -> Result<(), AppError> {
+    repo.record_rate_change(
+        RateOwner::Fee(stock_fee_id),
+        commission_fee_rate,
+        market_rates,
+        min_commission,
+        valid_from,
+    )?;
+    repo.update_fee(
+        stock_fee_id,
+        commission_fee_rate,
+        market_rates,
+        min_commission,
     )
     .map_err(|err| {
         eprintln!("Error updating stock fee: {}", err);
-        format!("Failed to update stock fee: {}", err)
+        AppError::from(err)
     })?;
+    for stock in repo.get_all_stocks()? {
+        if stock.stock_fee_id == Some(stock_fee_id) {
+            recalculate_fees(repo, Some(stock.stock_id), false)?;
+        }
+    }
     Ok(())
 }
+
+/// 查询费率历史：传stock_fee_id查询费率方案，传stock_id查询股票自定义费率
+#[tauri::command]
+pub fn handle_get_fee_rate_history(
+    db: State<'_, DatabaseState>,
+    stock_fee_id: Option<i32>,
+    stock_id: Option<i32>,
+) -> Result<Vec<FeeRateHistoryRecord>, AppError> {
+    let owner = match (stock_fee_id, stock_id) {
+        (Some(stock_fee_id), None) => RateOwner::Fee(stock_fee_id),
+        (None, Some(stock_id)) => RateOwner::Stock(stock_id),
+        _ => return Err(AppError::RateOwnerRequired),
+    };
+    db.transaction(|conn| conn.get_rate_history(owner).map_err(AppError::from))
+}
+
+/// 设为默认费率
+#[tauri::command]
+pub fn handle_stock_fee_set_default(
+    db: State<'_, DatabaseState>,
+    stock_fee_id: i32,
+) -> Result<(), AppError> {
+    let updated =
+        db.transaction(|conn| conn.set_default_fee(stock_fee_id).map_err(AppError::from))?;
+    match updated {
+        0 => Err(AppError::not_found(Entity::Fee, stock_fee_id)),
+        _ => Ok(()),
+    }
+}
+
+/// 删除费率方案，默认费率及仍有股票使用的费率方案不能删除
+#[tauri::command]
+pub fn handle_stock_fee_delete(
+    db: State<'_, DatabaseState>,
+    stock_fee_id: i32,
+) -> Result<(), AppError> {
+    db.transaction(|conn| {
+        let stock_fee = conn
+            .get_fee_by_id(stock_fee_id)?
+            .ok_or(AppError::not_found(Entity::Fee, stock_fee_id))?;
+        if stock_fee.is_default {
+            return Err(AppError::DefaultFeeUndeletable);
+        }
+        let stock_count = conn
+            .get_all_stocks()?
+            .iter()
+            .filter(|stock| stock.stock_fee_id == Some(stock_fee_id))
+            .count();
+        if stock_count > 0 {
+            return Err(AppError::FeeInUse {
+                stock_fee_id,
+                stock_count,
+            });
+        }
+        conn.delete_fee(stock_fee_id)?;
+        Ok(())
+    })
+}
+
+/// 开仓预填费率：费率表中该股票类型在交易日适用的费率，叠加费率方案的设置
+/// trade_date 格式 YYYY-MM-DD(可带时间)，不传时取当前时间；stock_fee_id 不传时使用默认费率
+#[tauri::command]
+pub fn handle_get_open_fee_rate(
+    db: State<'_, DatabaseState>,
+    stock_type: i32,
+    stock_fee_id: Option<i32>,
+    trade_date: Option<String>,
+) -> Result<TradeFeeRate, AppError> {
+    let trade_time = match trade_date {
+        Some(trade_date) => parse_time(&trade_date)
+            .ok_or_else(|| AppError::invalid_time("trade_date", &trade_date))?,
+        None => now(),
+    };
+    db.transaction(|conn| {
+        let stock_fee = get_fee_or_default(conn, stock_fee_id)?;
+        open_fee_rate(conn, &stock_fee, stock_type, trade_time)
+    })
+}
+
+// 获取指定费率方案，未指定时取默认费率
+pub fn get_fee_or_default(
+    repo: &impl Repository,
+    stock_fee_id: Option<i32>,
+) -> Result<StockFeeRate, AppError> {
+    match stock_fee_id {
+        Some(stock_fee_id) => repo
+            .get_fee_by_id(stock_fee_id)?
+            .ok_or(AppError::not_found(Entity::Fee, stock_fee_id)),
+        None => repo.get_fee().map_err(AppError::from),
+    }
+}
+
+// 交易时生效的费率方案，叠加在交易日适用的费率表上
+pub fn open_fee_rate(
+    repo: &impl Repository,
+    stock_fee: &StockFeeRate,
+    stock_type: i32,
+    trade_time: NaiveDateTime,
+) -> Result<TradeFeeRate, AppError> {
+    let trade_date = trade_time.date();
+    let market = FeeRates::for_stock_type(StockType::from(stock_type), trade_date).ok_or(
+        AppError::FeeRateTableNotFound {
+            trade_date: trade_date.to_string(),
+        },
+    )?;
+    let history = repo.get_rate_at(RateOwner::Fee(stock_fee.stock_fee_id), trade_time)?;
+    Ok(match history {
+        Some(record) => record.fee_rate(&market),
+        None => stock_fee.fee_rate(&market),
+    })
+}
@@ -0,0 +1,23 @@
+use crate::database::db_connect::DatabaseState;
+use crate::database::repository::ActionRepo;
+use crate::database::stock_lot::{StockLotRecord, StockLotSaleRecord};
+use crate::error::AppError;
+use tauri::State;
+
+/// 获取股票的买入批次
+#[tauri::command]
+pub fn handle_get_lot_list(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+) -> Result<Vec<StockLotRecord>, AppError> {
+    db.transaction(|conn| conn.get_lots_by_stock_id(stock_id).map_err(AppError::from))
+}
+
+/// 获取股票的批次卖出记录(每批已实现盈亏)
+#[tauri::command]
+pub fn handle_get_lot_sale_list(
+    db: State<'_, DatabaseState>,
+    stock_id: i32,
+) -> Result<Vec<StockLotSaleRecord>, AppError> {
+    db.transaction(|conn| conn.get_sales_by_stock_id(stock_id).map_err(AppError::from))
+}
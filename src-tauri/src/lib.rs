@@ -1,31 +1,66 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+mod calc;
 mod constant;
 mod database;
+mod error;
 mod handler;
 //
+use crate::database::db_connect::DatabaseState;
+use crate::database::db_path::{prepare_db_path, resolve_db_path};
 use crate::handler::background::check_background_image;
+use crate::handler::calendar::{
+    handle_get_next_trading_day, handle_get_previous_trading_day, handle_get_trading_days_between,
+    handle_get_trading_holidays, handle_import_trading_calendar, handle_is_trading_day,
+};
+use crate::handler::event::{handle_get_event_list, handle_redo, handle_undo};
+use crate::handler::setting::{handle_get_db_path, handle_set_db_path};
 use crate::handler::stock::{
-    handle_delete_stock, handle_get_all_stocks, handle_get_stock_info, handle_update_stock_sort,
+    handle_delete_stock, handle_get_all_stocks, handle_get_stock_info,
+    handle_update_stock_fee_rate, handle_update_stock_sort, handle_update_stock_st,
 };
 use crate::handler::stock_action::{
-    handle_add_position, handle_back_position, handle_close_position, handle_get_action_list,
-    handle_open_position, handle_reduce_position,
+    handle_add_position, handle_back_position, handle_bonus_shares, handle_cash_dividend,
+    handle_close_position, handle_edit_action, handle_get_action_list, handle_open_position,
+    handle_recalculate_fees, handle_reduce_position, handle_rights_issue, handle_split_shares,
 };
 use crate::handler::stock_action_info::handle_save_action_info;
-use crate::handler::stock_fee::{handle_stock_fee, handle_stock_fee_update};
+use crate::handler::stock_fee::{
+    handle_get_fee_list, handle_get_fee_rate_history, handle_get_open_fee_rate, handle_stock_fee,
+    handle_stock_fee_create, handle_stock_fee_delete, handle_stock_fee_rename,
+    handle_stock_fee_set_default, handle_stock_fee_update,
+};
+use crate::handler::stock_lot::{handle_get_lot_list, handle_get_lot_sale_list};
+use tauri::Manager;
 //
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            // 初始化数据库
+            let (db_path, _) =
+                resolve_db_path(&app.path().app_data_dir()?, &app.path().app_config_dir()?);
+            prepare_db_path(&db_path)?;
+            app.manage(DatabaseState::new(&db_path)?);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             handle_stock_fee,
             handle_stock_fee_update,
+            handle_get_fee_list,
+            handle_stock_fee_create,
+            handle_stock_fee_rename,
+            handle_stock_fee_set_default,
+            handle_stock_fee_delete,
+            handle_get_open_fee_rate,
+            handle_get_fee_rate_history,
             //
             handle_get_all_stocks,
             handle_get_stock_info,
             handle_delete_stock,
             handle_update_stock_sort,
+            handle_update_stock_fee_rate,
+            handle_update_stock_st,
             //
             handle_get_action_list,
             handle_open_position,
@@ -33,10 +68,34 @@ pub fn run() {
             handle_back_position,
             handle_reduce_position,
             handle_close_position,
+            handle_cash_dividend,
+            handle_bonus_shares,
+            handle_rights_issue,
+            handle_split_shares,
+            handle_recalculate_fees,
+            handle_edit_action,
             handle_delete_stock,
             //
+            handle_get_lot_list,
+            handle_get_lot_sale_list,
+            //
             handle_save_action_info,
+            //
+            handle_get_event_list,
+            handle_undo,
+            handle_redo,
+            //
+            handle_import_trading_calendar,
+            handle_get_trading_holidays,
+            handle_get_trading_days_between,
+            handle_is_trading_day,
+            handle_get_next_trading_day,
+            handle_get_previous_trading_day,
+            //
             check_background_image,
+            //
+            handle_get_db_path,
+            handle_set_db_path,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
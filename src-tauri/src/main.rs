@@ -1,13 +1,7 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod constant;
-mod database;
-use crate::database::db_connect::get_db_state;
-
 fn main() {
-    // 初始化数据库
-    let _db_conn = get_db_state();
-    // 启动Tauri应用
+    // 启动Tauri应用(数据库在setup中初始化)
     tauri_app_lib::run()
 }
@@ -28,33 +28,16 @@ function App() {
 	// Toast Hook
 	const { toasts, removeToast, showError, showSuccess } = useToast();
 
-	// 从localStorage获取上次保存的佣金率，如果没有则使用默认值
-	const [defaultBrokerageFeeRate, setDefaultBrokerageFeeRate] = useState(0.0000487);
-	const getFeeRate = (key) => {
-		const saved = localStorage.getItem(key);
-		if (key === 'commissionFeeRate') {
-			return saved ? parseFloat(saved) : 0.00025;
-		} else if (key === 'taxFeeRate') {
-			return saved ? parseFloat(saved) : 0.001;
-		} else if (key === 'regulatoryFeeRate') {
-			return saved ? parseFloat(saved) : 0.00002;
-		} else if (key === 'brokerageFeeRate') {
-			return saved ? parseFloat(saved) : defaultBrokerageFeeRate;
-		} else if (key === 'transferFeeRate') {
-			return saved ? parseFloat(saved) : 0.00001;
-		}
-		return 0.0;
-	};
-
-	// 默认费用数据
+	// 默认费用数据，交易所费率为空时按费率表收取
 	const [defaultFeeData, setDefaultFeeData] = useState({
-		stockType: 1,
 		commissionFeeRate: 0,
-		taxFeeRate: 0,
-		regulatoryFeeRate: 0,
-		brokerageFeeRate: 0,
-		transferFeeRate: 0,
+		taxFeeRate: null,
+		regulatoryFeeRate: null,
+		brokerageFeeRate: null,
+		transferFeeRate: null,
 	});
+	// 打开设置时的默认费率，未修改的项按原值保存
+	const [savedDefaultFee, setSavedDefaultFee] = useState({});
 	// 表单数据状态
 	const [formData, setFormData] = useState({
 		stockName: '',
@@ -62,12 +45,11 @@ function App() {
 		currentPrice: '',
 		transactionPrice: '',
 		transactionPosition: 100,
-		commissionFeeRate: getFeeRate('commissionFeeRate'),
-		taxFeeRate: getFeeRate('taxFeeRate'),
-		regulatoryFeeRate: getFeeRate('regulatoryFeeRate'),
-		brokerageFeeRate: getFeeRate('brokerageFeeRate'),
-		transferFeeRate: getFeeRate('transferFeeRate'),
+		stockFeeId: null,
 	});
+	// 费率方案列表及建仓时适用的费率(只读)
+	const [feeList, setFeeList] = useState([]);
+	const [openFeeRate, setOpenFeeRate] = useState(null);
 	// 颜色列表
 	const colorList = [
 		{
@@ -226,29 +208,36 @@ function App() {
 		return (rate * 100).toFixed(4) + '%';
 	};
 	/*************设置默认费用**************/
-	// 恢复默认值
+	// 恢复默认值，交易所费率清空后按费率表收取
 	const resetDefault = () => {
 		setDefaultFeeData({
-			stockType: 1,
-			transactionPosition: 100,
 			commissionFeeRate: 0.00025,
-			taxFeeRate: 0.001,
-			regulatoryFeeRate: 0.00002,
-			brokerageFeeRate: 0.0000487,
-			transferFeeRate: 0.00001,
+			taxFeeRate: null,
+			regulatoryFeeRate: null,
+			brokerageFeeRate: null,
+			transferFeeRate: null,
 		});
 	};
 
 	const getDefaultFeeRate = async () => {
 		const result = await invoke('handle_stock_fee');
-		setDefaultFeeData({
-			stockType: 3,
+		const feeData = {
 			commissionFeeRate: result.commission_fee_rate,
 			taxFeeRate: result.tax_fee_rate,
 			regulatoryFeeRate: result.regulatory_fee_rate,
 			brokerageFeeRate: result.brokerage_fee_rate,
 			transferFeeRate: result.transfer_fee_rate,
-		});
+		};
+		setDefaultFeeData(feeData);
+		setSavedDefaultFee(feeData);
+	};
+	// 交易所费率：未修改时保持原值，清空时为null(按费率表收取)
+	const marketFeeRate = (name) => {
+		const value = defaultFeeData[name];
+		if (value === savedDefaultFee[name]) {
+			return value;
+		}
+		return value === '' || value === null ? null : parseFloat(value);
 	};
 	const handleDefaultInputChange = (e) => {
 		const { name, value } = e.target;
@@ -265,19 +254,14 @@ function App() {
 	const saveDefaultFee = async () => {
 		const result = await invoke('handle_stock_fee_update', {
 			commissionFeeRate: parseFloat(defaultFeeData.commissionFeeRate),
-			taxFeeRate: parseFloat(defaultFeeData.taxFeeRate),
-			regulatoryFeeRate: parseFloat(defaultFeeData.regulatoryFeeRate),
-			brokerageFeeRate: parseFloat(defaultFeeData.brokerageFeeRate),
-			transferFeeRate: parseFloat(defaultFeeData.transferFeeRate),
+			taxFeeRate: marketFeeRate('taxFeeRate'),
+			regulatoryFeeRate: marketFeeRate('regulatoryFeeRate'),
+			brokerageFeeRate: marketFeeRate('brokerageFeeRate'),
+			transferFeeRate: marketFeeRate('transferFeeRate'),
 		});
 		if (!result) {
 			showSuccess('默认费率已保存');
-			setDefaultBrokerageFeeRate(defaultFeeData.brokerageFeeRate);
-			localStorage.setItem('commissionFeeRate', defaultFeeData.commissionFeeRate);
-			localStorage.setItem('taxFeeRate', defaultFeeData.taxFeeRate);
-			localStorage.setItem('regulatoryFeeRate', defaultFeeData.regulatoryFeeRate);
-			localStorage.setItem('brokerageFeeRate', defaultFeeData.brokerageFeeRate);
-			localStorage.setItem('transferFeeRate', defaultFeeData.transferFeeRate);
+			getDefaultFeeRate();
 		} else {
 			showError('保存失败，请稍后再试');
 		}
@@ -285,37 +269,45 @@ function App() {
 	};
 
 	/*************建仓**************/
-	// 使用默认费率
-	const useDefaultFee = async () => {
-		const result = await invoke('handle_stock_fee');
-		setFormData((prev) => ({
-			...prev,
-			commissionFeeRate: result.commission_fee_rate,
-			taxFeeRate: result.tax_fee_rate,
-			regulatoryFeeRate: result.regulatory_fee_rate,
-			brokerageFeeRate: result.brokerage_fee_rate,
-			transferFeeRate: result.transfer_fee_rate,
-		}));
-	};
-	// 建仓对话框
-	const openDialog = () => {
+	// 建仓对话框，默认选中默认费率方案
+	const openDialog = async () => {
+		try {
+			const result = await invoke('handle_get_fee_list');
+			setFeeList(result);
+			const defaultFee = result.find((fee) => fee.is_default);
+			setFormData((prev) => ({ ...prev, stockFeeId: defaultFee ? defaultFee.stock_fee_id : null }));
+		} catch (error) {
+			console.error('Error getting fee list:', error);
+		}
 		setShowDialog(true);
 	};
 	const closeDialog = () => {
 		setShowDialog(false);
+		setOpenFeeRate(null);
 		setFormData({
 			stockName: '',
 			stockType: 1,
 			currentPrice: '',
 			transactionPrice: '',
 			transactionPosition: '',
-			commissionFeeRate: getFeeRate('commissionFeeRate'),
-			taxFeeRate: getFeeRate('taxFeeRate'),
-			regulatoryFeeRate: getFeeRate('regulatoryFeeRate'),
-			brokerageFeeRate: getFeeRate('brokerageFeeRate'),
-			transferFeeRate: getFeeRate('transferFeeRate'),
+			stockFeeId: null,
 		});
 	};
+	// 按费率方案、股票类型和当天适用的费率表计算建仓费率，仅供查看
+	useEffect(() => {
+		if (!showDialog) {
+			return;
+		}
+		invoke('handle_get_open_fee_rate', {
+			stockType: formData.stockType,
+			stockFeeId: formData.stockFeeId,
+		})
+			.then(setOpenFeeRate)
+			.catch((error) => {
+				console.error('Error getting open fee rate:', error);
+				setOpenFeeRate(null);
+			});
+	}, [showDialog, formData.stockType, formData.stockFeeId]);
 	const handleInputChange = (e) => {
 		const { name, value } = e.target;
 		// 如果交易价格小于当前价格,则提示
@@ -332,34 +324,10 @@ function App() {
 			showError('交易数量不能大于1000000');
 			return;
 		}
-		setFormData((prev) => {
-			const newFormData = {
-				...prev,
-				[name]: value,
-			};
-
-			// 如果修改的是佣金费率，保存到localStorage
-			if (name === 'commissionFeeRate') {
-				localStorage.setItem('commissionFeeRate', value);
-			}
-			if (name === 'taxFeeRate') {
-				// 印花税
-				localStorage.setItem('taxFeeRate', value);
-			}
-			if (name === 'regulatoryFeeRate') {
-				// 证管费
-				localStorage.setItem('regulatoryFeeRate', value);
-			}
-			if (name === 'brokerageFeeRate') {
-				// 经手费
-				localStorage.setItem('brokerageFeeRate', value);
-			}
-			if (name === 'transferFeeRate') {
-				localStorage.setItem('transferFeeRate', value);
-			}
-
-			return newFormData;
-		});
+		setFormData((prev) => ({
+			...prev,
+			[name]: name === 'stockFeeId' ? parseInt(value) : value,
+		}));
 	};
 	// 确认建仓
 	const handleConfirm = async () => {
@@ -379,11 +347,7 @@ function App() {
 				currentPrice: parseFloat(formData.currentPrice),
 				transactionPrice: parseFloat(formData.transactionPrice),
 				transactionPosition: parseInt(formData.transactionPosition),
-				commissionFeeRate: parseFloat(formData.commissionFeeRate),
-				taxFeeRate: parseFloat(formData.taxFeeRate),
-				regulatoryFeeRate: parseFloat(formData.regulatoryFeeRate),
-				brokerageFeeRate: parseFloat(formData.brokerageFeeRate),
-				transferFeeRate: parseFloat(formData.transferFeeRate),
+				stockFeeId: formData.stockFeeId,
 			});
 			getStockList();
 			closeDialog();
@@ -744,11 +708,8 @@ function App() {
 										onClick={() => {
 											setFormData({
 												...formData,
-												brokerageFeeRate: 0.0000487,
 												stockType: 1,
 											});
-											localStorage.setItem('brokerageFeeRate', 0.0000487);
-											setDefaultBrokerageFeeRate(0.0000487);
 										}}
 									>
 										上海股(60开头)
@@ -760,11 +721,8 @@ function App() {
 										onClick={() => {
 											setFormData({
 												...formData,
-												brokerageFeeRate: 0.0000341,
 												stockType: 2,
 											});
-											localStorage.setItem('brokerageFeeRate', 0.0000341);
-											setDefaultBrokerageFeeRate(0.0000341);
 										}}
 									>
 										深圳(00或30开头)
@@ -809,70 +767,30 @@ function App() {
 								/>
 							</div>
 							<div className="form-group">
-								<label>佣金比例:</label>
-								<input
-									type="number"
-									name="commissionFeeRate"
-									value={formData.commissionFeeRate}
-									onChange={handleInputChange}
-									placeholder="请输入佣金比例"
-									step={0.00001}
-									max="1"
-								/>
-							</div>
-							<div className="form-group">
-								<label>印花税率:</label>
-								<input
-									type="number"
-									name="taxFeeRate"
-									value={formData.taxFeeRate}
-									onChange={handleInputChange}
-									placeholder="请输入印花税比例"
-									step={0.001}
-									max="1"
-								/>
-							</div>
-							<div className="form-group">
-								<label>证管费率:</label>
-								<input
-									type="number"
-									name="regulatoryFeeRate"
-									value={formData.regulatoryFeeRate}
-									onChange={handleInputChange}
-									placeholder="请输入证管费比例"
-									step={0.00001}
-									max="1"
-								/>
-							</div>
-							<div className="form-group">
-								<label>经手费率:</label>
-								<input
-									type="number"
-									name="brokerageFeeRate"
-									value={formData.brokerageFeeRate}
-									onChange={handleInputChange}
-									placeholder="请输入经手费比例"
-									step={0.0000001}
-									max="1"
-								/>
-							</div>
-							<div className="form-group">
-								<label>过户费率:</label>
-								<input
-									type="number"
-									name="transferFeeRate"
-									value={formData.transferFeeRate}
-									onChange={handleInputChange}
-									placeholder="请输入过户费比例"
-									step={0.00001}
-									max="1"
-								/>
+								<label>费率方案:</label>
+								<select name="stockFeeId" value={formData.stockFeeId ?? ''} onChange={handleInputChange}>
+									{feeList.map((fee) => (
+										<option key={fee.stock_fee_id} value={fee.stock_fee_id}>
+											{fee.stock_fee_name}
+											{fee.is_default ? '(默认)' : ''}
+										</option>
+									))}
+								</select>
 							</div>
+							{openFeeRate && (
+								<div className="form-group">
+									<label>适用费率:</label>
+									<span>
+										佣金{formatCommissionFee(openFeeRate.commission_fee_rate)}(最低
+										{openFeeRate.min_commission}元) 印花税{formatCommissionFee(openFeeRate.tax_fee_rate)}{' '}
+										证管费{formatCommissionFee(openFeeRate.regulatory_fee_rate)} 经手费
+										{formatCommissionFee(openFeeRate.brokerage_fee_rate)} 过户费
+										{formatCommissionFee(openFeeRate.transfer_fee_rate)}
+									</span>
+								</div>
+							)}
 						</div>
 						<div className="dialog-actions">
-							<button className="btn-cancel" onClick={useDefaultFee}>
-								默认费率
-							</button>
 							<button
 								className="btn-confirm"
 								disabled={
@@ -944,58 +862,6 @@ function App() {
 							<h3>设置默认费率</h3>
 						</div>
 						<div className="dialog-content">
-							<div className="form-group">
-								<div className="stock-type-buttons">
-									<label>股票类型:</label>
-									<button
-										className={
-											defaultFeeData.stockType === 1
-												? 'stock-type-button-active'
-												: 'stock-type-button'
-										}
-										onClick={() => {
-											setDefaultFeeData({
-												...defaultFeeData,
-												brokerageFeeRate: 0.0000487,
-												stockType: 1,
-											});
-										}}
-									>
-										上海股
-									</button>
-									<button
-										className={
-											defaultFeeData.stockType === 2
-												? 'stock-type-button-active'
-												: 'stock-type-button'
-										}
-										onClick={() => {
-											setDefaultFeeData({
-												...defaultFeeData,
-												brokerageFeeRate: 0.0000341,
-												stockType: 2,
-											});
-										}}
-									>
-										深圳
-									</button>
-									<button
-										className={
-											defaultFeeData.stockType === 3
-												? 'stock-type-button-active'
-												: 'stock-type-button'
-										}
-										onClick={() => {
-											setDefaultFeeData({
-												...defaultFeeData,
-												stockType: 3,
-											});
-										}}
-									>
-										自定义
-									</button>
-								</div>
-							</div>
 							<div className="form-group">
 								<label>佣金比例:</label>
 								<input
@@ -1013,9 +879,9 @@ function App() {
 								<input
 									type="number"
 									name="taxFeeRate"
-									value={defaultFeeData.taxFeeRate}
+									value={defaultFeeData.taxFeeRate ?? ''}
 									onChange={handleDefaultInputChange}
-									placeholder="请输入印花税比例"
+									placeholder="为空时按费率表收取"
 									step={0.001}
 									max="1"
 								/>
@@ -1025,9 +891,9 @@ function App() {
 								<input
 									type="number"
 									name="regulatoryFeeRate"
-									value={defaultFeeData.regulatoryFeeRate}
+									value={defaultFeeData.regulatoryFeeRate ?? ''}
 									onChange={handleDefaultInputChange}
-									placeholder="请输入证管费比例"
+									placeholder="为空时按费率表收取"
 									step={0.00001}
 									max="1"
 								/>
@@ -1037,9 +903,9 @@ function App() {
 								<input
 									type="number"
 									name="brokerageFeeRate"
-									value={defaultFeeData.brokerageFeeRate}
+									value={defaultFeeData.brokerageFeeRate ?? ''}
 									onChange={handleDefaultInputChange}
-									placeholder="请输入经手费比例"
+									placeholder="为空时按费率表收取"
 									step={0.0000001}
 									max="1"
 								/>
@@ -1049,9 +915,9 @@ function App() {
 								<input
 									type="number"
 									name="transferFeeRate"
-									value={defaultFeeData.transferFeeRate}
+									value={defaultFeeData.transferFeeRate ?? ''}
 									onChange={handleDefaultInputChange}
-									placeholder="请输入过户费比例"
+									placeholder="为空时按费率表收取"
 									step={0.00001}
 									max="1"
 								/>